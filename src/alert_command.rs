@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use log::{info, warn};
+use tokio::process::Command;
+use tokio::sync::broadcast;
+use crate::alerts::{next_event, unix_secs, AlertEvent, Delivery, DeliveryLog, Routing};
+use crate::webhook::fill_text;
+
+/// Seconds a command gets before it is killed.
+const TIMEOUT_SECS: u64 = 30;
+
+/// Runs the `command` of the rule of every alert event, one at a time so a relay sees the
+/// events in order; `commands` are by rule name.
+pub async fn run_alert_commands(commands: BTreeMap<String, Vec<String>>, mut events: broadcast::Receiver<AlertEvent>, log: Arc<Mutex<DeliveryLog>>) {
+    while let Some(event) = next_event(&mut events, "command", &Routing::default(), None).await {
+        let Some(command) = commands.get(&event.rule) else {
+            continue;
+        };
+        let delivery = run_command(command, &event, Duration::from_secs(TIMEOUT_SECS)).await;
+        match &delivery.error {
+            None => info!("Ran {} for alert {} of {}", delivery.target, event.rule, event.sensor),
+            Some(error) => warn!("failed to run {} for alert {} of {}: {}", delivery.target, event.rule, event.sensor, error),
+        }
+        if let Ok(mut log) = log.lock() {
+            log.record(delivery);
+        }
+    }
+}
+
+/// Runs `command` with the placeholders of its arguments filled in from `event` and the event in
+/// `environment`, killed after `timeout`.
+pub async fn run_command(command: &[String], event: &AlertEvent, timeout: Duration) -> Delivery {
+    let args: Vec<String> = command.iter().map(|arg| fill_text(arg, event)).collect();
+    let program = args.first().cloned().unwrap_or_default();
+    let output = Command::new(&program)
+        .args(args.iter().skip(1))
+        .envs(environment(event))
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let (status, error) = match tokio::time::timeout(timeout, output).await {
+        Ok(Ok(output)) if output.status.success() => (output.status.code(), None),
+        Ok(Ok(output)) => (output.status.code(), Some(format!("exited with {}: {}",
+            output.status, String::from_utf8_lossy(&output.stderr).trim()))),
+        Ok(Err(e)) => (None, Some(e.to_string())),
+        Err(_) => (None, Some(format!("killed after {:?}", timeout))),
+    };
+    Delivery {
+        channel: "command".to_string(),
+        target: program,
+        rule: event.rule.clone(),
+        sensor: event.sensor.clone(),
+        state: event.state,
+        sent_at: unix_secs(SystemTime::now()),
+        attempts: 1,
+        status: status.and_then(|code| u16::try_from(code).ok()),
+        error,
+    }
+}
+
+/// The event as `HEATER_ALERT_*` environment variables of the command.
+pub fn environment(event: &AlertEvent) -> Vec<(&'static str, String)> {
+    vec![
+        ("HEATER_ALERT_ID", event.id.to_string()),
+        ("HEATER_ALERT_RULE", event.rule.clone()),
+        ("HEATER_ALERT_SENSOR", event.sensor.clone()),
+        ("HEATER_ALERT_METRIC", event.metric.clone()),
+        ("HEATER_ALERT_STATE", event.state.as_str().to_string()),
+        ("HEATER_ALERT_VALUE", event.value.to_string()),
+        ("HEATER_ALERT_SINCE", event.since.to_string()),
+        ("HEATER_ALERT_TIMESTAMP", event.timestamp.to_string()),
+        ("HEATER_ALERT_PRIORITY", event.priority.as_str().to_string()),
+        ("HEATER_ALERT_MESSAGE", event.message()),
+    ]
+}
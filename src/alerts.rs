@@ -0,0 +1,647 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use chrono_tz::Tz;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+use crate::storage::{Sample, TEMPERATURE};
+
+/// Name of the rule of `[stale_alert]` in its events.
+pub const STALE_RULE: &str = "stale";
+/// Alert events a notifier may fall behind by before it misses some.
+const SUBSCRIBER_BACKLOG: usize = 64;
+/// Deliveries `DeliveryLog` keeps, older ones are dropped.
+const DELIVERY_LOG_SIZE: usize = 100;
+
+/// `[[alerts]]` entry: a metric of a sensor crossing a threshold for longer than `duration`,
+/// e.g. the temperature below 15 °C for 10 minutes.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AlertRule {
+    pub name: String,
+    /// Sensor the rule watches, every sensor when unset.
+    #[serde(default)]
+    pub sensor: Option<String>,
+    #[serde(default = "default_metric")]
+    pub metric: String,
+    /// Fires while the value is below this.
+    #[serde(default)]
+    pub below: Option<f64>,
+    /// Fires while the value is above this.
+    #[serde(default)]
+    pub above: Option<f64>,
+    /// Fires while the value is more than this below its highest within `window`, e.g. a
+    /// heater failing or a window left open.
+    #[serde(default)]
+    pub drop: Option<f64>,
+    /// Fires while the value is more than this above its lowest within `window`.
+    #[serde(default)]
+    pub rise: Option<f64>,
+    /// Fires while the trend of the values within `window`, extended by `horizon` seconds,
+    /// ends below this, e.g. 0 °C to warn of frost hours before it happens.
+    #[serde(default)]
+    pub projected_below: Option<f64>,
+    #[serde(default = "default_horizon")]
+    pub horizon: u64,
+    /// Fires once the means of this many `bucket`s in a row each fell below the one before, the
+    /// heater failing to heat, counted only within `heating_hours`.
+    #[serde(default)]
+    pub falling_buckets: Option<u32>,
+    /// Seconds of a `falling_buckets` bucket.
+    #[serde(default = "default_bucket")]
+    pub bucket: u64,
+    /// Local time range, e.g. `06:00-22:00`, in which the heater should keep it warm, always when unset.
+    #[serde(default)]
+    pub heating_hours: Option<String>,
+    /// Seconds of samples `drop`, `rise` and `projected_below` look at.
+    #[serde(default = "default_window")]
+    pub window: u64,
+    /// Seconds the threshold has to stay crossed before the alert fires, 0 fires on the first sample.
+    #[serde(default)]
+    pub duration: u64,
+    #[serde(default)]
+    pub priority: Priority,
+    /// How far the value has to get back past the threshold to clear the alert, e.g. 0.5 for
+    /// a `below = 15.0` rule firing under 15.0 and resolving at 15.5.
+    #[serde(default)]
+    pub hysteresis: f64,
+    /// Minimum seconds between two notifications of the rule for a sensor, changes within it
+    /// are held back and the latest one sent once it passes.
+    #[serde(default)]
+    pub cooldown: u64,
+    /// Seconds between repeats of an active alert until it is acknowledged or resolves, none when 0.
+    #[serde(default)]
+    pub renotify: u64,
+    /// Program and arguments, no shell, run on every event of the rule.
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+}
+
+fn default_metric() -> String {
+    TEMPERATURE.to_string()
+}
+
+fn default_window() -> u64 {
+    1800
+}
+
+fn default_horizon() -> u64 {
+    3 * 3600
+}
+
+fn default_bucket() -> u64 {
+    900
+}
+
+/// Start and end of a local time range like `22:00-07:00`, `None` when invalid.
+pub fn parse_time_range(range: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = range.split_once('-')?;
+    let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M").ok();
+    Some((parse(start)?, parse(end)?))
+}
+
+/// Whether `time` is within the range of `parse_time_range`, which may span midnight.
+fn in_time_range((start, end): (NaiveTime, NaiveTime), time: NaiveTime) -> bool {
+    match start <= end {
+        true => start <= time && time < end,
+        false => start <= time || time < end,
+    }
+}
+
+/// Time of day of `time` in `timezone`, the system one when `None`.
+fn time_of_day(timezone: Option<Tz>, time: SystemTime) -> NaiveTime {
+    match timezone {
+        Some(tz) => DateTime::<Utc>::from(time).with_timezone(&tz).time(),
+        None => DateTime::<Local>::from(time).time(),
+    }
+}
+
+impl AlertRule {
+    fn watches(&self, sensor: &str) -> bool {
+        self.sensor.as_deref().is_none_or(|watched| watched == sensor)
+    }
+
+    fn heating_hours(&self) -> Option<(NaiveTime, NaiveTime)> {
+        parse_time_range(self.heating_hours.as_deref()?)
+    }
+
+    fn uses_window(&self) -> bool {
+        self.drop.is_some() || self.rise.is_some() || self.projected_below.is_some()
+    }
+
+    /// Value in `horizon` seconds at the `slope` of the window, none without one.
+    fn projected(&self, value: f64, recent: &Recent) -> Option<f64> {
+        recent.slope.map(|slope| value + slope * self.horizon as f64)
+    }
+
+    fn is_crossed(&self, value: f64, recent: &Recent) -> bool {
+        self.below.is_some_and(|below| value < below)
+            || self.above.is_some_and(|above| value > above)
+            || self.drop.is_some_and(|drop| recent.highest - value > drop)
+            || self.rise.is_some_and(|rise| value - recent.lowest > rise)
+            || self.projected_below.zip(self.projected(value, recent)).is_some_and(|(below, projected)| projected < below)
+            || self.falling_buckets.is_some_and(|buckets| recent.falling >= buckets)
+    }
+
+    /// Back past the thresholds by `hysteresis`.
+    fn is_cleared(&self, value: f64, recent: &Recent) -> bool {
+        self.below.is_none_or(|below| value >= below + self.hysteresis)
+            && self.above.is_none_or(|above| value <= above - self.hysteresis)
+            && self.drop.is_none_or(|drop| recent.highest - value <= drop - self.hysteresis)
+            && self.rise.is_none_or(|rise| value - recent.lowest <= rise - self.hysteresis)
+            && self.projected_below.is_none_or(|below| self.projected(value, recent).is_none_or(|projected| projected >= below + self.hysteresis))
+            && self.falling_buckets.is_none_or(|buckets| recent.falling < buckets)
+    }
+}
+
+/// `[stale_alert]` section: a sensor without a stored sample for `intervals` times its
+/// sampling interval, e.g. a dead battery.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct StaleAlert {
+    #[serde(default = "default_stale_intervals")]
+    pub intervals: u32,
+    #[serde(default)]
+    pub priority: Priority,
+}
+
+fn default_stale_intervals() -> u32 {
+    5
+}
+
+/// How urgently the alerts of a rule are pushed by ntfy and Pushover, `urgent` ones get past
+/// Do-Not-Disturb.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+}
+
+/// Which alert events a notification channel sends, part of its section.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Routing {
+    /// Events of rules below this priority are not sent.
+    #[serde(default = "lowest_priority")]
+    pub min_priority: Priority,
+    /// Local time range, e.g. `22:00-07:00`, in which only events of at least
+    /// `quiet_min_priority` are sent.
+    #[serde(default)]
+    pub quiet_hours: Option<String>,
+    #[serde(default = "highest_priority")]
+    pub quiet_min_priority: Priority,
+}
+
+fn lowest_priority() -> Priority {
+    Priority::Low
+}
+
+fn highest_priority() -> Priority {
+    Priority::Urgent
+}
+
+impl Default for Routing {
+    fn default() -> Self {
+        Self { min_priority: lowest_priority(), quiet_hours: None, quiet_min_priority: highest_priority() }
+    }
+}
+
+impl Routing {
+    /// Start and end of `quiet_hours`, `None` without them or when invalid.
+    pub fn quiet_hours(&self) -> Option<(NaiveTime, NaiveTime)> {
+        parse_time_range(self.quiet_hours.as_deref()?)
+    }
+
+    /// Whether an event of `priority` is sent at local `time`.
+    pub fn allows(&self, priority: Priority, time: NaiveTime) -> bool {
+        let quiet = self.quiet_hours().is_some_and(|range| in_time_range(range, time));
+        priority >= self.min_priority && (!quiet || priority >= self.quiet_min_priority)
+    }
+}
+
+/// Next event of `events` that `routing` lets `channel` send at the current time in
+/// `timezone`, the system one when `None`; `None` once the alerts are gone.
+pub async fn next_event(events: &mut broadcast::Receiver<AlertEvent>, channel: &str, routing: &Routing, timezone: Option<Tz>) -> Option<AlertEvent> {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("{} skipped {} alert events", channel, missed);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        };
+        let time = time_of_day(timezone, SystemTime::now());
+        if routing.allows(event.priority, time) {
+            return Some(event);
+        }
+        debug!("{} not sending alert {} of {}, routed away at {}", channel, event.rule, event.sensor, time.format("%H:%M"));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Active,
+    Resolved,
+}
+
+impl AlertState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertState::Active => "active",
+            AlertState::Resolved => "resolved",
+        }
+    }
+}
+
+/// A rule firing or resolving for a sensor, sent to the subscribers of `Alerts::subscribe`.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct AlertEvent {
+    /// Of the activation, shared by its repeats and its resolution.
+    pub id: u64,
+    pub rule: String,
+    pub sensor: String,
+    pub metric: String,
+    pub state: AlertState,
+    /// Value of the sample that changed the state.
+    pub value: f64,
+    /// Unix seconds of the first sample crossing the threshold.
+    pub since: u64,
+    /// Unix seconds of the sample that changed the state.
+    pub timestamp: u64,
+    /// Of the rule.
+    pub priority: Priority,
+}
+
+impl AlertEvent {
+    /// One line for humans, e.g. `frost is active for office: temperature 14.2`.
+    pub fn message(&self) -> String {
+        format!("{} is {} for {}: {} {:.1}", self.rule, self.state.as_str(), self.sensor, self.metric, self.value)
+    }
+}
+
+/// An alert that is active, as listed by `/api/v1/alerts`.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ActiveAlert {
+    /// The event that made it active.
+    #[serde(flatten)]
+    pub event: AlertEvent,
+    /// Unix seconds of its acknowledgment, which stops the repeats of `renotify`.
+    pub acknowledged_at: Option<u64>,
+}
+
+impl ActiveAlert {
+    fn new(event: AlertEvent) -> Self {
+        Self { event, acknowledged_at: None }
+    }
+}
+
+/// Progress of a rule for a single sensor.
+#[derive(Debug, Default)]
+struct RuleState {
+    /// Time of the first sample of the current crossing.
+    crossed_since: Option<SystemTime>,
+    /// The alert, until it resolves.
+    active: Option<ActiveAlert>,
+    /// State the subscribers were last told of and when.
+    notified: Option<(AlertState, SystemTime)>,
+    /// Change held back by the rule's `cooldown`.
+    pending: Option<AlertEvent>,
+    /// Values within the `window` of a `drop` or `rise` rule, oldest first.
+    recent: VecDeque<(SystemTime, f64)>,
+    falling: Falling,
+}
+
+/// Buckets of a `falling_buckets` rule.
+#[derive(Debug, Default)]
+struct Falling {
+    /// Start, sum and number of the values of the current bucket.
+    current: Option<(SystemTime, f64, u32)>,
+    /// Mean of the last complete bucket.
+    previous: Option<f64>,
+    /// Complete buckets in a row whose mean fell.
+    count: u32,
+}
+
+impl Falling {
+    /// Adds `value` to its bucket, returns the buckets that fell in a row before it.
+    fn record(&mut self, timestamp: SystemTime, value: f64, bucket: Duration) -> u32 {
+        let secs = unix_secs(timestamp);
+        let start = timestamp - Duration::from_secs(secs % bucket.as_secs().max(1));
+        match &mut self.current {
+            Some((current, sum, count)) if *current == start => {
+                *sum += value;
+                *count += 1;
+            }
+            current => {
+                if let Some((_, sum, count)) = current.take() {
+                    let mean = sum / count as f64;
+                    self.count = if self.previous.is_some_and(|previous| mean < previous) { self.count + 1 } else { 0 };
+                    self.previous = Some(mean);
+                }
+                *current = Some((start, value, 1));
+            }
+        }
+        self.count
+    }
+}
+
+/// The values of a rule within its `window`.
+struct Recent {
+    lowest: f64,
+    highest: f64,
+    /// Per second, by least squares, once the values span half the window.
+    slope: Option<f64>,
+    /// Buckets in a row that fell within the heating hours.
+    falling: u32,
+}
+
+impl RuleState {
+    /// Keeps `value` and drops those older than `window`, describes those left.
+    fn record(&mut self, timestamp: SystemTime, value: f64, window: Duration) -> Recent {
+        self.recent.push_back((timestamp, value));
+        while self.recent.front().is_some_and(|(oldest, _)| timestamp.duration_since(*oldest).unwrap_or_default() > window) {
+            self.recent.pop_front();
+        }
+        let (lowest, highest) = self.recent.iter().fold((value, value), |(lowest, highest), (_, value)| (lowest.min(*value), highest.max(*value)));
+        let oldest = self.recent.front().map_or(timestamp, |(oldest, _)| *oldest);
+        let secs = |time: &SystemTime| time.duration_since(oldest).unwrap_or_default().as_secs_f64();
+        let span = timestamp.duration_since(oldest).unwrap_or_default();
+        let slope = (span >= window / 2 && !span.is_zero()).then(|| {
+            let count = self.recent.len() as f64;
+            let mean_t = self.recent.iter().map(|(time, _)| secs(time)).sum::<f64>() / count;
+            let mean_v = self.recent.iter().map(|(_, value)| value).sum::<f64>() / count;
+            let (covariance, variance) = self.recent.iter().fold((0.0, 0.0), |(covariance, variance), (time, value)| {
+                let dt = secs(time) - mean_t;
+                (covariance + dt * (value - mean_v), variance + dt * dt)
+            });
+            covariance / variance
+        });
+        Recent { lowest, highest, slope, falling: 0 }
+    }
+}
+
+/// Evaluates the `[[alerts]]` rules against every stored sample.
+#[derive(Debug)]
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    /// By rule index and sensor.
+    states: BTreeMap<(usize, String), RuleState>,
+    stale_alert: Option<StaleAlert>,
+    /// Of `heating_hours`, the system one when `None`.
+    timezone: Option<Tz>,
+    /// Active stale alerts by sensor.
+    stale: BTreeMap<String, ActiveAlert>,
+    /// Id of the next activation.
+    next_id: u64,
+    events: broadcast::Sender<AlertEvent>,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        Self {
+            rules,
+            states: BTreeMap::new(),
+            stale_alert: None,
+            timezone: None,
+            stale: BTreeMap::new(),
+            next_id: 1,
+            events: broadcast::channel(SUBSCRIBER_BACKLOG).0,
+        }
+    }
+
+    pub fn with_timezone(mut self, timezone: Option<Tz>) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    pub fn with_stale_alert(mut self, stale_alert: Option<StaleAlert>) -> Self {
+        self.stale_alert = stale_alert;
+        self
+    }
+
+    /// Receives every event of `evaluate` from now on, for the notifiers.
+    pub fn subscribe(&self) -> broadcast::Receiver<AlertEvent> {
+        self.events.subscribe()
+    }
+
+    /// Checks a new sample of `sensor` against the rules watching it, returns the alerts it
+    /// made active or resolved. A sample without the rule's metric leaves the rule as it is.
+    /// The subscribers get the changes at most once per `cooldown` of a rule, and an active
+    /// alert again every `renotify` until it is acknowledged.
+    pub fn evaluate(&mut self, sensor: &str, sample: &Sample) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.watches(sensor) {
+                continue;
+            }
+            let Some(value) = sample.value(&rule.metric).filter(|value| value.is_finite()) else {
+                continue;
+            };
+            let state = self.states.entry((index, sensor.to_string())).or_default();
+            let mut recent = if rule.uses_window() {
+                state.record(sample.timestamp, value, Duration::from_secs(rule.window))
+            } else {
+                Recent { lowest: value, highest: value, slope: None, falling: 0 }
+            };
+            if rule.falling_buckets.is_some() {
+                let heating = rule.heating_hours().is_none_or(|range| in_time_range(range, time_of_day(self.timezone, sample.timestamp)));
+                if heating {
+                    recent.falling = state.falling.record(sample.timestamp, value, Duration::from_secs(rule.bucket));
+                } else {
+                    state.falling = Falling::default();
+                }
+            }
+            if rule.is_crossed(value, &recent) {
+                let since = *state.crossed_since.get_or_insert(sample.timestamp);
+                let crossed_for = sample.timestamp.duration_since(since).unwrap_or_default();
+                if state.active.is_none() && crossed_for >= Duration::from_secs(rule.duration) {
+                    let event = event(self.next_id, rule, sensor, AlertState::Active, value, unix_secs(since), sample.timestamp);
+                    self.next_id += 1;
+                    state.active = Some(ActiveAlert::new(event.clone()));
+                    state.pending = Some(event.clone());
+                    events.push(event);
+                }
+            } else if state.active.is_none() || rule.is_cleared(value, &recent) {
+                state.crossed_since = None;
+                if let Some(active) = state.active.take() {
+                    let event = event(active.event.id, rule, sensor, AlertState::Resolved, value, active.event.since, sample.timestamp);
+                    state.pending = Some(event.clone());
+                    events.push(event);
+                }
+            }
+            let Some(pending) = state.pending.take() else {
+                if let (Some(active), Some((AlertState::Active, at))) = (&state.active, state.notified) {
+                    let unnotified_for = sample.timestamp.duration_since(at).unwrap_or_default();
+                    if rule.renotify > 0 && active.acknowledged_at.is_none() && unnotified_for >= Duration::from_secs(rule.renotify) {
+                        state.notified = Some((AlertState::Active, sample.timestamp));
+                        let repeat = AlertEvent { value, timestamp: unix_secs(sample.timestamp), ..active.event.clone() };
+                        let _ = self.events.send(repeat);
+                    }
+                }
+                continue;
+            };
+            match state.notified {
+                // changed back before the subscribers heard of it
+                Some((notified, _)) if notified == pending.state => {}
+                Some((_, at)) if sample.timestamp.duration_since(at).unwrap_or_default() < Duration::from_secs(rule.cooldown) => {
+                    state.pending = Some(pending);
+                }
+                _ => {
+                    state.notified = Some((pending.state, sample.timestamp));
+                    // nobody listening is not an error
+                    let _ = self.events.send(pending);
+                }
+            }
+        }
+        events
+    }
+
+    /// Checks the age of the newest sample of `sensor` at `now` against `[stale_alert]`, returns
+    /// the alert it made active or resolved. The value of the events is the age in seconds and
+    /// `since` the time of the newest sample.
+    pub fn check_stale(&mut self, sensor: &str, newest: SystemTime, sampling_interval: Duration, now: SystemTime) -> Option<AlertEvent> {
+        let stale_alert = self.stale_alert.as_ref()?;
+        let age = now.duration_since(newest).unwrap_or_default();
+        let state = if age > sampling_interval * stale_alert.intervals {
+            AlertState::Active
+        } else {
+            AlertState::Resolved
+        };
+        let id = match (state, self.stale.get(sensor)) {
+            (AlertState::Active, None) => self.next_id,
+            (AlertState::Resolved, Some(active)) => active.event.id,
+            _ => return None,
+        };
+        let event = AlertEvent {
+            id,
+            rule: STALE_RULE.to_string(),
+            sensor: sensor.to_string(),
+            metric: "age".to_string(),
+            state,
+            value: age.as_secs() as f64,
+            since: unix_secs(newest),
+            timestamp: unix_secs(now),
+            priority: stale_alert.priority,
+        };
+        if state == AlertState::Active {
+            self.next_id += 1;
+            self.stale.insert(sensor.to_string(), ActiveAlert::new(event.clone()));
+        } else {
+            self.stale.remove(sensor);
+        }
+        let _ = self.events.send(event.clone());
+        Some(event)
+    }
+
+    /// The alerts currently active, as they fired.
+    pub fn active(&self) -> impl Iterator<Item = &ActiveAlert> {
+        self.states.values().filter_map(|state| state.active.as_ref()).chain(self.stale.values())
+    }
+
+    /// Marks the active alert `id` acknowledged at `now`, unless it already is; `None` when no
+    /// active alert has the id.
+    pub fn acknowledge(&mut self, id: u64, now: SystemTime) -> Option<&ActiveAlert> {
+        let alert = self.states.values_mut()
+            .filter_map(|state| state.active.as_mut())
+            .chain(self.stale.values_mut())
+            .find(|alert| alert.event.id == id)?;
+        alert.acknowledged_at.get_or_insert(unix_secs(now));
+        Some(alert)
+    }
+}
+
+/// Outcome of sending an alert event to a notification channel.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Delivery {
+    /// `webhook`, `email`, `telegram`, `ntfy`, `pushover` or `command`.
+    pub channel: String,
+    /// Where it was sent: the URL with its password redacted, the mail recipients, the chat ids,
+    /// the Pushover device or the program run.
+    pub target: String,
+    pub rule: String,
+    pub sensor: String,
+    pub state: AlertState,
+    /// Unix seconds of the last attempt.
+    pub sent_at: u64,
+    pub attempts: u32,
+    /// HTTP status of the last attempt, when there was a response, or the exit code of a command.
+    pub status: Option<u16>,
+    /// Why the last attempt failed, `None` once delivered.
+    pub error: Option<String>,
+}
+
+/// Runs `attempt` until it succeeds or has been retried `retries` times, waiting `backoff` before
+/// the first retry and twice as long before each further one. Returns the attempts made and the
+/// result of the last one.
+pub async fn retry<T, E, F, Fut>(retries: u32, backoff: Duration, mut attempt: F) -> (u32, Result<T, E>)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut backoff = backoff;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = attempt().await;
+        if result.is_ok() || attempts > retries {
+            return (attempts, result);
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = backoff.saturating_mul(2);
+    }
+}
+
+/// The latest deliveries of every notification channel, listed by `/api/v1/alerts/deliveries`.
+#[derive(Debug, Default)]
+pub struct DeliveryLog {
+    deliveries: VecDeque<Delivery>,
+}
+
+impl DeliveryLog {
+    pub fn record(&mut self, delivery: Delivery) {
+        if self.deliveries.len() == DELIVERY_LOG_SIZE {
+            self.deliveries.pop_front();
+        }
+        self.deliveries.push_back(delivery);
+    }
+
+    /// Newest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Delivery> {
+        self.deliveries.iter().rev()
+    }
+}
+
+fn event(id: u64, rule: &AlertRule, sensor: &str, state: AlertState, value: f64, since: u64, timestamp: SystemTime) -> AlertEvent {
+    AlertEvent {
+        id,
+        rule: rule.name.clone(),
+        sensor: sensor.to_string(),
+        metric: rule.metric.clone(),
+        state,
+        value,
+        since,
+        timestamp: unix_secs(timestamp),
+        priority: rule.priority,
+    }
+}
+
+pub(crate) fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
@@ -0,0 +1,69 @@
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::app_error::AppError;
+
+/// Longest accepted annotation text in characters.
+pub const MAX_TEXT_LEN: usize = 500;
+
+/// A note on the timeline, e.g. "changed boiler filter", shown as a marker on the chart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Annotation {
+    /// Unix seconds.
+    pub timestamp: u64,
+    pub text: String,
+    /// Sensor the note is about, `None` for all of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor: Option<String>,
+}
+
+/// Annotations ordered by time, appended to a JSON-lines file when one is configured.
+#[derive(Debug, Default)]
+pub struct Annotations {
+    path: Option<String>,
+    annotations: Vec<Annotation>,
+}
+
+impl Annotations {
+    /// Reads the annotations stored at `path`, lines that don't parse are skipped.
+    pub fn open(path: Option<&str>) -> Result<Self, AppError> {
+        let mut annotations = Vec::new();
+        if let Some(path) = path {
+            match fs::read_to_string(path) {
+                Ok(contents) => {
+                    annotations = contents.lines()
+                        .filter(|line| !line.trim().is_empty())
+                        .filter_map(|line| serde_json::from_str(line).ok())
+                        .collect();
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        annotations.sort_by_key(|annotation: &Annotation| annotation.timestamp);
+        Ok(Self { path: path.map(str::to_string), annotations })
+    }
+
+    pub fn add(&mut self, annotation: Annotation) -> Result<(), AppError> {
+        if annotation.text.trim().is_empty() || annotation.text.chars().count() > MAX_TEXT_LEN {
+            return Err(AppError::BadRequest(format!("text must have 1 to {} characters", MAX_TEXT_LEN)));
+        }
+        if let Some(path) = &self.path {
+            let mut file = File::options().create(true).append(true).open(path)?;
+            file.write_all(format!("{}\n", serde_json::to_string(&annotation)?).as_bytes())?;
+        }
+        let at = self.annotations.partition_point(|other| other.timestamp <= annotation.timestamp);
+        self.annotations.insert(at, annotation);
+        Ok(())
+    }
+
+    /// Annotations of `sensor` or of all sensors between `from` and `to` (Unix seconds).
+    pub fn in_range(&self, sensor: &str, from: u64, to: u64) -> Vec<Annotation> {
+        self.annotations.iter()
+            .filter(|annotation| annotation.timestamp >= from && annotation.timestamp <= to)
+            .filter(|annotation| annotation.sensor.as_deref().is_none_or(|name| name == sensor))
+            .cloned()
+            .collect()
+    }
+}
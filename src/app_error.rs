@@ -3,7 +3,7 @@ use axum::Json;
 use axum::response::{IntoResponse, Response};
 use log::{info};
 use uuid::Uuid;
-// use crate::storage::Storage;
+use crate::storage::StorageError;
 
 #[derive(Debug, thiserror::Error)]
 pub enum AppError {
@@ -40,6 +40,15 @@ pub enum AppError {
 
     #[error("parse error: {0}")]
     ParseError(String),
+
+    #[error("not found: {0}")]
+    NotFound(String),
+
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("bad request: {0}")]
+    BadRequest(String),
 }
 
 impl IntoResponse for AppError {
@@ -61,15 +70,59 @@ impl IntoResponse for AppError {
             AppError::InternalError(_)   => (StatusCode::INTERNAL_SERVER_ERROR, 10),
             AppError::TimeError(_)       => (StatusCode::INTERNAL_SERVER_ERROR, 11),
             AppError::ParseError(_)      => (StatusCode::INTERNAL_SERVER_ERROR, 12),
+            AppError::NotFound(_)        => (StatusCode::NOT_FOUND, 13),
+            AppError::Unauthorized(_)    => (StatusCode::UNAUTHORIZED, 14),
+            AppError::BadRequest(_)      => (StatusCode::BAD_REQUEST, 15),
 
         };
 
-        let body = serde_json::json!({
+        let mut chain = Vec::new();
+        let mut source = std::error::Error::source(&self);
+        while let Some(cause) = source {
+            chain.push(cause.to_string());
+            source = cause.source();
+        }
+        let details = ErrorDetails { code, uuid, message, chain };
+        let mut response = (http_status, Json(details.body(false))).into_response();
+        response.extensions_mut().insert(details);
+        response
+    }
+}
+
+/// What `AppError::into_response` knows of an error, kept in the response extensions so the
+/// server can add the message and its causes to the body when `detailed_errors` is on.
+#[derive(Debug, Clone)]
+pub struct ErrorDetails {
+    pub code: u16,
+    pub uuid: Uuid,
+    pub message: String,
+    /// Messages of the underlying causes, outermost first.
+    pub chain: Vec<String>,
+}
+
+impl ErrorDetails {
+    /// The JSON body of the response, with `message` and `chain` only when `detailed`.
+    pub fn body(&self, detailed: bool) -> serde_json::Value {
+        let mut body = serde_json::json!({
             "status": "error",
-            "code": code,
-            "uuid": uuid.to_string(),
+            "code": self.code,
+            "uuid": self.uuid.to_string(),
         });
-        (http_status, Json(body)).into_response()
+        if detailed {
+            body["message"] = self.message.clone().into();
+            body["chain"] = self.chain.clone().into();
+        }
+        body
+    }
+}
+
+/// An empty range is the client's to fix, not a server failure.
+impl From<StorageError> for AppError {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::InvalidTimeRange => AppError::BadRequest("invalid time range".to_string()),
+            StorageError::NoDataAvailable => AppError::NotFound("no data available for the requested time range".to_string()),
+        }
     }
 }
 
@@ -0,0 +1,44 @@
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use crate::app_error::AppError;
+use crate::storage::{Sample, Storage};
+
+/// Where readings are stored and read back. The sensor tasks write through it only, so a
+/// database or remote backend needs no change to the sampling code.
+pub trait StorageBackend: Send + Sync {
+    /// Stores a reading of `series`.
+    fn append(&mut self, series: &str, sample: Sample) -> Result<(), AppError>;
+
+    /// Samples of `series` taken between `from` and `to`, oldest first.
+    fn range(&self, series: &str, from: SystemTime, to: SystemTime) -> Result<Vec<Sample>, AppError>;
+
+    /// The newest sample of `series`.
+    fn latest(&self, series: &str) -> Option<Sample>;
+}
+
+/// A backend shared by the sensor tasks.
+pub type SharedBackend = Arc<RwLock<dyn StorageBackend>>;
+
+/// The in-memory series persisted to the backlog.
+impl StorageBackend for Storage {
+    fn append(&mut self, series: &str, sample: Sample) -> Result<(), AppError> {
+        self.add_sample(series, sample);
+        Ok(())
+    }
+
+    fn range(&self, series: &str, from: SystemTime, to: SystemTime) -> Result<Vec<Sample>, AppError> {
+        if from > to {
+            return Err(AppError::BadRequest("from is after to".to_string()));
+        }
+        let Some(series) = self.series(series) else {
+            return Ok(Vec::new());
+        };
+        Ok(series.get_samples_in_range(from, to)
+            .map(|samples| samples.into_iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn latest(&self, series: &str) -> Option<Sample> {
+        self.series(series)?.get_last_sample().cloned()
+    }
+}
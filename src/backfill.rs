@@ -0,0 +1,72 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use log::{info, warn};
+use serde_json::Value;
+use crate::app_error::AppError;
+use crate::storage::{is_valid_metric_name, Sample, Storage, TEMPERATURE};
+
+/// Seconds the peer gets to send its export; startup waits for the backfill.
+const TIMEOUT_SECS: u64 = 120;
+
+/// Copies the samples another heater-monitor instance at `peer` has of each of `sensors` and
+/// this one misses, i.e. those newer than the newest local sample. A peer that cannot be
+/// reached or lacks a sensor is logged and skipped.
+pub async fn backfill(storage: &Arc<RwLock<Storage>>, client: &reqwest::Client, peer: &str, sensors: &[String]) {
+    for sensor in sensors {
+        let after = match storage.read() {
+            Ok(storage) => storage.series(sensor).and_then(|series| series.latest_sample()).map(|sample| sample.timestamp),
+            Err(_) => return,
+        };
+        let samples = match fetch_samples(client, peer, sensor, after).await {
+            Ok(samples) => samples,
+            Err(e) => {
+                warn!("Cannot backfill {} from {}: {}", sensor, peer, e);
+                continue;
+            }
+        };
+        let Ok(mut storage) = storage.write() else {
+            return;
+        };
+        let added = storage.backfill(sensor, samples);
+        info!("Backfilled {} samples of {} from {}", added, sensor, peer);
+    }
+}
+
+/// Samples of `sensor` taken after `after` from the `/export` endpoint of `peer`.
+pub async fn fetch_samples(client: &reqwest::Client, peer: &str, sensor: &str, after: Option<SystemTime>) -> Result<Vec<Sample>, AppError> {
+    let mut query = vec![("format", "json".to_string()), ("sensor", sensor.to_string())];
+    if let Some(after) = after {
+        query.push(("from", (after.duration_since(SystemTime::UNIX_EPOCH)?.as_secs() + 1).to_string()));
+    }
+    // the legacy path, which peers from before `/api/v1` serve too
+    let url = format!("{}/export", peer.trim_end_matches('/'));
+    let body = client.get(&url).query(&query).timeout(Duration::from_secs(TIMEOUT_SECS)).send().await?.error_for_status()?.bytes().await?;
+    parse_export(&serde_json::from_slice(&body)?)
+}
+
+/// Reads the `[{"timestamp": <secs>, "temperature": ..., "<metric>": ...}]` of `/export?format=json`.
+pub fn parse_export(export: &Value) -> Result<Vec<Sample>, AppError> {
+    let objects = export.as_array()
+        .ok_or_else(|| AppError::ParseError("export is not an array".to_string()))?;
+    let mut samples = Vec::with_capacity(objects.len());
+    for object in objects {
+        let error = || AppError::ParseError(format!("invalid exported sample {}", object));
+        let object = object.as_object().ok_or_else(error)?;
+        let timestamp = object.get("timestamp").and_then(Value::as_u64)
+            .and_then(|secs| SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs)))
+            .ok_or_else(error)?;
+        let temperature = object.get(TEMPERATURE).and_then(Value::as_f64).ok_or_else(error)?;
+        let mut sample = Sample::new(timestamp, temperature);
+        for (metric, value) in object {
+            if metric == "timestamp" || !is_valid_metric_name(metric) {
+                continue;
+            }
+            if let Some(value) = value.as_f64() {
+                sample.metrics.insert(metric.clone(), value);
+            }
+        }
+        samples.push(sample);
+    }
+    samples.sort_by_key(|sample| sample.timestamp);
+    Ok(samples)
+}
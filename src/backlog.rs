@@ -0,0 +1,676 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use crate::app_error::AppError;
+use crate::downsampling::{roll_up_records, Tier};
+use crate::storage::{Sample, DEFAULT_SERIES};
+
+/// Starts every binary backlog, followed by the format version.
+const BINARY_MAGIC: &[u8; 4] = b"HMBL";
+const BINARY_VERSION: u8 = 1;
+
+/// Suffix of a zstd-compressed closed segment.
+const COMPRESSED_SUFFIX: &str = ".zst";
+
+/// Held while closed segments are compressed, rewritten or deleted, as `remove_from_segments`
+/// runs without the storage lock.
+static SEGMENT_REWRITES: Mutex<()> = Mutex::new(());
+
+/// A sample and the series it belongs to.
+pub type Record = (String, Sample);
+
+/// Record format of newly created backlog files, existing files keep the format they have.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BacklogFormat {
+    /// One `t1`/`s1`/`m1` line per sample.
+    #[default]
+    Text,
+    /// Length-prefixed records with a CRC32 each, a torn record at the end is cut off on load.
+    Binary,
+}
+
+/// When the backlog is closed as a segment and a new file started.
+#[derive(Debug, Clone, Default)]
+pub struct Rotation {
+    /// Size in bytes the backlog may reach.
+    pub max_size: Option<u64>,
+    /// Time since the first sample of the backlog.
+    pub max_age: Option<Duration>,
+    /// Compress closed segments with zstd.
+    pub compress: bool,
+}
+
+/// How often buffered records reach the file and the disk.
+#[derive(Debug, Clone, Default)]
+pub struct Flushing {
+    /// Records are written to the file at most this often, `None` writes every record at once.
+    pub flush_interval: Option<Duration>,
+    /// The file is synced to the disk at most this often, `None` leaves it to the OS.
+    pub fsync_interval: Option<Duration>,
+}
+
+/// Append-only file the samples are persisted to, replayed by `Storage::new`.
+#[derive(Debug)]
+pub struct Backlog {
+    path: String,
+    file: BufWriter<File>,
+    format: BacklogFormat,
+    rotation: Rotation,
+    flushing: Flushing,
+    last_flush: Instant,
+    last_fsync: Instant,
+    size: u64,
+    /// Timestamp of the first sample in the backlog.
+    started: Option<SystemTime>,
+}
+
+impl Backlog {
+    /// Reads every sample of the backlog at `path` and opens it for appending.
+    pub fn open(path: &str, format: BacklogFormat) -> Result<(Self, Vec<Record>), AppError> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut file = File::options().create(true).append(true).open(path)?;
+        let (format, samples) = if data.starts_with(BINARY_MAGIC) {
+            let (samples, valid_len) = read_binary(&data);
+            if valid_len < data.len() {
+                // appending after a torn record would hide everything written later
+                warn!("Cutting off {} corrupt bytes at the end of {}", data.len() - valid_len, path);
+                file.set_len(valid_len as u64)?;
+            }
+            (BacklogFormat::Binary, samples)
+        } else if data.is_empty() {
+            write_header(&mut file, format)?;
+            (format, Vec::new())
+        } else {
+            (BacklogFormat::Text, read_text(&data))
+        };
+
+        let started = samples.iter().map(|(_, sample)| sample.timestamp).min();
+        Ok((Self::new(path, file, format, started)?, samples))
+    }
+
+    /// Opens the backlog at `path` for appending without reading its samples, as long as
+    /// it still has the `size` a snapshot recorded. `None` when it changed since.
+    pub fn resume(path: &str, size: u64, started: Option<SystemTime>) -> Result<Option<Self>, AppError> {
+        let file = match File::options().read(true).append(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if file.metadata()?.len() != size {
+            return Ok(None);
+        }
+        let mut magic = [0u8; 4];
+        let format = match (&file).read_exact(&mut magic) {
+            Ok(()) if &magic == BINARY_MAGIC => BacklogFormat::Binary,
+            _ => BacklogFormat::Text,
+        };
+        Ok(Some(Self::new(path, file, format, started)?))
+    }
+
+    fn new(path: &str, file: File, format: BacklogFormat, started: Option<SystemTime>) -> Result<Self, AppError> {
+        Ok(Self {
+            path: path.to_string(),
+            size: file.metadata()?.len(),
+            started,
+            file: BufWriter::new(file),
+            format,
+            rotation: Rotation::default(),
+            flushing: Flushing::default(),
+            last_flush: Instant::now(),
+            last_fsync: Instant::now(),
+        })
+    }
+
+    /// Bytes written to the backlog file, including buffered records.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Timestamp of the first sample in the backlog file.
+    pub fn started(&self) -> Option<SystemTime> {
+        self.started
+    }
+
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_flushing(mut self, flushing: Flushing) -> Self {
+        self.flushing = flushing;
+        self
+    }
+
+    /// Writes the buffered records to the file and syncs it once `fsync_interval` has passed.
+    pub fn flush(&mut self) -> Result<(), AppError> {
+        self.file.flush()?;
+        self.last_flush = Instant::now();
+        if self.flushing.fsync_interval.is_some_and(|interval| self.last_fsync.elapsed() >= interval) {
+            self.file.get_ref().sync_data()?;
+            self.last_fsync = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Flushes and syncs unconditionally, before shutdown.
+    pub fn sync(&mut self) -> Result<(), AppError> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()?;
+        self.last_flush = Instant::now();
+        self.last_fsync = Instant::now();
+        Ok(())
+    }
+
+    /// Closed segments next to the backlog at `path`, named `<backlog>.<suffix>`, newest first.
+    /// Ordered by modification time, i.e. by their last sample, so segments left by logrotate
+    /// sort correctly too.
+    pub fn closed_segments(path: &str) -> Result<Vec<PathBuf>, AppError> {
+        let path = Path::new(path);
+        let Some(name) = path.file_name().map(|name| format!("{}.", name.to_string_lossy())) else {
+            return Ok(Vec::new());
+        };
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            // a compression interrupted by a restart leaves its temporary file behind
+            if !file_name.starts_with(&name) || file_name.ends_with(".tmp") || !entry.file_type()?.is_file() {
+                continue;
+            }
+            match entry.metadata().and_then(|metadata| metadata.modified()) {
+                Ok(modified) => segments.push((modified, entry.path())),
+                // compressed or deleted since the directory was read
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        segments.sort_by(|a, b| b.cmp(a));
+        Ok(segments.into_iter().map(|(_, segment)| segment).collect())
+    }
+
+    /// Reads a closed segment, decompressing a `.zst` one.
+    pub fn load_segment(segment: &Path) -> Result<Vec<Record>, AppError> {
+        Ok(read_segment(&read_segment_data(segment)?))
+    }
+
+    /// Deletes the closed segments of the backlog at `path` last written before `cutoff`,
+    /// i.e. holding only older samples.
+    pub fn prune_segments(path: &str, cutoff: SystemTime) -> Result<usize, AppError> {
+        let _rewrites = SEGMENT_REWRITES.lock()?;
+        let mut pruned = 0;
+        for segment in Self::closed_segments(path)? {
+            if fs::metadata(&segment)?.modified()? < cutoff {
+                fs::remove_file(&segment)?;
+                info!("Removed expired backlog segment {}", segment.display());
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Rewrites the closed segments of the backlog at `path` holding samples due for a rollup
+    /// by `tiers`, keeping their format, compression and modification time.
+    pub fn downsample_segments(path: &str, tiers: &[Tier]) -> Result<(), AppError> {
+        let Some(newest) = tiers.iter().map(|tier| tier.before).max() else {
+            return Ok(());
+        };
+        let _rewrites = SEGMENT_REWRITES.lock()?;
+        for segment in Self::closed_segments(path)? {
+            let modified = match fs::metadata(&segment) {
+                Ok(metadata) => metadata.modified()?,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if modified >= newest {
+                continue;
+            }
+            let Some(data) = read_listed_segment(&segment)? else {
+                continue;
+            };
+            let records = read_segment(&data);
+            let count = records.len();
+            let rolled = roll_up_records(records, tiers);
+            if rolled.len() == count {
+                continue;
+            }
+
+            rewrite_segment(&segment, &data, &rolled, modified)?;
+            info!("Downsampled backlog segment {} from {} to {} samples", segment.display(), count, rolled.len());
+        }
+        Ok(())
+    }
+
+    /// Removes the records of `series` taken between `from` and `to` from the closed segments
+    /// of the backlog at `path`, keeping their format, compression and modification time.
+    /// A segment left empty is deleted. Returns the number of removed records.
+    pub fn remove_from_segments(path: &str, series: &str, from: SystemTime, to: SystemTime) -> Result<usize, AppError> {
+        let _rewrites = SEGMENT_REWRITES.lock()?;
+        let mut removed = 0;
+        for segment in Self::closed_segments(path)? {
+            let Some(data) = read_listed_segment(&segment)? else {
+                continue;
+            };
+            let records = read_segment(&data);
+            let count = records.len();
+            let kept = without_range(records, series, from, to);
+            if kept.len() == count {
+                continue;
+            }
+            removed += count - kept.len();
+            if kept.is_empty() {
+                fs::remove_file(&segment)?;
+            } else {
+                rewrite_segment(&segment, &data, &kept, fs::metadata(&segment)?.modified()?)?;
+            }
+            info!("Removed {} samples of {} from backlog segment {}", count - kept.len(), series, segment.display());
+        }
+        Ok(removed)
+    }
+
+    /// Like `remove_from_segments` for the open backlog only.
+    pub fn remove_range(&mut self, series: &str, from: SystemTime, to: SystemTime) -> Result<usize, AppError> {
+        self.file.flush()?;
+        let data = fs::read(&self.path)?;
+        let records = read_segment(&data);
+        let count = records.len();
+        let kept = without_range(records, series, from, to);
+        if kept.len() < count {
+            replace_file(Path::new(&self.path), &encode(self.format, &kept)?, SystemTime::now())?;
+            let file = File::options().append(true).open(&self.path)?;
+            self.size = file.metadata()?.len();
+            self.file = BufWriter::new(file);
+            self.started = kept.iter().map(|(_, sample)| sample.timestamp).min();
+        }
+        Ok(count - kept.len())
+    }
+
+    /// Writes `records`, oldest first, as a closed segment `<backlog>.import-<YYYYmmdd-HHMMSS>`
+    /// of the backlog at `path`, loaded with the other segments on the next startup.
+    pub fn write_segment(path: &str, format: BacklogFormat, records: &[Record]) -> Result<PathBuf, AppError> {
+        let segment = PathBuf::from(format!("{}.import-{}", path, chrono::Local::now().format("%Y%m%d-%H%M%S")));
+        if segment.exists() {
+            return Err(AppError::InternalError(format!("{} already exists", segment.display())));
+        }
+        // segments are ordered by their modification time, i.e. their last sample
+        let modified = records.last().map_or_else(SystemTime::now, |(_, sample)| sample.timestamp);
+        replace_file(&segment, &encode(format, records)?, modified)?;
+        Ok(segment)
+    }
+
+    /// Replaces the uncompressed closed segments of the backlog at `path` by zstd-compressed copies.
+    pub fn compress_closed_segments(path: &str) -> Result<(), AppError> {
+        for segment in Self::closed_segments(path)? {
+            if !is_compressed(&segment) {
+                compress_segment(&segment)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn append(&mut self, series: &str, sample: &Sample) -> Result<(), AppError> {
+        let record = match self.format {
+            BacklogFormat::Text => {
+                let mut line = text_record(series, sample)?;
+                line.push('\n');
+                line.into_bytes()
+            }
+            BacklogFormat::Binary => binary_record(series, sample)?,
+        };
+        if self.due_for_rotation(sample.timestamp) {
+            self.rotate()?;
+        }
+        // records reach the file whole, a torn one is only left by a power cut mid-write
+        self.file.write_all(&record)?;
+        self.size += record.len() as u64;
+        self.started.get_or_insert(sample.timestamp);
+        if self.flushing.flush_interval.is_none_or(|interval| self.last_flush.elapsed() >= interval) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn due_for_rotation(&self, now: SystemTime) -> bool {
+        let too_big = self.rotation.max_size.is_some_and(|max_size| self.size >= max_size);
+        let too_old = self.rotation.max_age.zip(self.started)
+            .is_some_and(|(max_age, started)| now.duration_since(started).unwrap_or_default() >= max_age);
+        too_big || too_old
+    }
+
+    /// Renames the backlog to `<backlog>.<YYYYmmdd-HHMMSS>` and starts a new one.
+    fn rotate(&mut self) -> Result<(), AppError> {
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        let mut segment = PathBuf::from(format!("{}.{}", self.path, stamp));
+        let mut n = 1;
+        while segment.exists() || PathBuf::from(format!("{}{}", segment.display(), COMPRESSED_SUFFIX)).exists() {
+            segment = PathBuf::from(format!("{}.{}-{}", self.path, stamp, n));
+            n += 1;
+        }
+        self.file.flush()?;
+        fs::rename(&self.path, &segment)?;
+        info!("Rotated backlog to {}", segment.display());
+
+        let mut file = File::options().create(true).append(true).open(&self.path)?;
+        write_header(&mut file, self.format)?;
+        self.size = file.metadata()?.len();
+        self.file = BufWriter::new(file);
+        self.started = None;
+
+        if self.rotation.compress {
+            compress_segment(&segment)?;
+        }
+        Ok(())
+    }
+}
+
+fn encode(format: BacklogFormat, records: &[Record]) -> Result<Vec<u8>, AppError> {
+    let mut out = Vec::new();
+    if format == BacklogFormat::Binary {
+        out.extend_from_slice(BINARY_MAGIC);
+        out.push(BINARY_VERSION);
+    }
+    for (series, sample) in records {
+        match format {
+            BacklogFormat::Text => {
+                out.extend_from_slice(text_record(series, sample)?.as_bytes());
+                out.push(b'\n');
+            }
+            BacklogFormat::Binary => out.extend_from_slice(&binary_record(series, sample)?),
+        }
+    }
+    Ok(out)
+}
+
+fn without_range(mut records: Vec<Record>, series: &str, from: SystemTime, to: SystemTime) -> Vec<Record> {
+    records.retain(|(name, sample)| name != series || sample.timestamp < from || sample.timestamp > to);
+    records
+}
+
+/// Replaces `segment`, read as `data`, by `records` in the same format and compression.
+fn rewrite_segment(segment: &Path, data: &[u8], records: &[Record], modified: SystemTime) -> Result<(), AppError> {
+    let format = if data.starts_with(BINARY_MAGIC) { BacklogFormat::Binary } else { BacklogFormat::Text };
+    let mut out = encode(format, records)?;
+    if is_compressed(segment) {
+        out = zstd::encode_all(out.as_slice(), 0)?;
+    }
+    replace_file(segment, &out, modified)
+}
+
+fn write_header(file: &mut File, format: BacklogFormat) -> Result<(), AppError> {
+    if format == BacklogFormat::Binary {
+        file.write_all(BINARY_MAGIC)?;
+        file.write_all(&[BINARY_VERSION])?;
+    }
+    Ok(())
+}
+
+fn is_compressed(segment: &Path) -> bool {
+    segment.to_string_lossy().ends_with(COMPRESSED_SUFFIX)
+}
+
+fn read_segment_data(segment: &Path) -> Result<Vec<u8>, AppError> {
+    let data = fs::read(segment)?;
+    if is_compressed(segment) {
+        Ok(zstd::decode_all(data.as_slice())?)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Like `read_segment_data`, `None` for a segment removed since it was listed.
+fn read_listed_segment(segment: &Path) -> Result<Option<Vec<u8>>, AppError> {
+    match read_segment_data(segment) {
+        Err(AppError::IOError(e)) if e.kind() == ErrorKind::NotFound => Ok(None),
+        result => result.map(Some),
+    }
+}
+
+/// Writes `data` to `target` through a temporary file with the modification time `modified`,
+/// which `closed_segments` orders by.
+fn replace_file(target: &Path, data: &[u8], modified: SystemTime) -> Result<(), AppError> {
+    let tmp = PathBuf::from(format!("{}.tmp", target.display()));
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.set_modified(modified)?;
+    drop(file);
+    fs::rename(&tmp, target)?;
+    Ok(())
+}
+
+/// Writes `<segment>.zst` and removes the uncompressed `segment`, unless it is gone already.
+fn compress_segment(segment: &Path) -> Result<(), AppError> {
+    let _rewrites = SEGMENT_REWRITES.lock()?;
+    let data = match fs::read(segment) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let modified = fs::metadata(segment)?.modified()?;
+    let target = PathBuf::from(format!("{}{}", segment.display(), COMPRESSED_SUFFIX));
+    replace_file(&target, &zstd::encode_all(data.as_slice(), 0)?, modified)?;
+    fs::remove_file(segment)?;
+    info!("Compressed backlog segment {}", target.display());
+    Ok(())
+}
+
+fn read_segment(data: &[u8]) -> Vec<Record> {
+    if data.starts_with(BINARY_MAGIC) {
+        read_binary(data).0
+    } else {
+        read_text(data)
+    }
+}
+
+fn read_text(data: &[u8]) -> Vec<Record> {
+    let mut samples = Vec::new();
+    for line in BufReader::new(data).lines() {
+        let Ok(mut line) = line else {
+            error!("Failed to read line from backlog");
+            continue;
+        };
+        if let Ok(record) = parse_text_record(&line) {
+            samples.push(record);
+        } else {
+            line.truncate(100);
+            error!("Failed to parse sample from file: {}", &line);
+        }
+    }
+    samples
+}
+
+// The default series keeps the original "t1" format so existing backlogs stay readable,
+// named series are stored as "s1 <name> <secs> <temp>", readings with further metrics
+// as "m1 <name> <secs> <temp> <metric>=<value>..." and downsampled averages as
+// "r1 <name> <secs> <span secs> <temp> <metric>=<value>...".
+fn text_record(series: &str, sample: &Sample) -> Result<String, AppError> {
+    let secs = sample.timestamp.duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+    if let Some(span) = sample.span {
+        let mut line = format!("r1 {} {} {} {}", series, secs, span.as_secs(), sample.temperature);
+        for (metric, value) in &sample.metrics {
+            line.push_str(&format!(" {}={}", metric, value));
+        }
+        Ok(line)
+    } else if !sample.metrics.is_empty() {
+        let mut line = format!("m1 {} {} {}", series, secs, sample.temperature);
+        for (metric, value) in &sample.metrics {
+            line.push_str(&format!(" {}={}", metric, value));
+        }
+        Ok(line)
+    } else if series == DEFAULT_SERIES {
+        Ok(format!("t1 {} {}", secs, sample.temperature))
+    } else {
+        Ok(format!("s1 {} {} {}", series, secs, sample.temperature))
+    }
+}
+
+fn parse_text_record(line: &str) -> Result<Record, AppError> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+
+    let (series, span, parts, metrics) = match parts.as_slice() {
+        ["t1", rest @ ..] if rest.len() == 2 => (DEFAULT_SERIES, None, rest, &[][..]),
+        ["s1", name, rest @ ..] if rest.len() == 2 => (*name, None, rest, &[][..]),
+        ["m1", name, rest @ ..] if rest.len() >= 2 => (*name, None, &rest[..2], &rest[2..]),
+        ["r1", name, secs, span, temp, metrics @ ..] => (*name, Some(*span), &[*secs, *temp][..], metrics),
+        _ => return Err(AppError::ParseError(format!("Invalid sample format: {}", line))),
+    };
+
+    let timestamp_secs: u64 = parts[0].parse()
+        .map_err(|_| AppError::ParseError(format!("Invalid timestamp: {}", parts[0])))?;
+
+    let temperature: f64 = parts[1].parse()
+        .map_err(|_| AppError::ParseError(format!("Invalid temperature: {}", parts[1])))?;
+
+    let mut sample = checked_sample(timestamp_secs, temperature)?;
+    if let Some(span) = span {
+        let span: u64 = span.parse()
+            .map_err(|_| AppError::ParseError(format!("Invalid span: {}", span)))?;
+        sample.span = Some(Duration::from_secs(span));
+    }
+    for metric in metrics {
+        let (name, value) = metric.split_once('=')
+            .ok_or_else(|| AppError::ParseError(format!("Invalid metric: {}", metric)))?;
+        let value: f64 = value.parse()
+            .map_err(|_| AppError::ParseError(format!("Invalid metric value: {}", metric)))?;
+        sample.metrics.insert(name.to_string(), value);
+    }
+
+    Ok((series.to_string(), sample))
+}
+
+fn checked_sample(timestamp_secs: u64, temperature: f64) -> Result<Sample, AppError> {
+    if !(-1000.0..=1000.0).contains(&temperature) {
+        return Err(AppError::ParseError(format!("Invalid temperature range: {}", temperature)));
+    }
+    Ok(Sample::new(SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp_secs), temperature))
+}
+
+// Record: u32 payload length, payload, u32 CRC32 of the payload, all little endian.
+// Payload: u64 secs, f64 temperature, series name, u8 metric count, (name, f64 value) per metric
+// and, for a downsampled average only, the u32 span secs; names are a u8 length followed by the bytes.
+pub(crate) fn binary_record(series: &str, sample: &Sample) -> Result<Vec<u8>, AppError> {
+    let mut payload = Vec::with_capacity(32);
+    payload.extend_from_slice(&sample.timestamp.duration_since(SystemTime::UNIX_EPOCH)?.as_secs().to_le_bytes());
+    payload.extend_from_slice(&sample.temperature.to_le_bytes());
+    push_name(&mut payload, series)?;
+    payload.push(u8::try_from(sample.metrics.len())?);
+    for (metric, value) in &sample.metrics {
+        push_name(&mut payload, metric)?;
+        payload.extend_from_slice(&value.to_le_bytes());
+    }
+    if let Some(span) = sample.span {
+        payload.extend_from_slice(&u32::try_from(span.as_secs())?.to_le_bytes());
+    }
+
+    let mut record = Vec::with_capacity(payload.len() + 8);
+    record.extend_from_slice(&u32::try_from(payload.len())?.to_le_bytes());
+    record.extend_from_slice(&payload);
+    record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    Ok(record)
+}
+
+fn push_name(payload: &mut Vec<u8>, name: &str) -> Result<(), AppError> {
+    payload.push(u8::try_from(name.len())?);
+    payload.extend_from_slice(name.as_bytes());
+    Ok(())
+}
+
+/// Returns the samples and the length of the data up to the first corrupt record.
+fn read_binary(data: &[u8]) -> (Vec<Record>, usize) {
+    let header = BINARY_MAGIC.len() + 1;
+    if data.get(BINARY_MAGIC.len()) != Some(&BINARY_VERSION) {
+        error!("Unsupported binary backlog version");
+        return (Vec::new(), data.len());
+    }
+    let (samples, len) = read_binary_records(&data[header..]);
+    (samples, header + len)
+}
+
+/// Reads binary records up to the first corrupt one, returns them and the length they take.
+pub(crate) fn read_binary_records(data: &[u8]) -> (Vec<Record>, usize) {
+    let mut samples = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        match parse_binary_record(&data[offset..]) {
+            Some((record, len)) => {
+                match record {
+                    Ok(record) => samples.push(record),
+                    Err(e) => error!("Failed to parse sample from file: {}", e),
+                }
+                offset += len;
+            }
+            None => {
+                error!("Corrupt backlog record at offset {}", offset);
+                break;
+            }
+        }
+    }
+    (samples, offset)
+}
+
+/// `None` when the record is truncated or fails its checksum.
+fn parse_binary_record(data: &[u8]) -> Option<(Result<Record, AppError>, usize)> {
+    let len = u32::from_le_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let payload = data.get(4..4 + len)?;
+    let crc = u32::from_le_bytes(data.get(4 + len..8 + len)?.try_into().ok()?);
+    if crc32fast::hash(payload) != crc {
+        return None;
+    }
+    Some((parse_binary_payload(payload), len + 8))
+}
+
+fn parse_binary_payload(payload: &[u8]) -> Result<Record, AppError> {
+    let mut reader = PayloadReader { data: payload };
+    let secs = u64::from_le_bytes(reader.take_array()?);
+    let mut sample = checked_sample(secs, f64::from_le_bytes(reader.take_array()?))?;
+    let series = reader.take_name()?;
+    let [count] = reader.take_array()?;
+    let mut metrics = BTreeMap::new();
+    for _ in 0..count {
+        let name = reader.take_name()?;
+        metrics.insert(name, f64::from_le_bytes(reader.take_array()?));
+    }
+    sample.metrics = metrics;
+    if !reader.data.is_empty() {
+        sample.span = Some(Duration::from_secs(u32::from_le_bytes(reader.take_array()?).into()));
+    }
+    Ok((series, sample))
+}
+
+struct PayloadReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> PayloadReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], AppError> {
+        if self.data.len() < len {
+            return Err(AppError::ParseError("truncated backlog record".to_string()));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], AppError> {
+        Ok(self.take(N)?.try_into().expect("take returns N bytes"))
+    }
+
+    fn take_name(&mut self) -> Result<String, AppError> {
+        let [len] = self.take_array()?;
+        String::from_utf8(self.take(len as usize)?.to_vec())
+            .map_err(|_| AppError::ParseError("invalid name in backlog record".to_string()))
+    }
+}
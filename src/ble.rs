@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use btleplug::api::{Central, CentralEvent, Manager as _, ScanFilter};
+use btleplug::platform::Manager;
+use futures::StreamExt;
+use log::{error, info, warn};
+use uuid::Uuid;
+use crate::config::Calibration;
+use crate::sensor_status::SensorStatuses;
+use crate::sample_filter::SampleFilter;
+use crate::backend::SharedBackend;
+use crate::temp_sensor::Measurement;
+
+/// Environmental Sensing service, the custom thermometer firmwares advertise their readings under it.
+const ENVIRONMENTAL_SENSING: Uuid = Uuid::from_u128(0x0000181a_0000_1000_8000_00805f9b34fb);
+
+/// A thermometer identified by the MAC address inside its advertisements.
+pub struct BleSubscription {
+    pub name: String,
+    pub mac: String,
+    /// Seconds between stored readings.
+    pub sampling_interval: u64,
+    pub calibration: Calibration,
+    pub filter: SampleFilter,
+}
+
+/// Decodes the service data of the ATC1441 (13 bytes) and pvvx (15 bytes) firmwares
+/// into the sender's MAC address and the measurement.
+pub fn parse_advertisement(data: &[u8]) -> Option<(String, Measurement)> {
+    match data.len() {
+        13 => Some((format_mac(data[..6].iter()), with_voltage(Measurement::new(
+            i16::from_be_bytes([data[6], data[7]]) as f64 / 10.0,
+            data[8] as f64,
+        ), u16::from_be_bytes([data[10], data[11]])))),
+        // pvvx sends everything little endian, including the MAC address
+        15 => Some((format_mac(data[..6].iter().rev()), with_voltage(Measurement::new(
+            i16::from_le_bytes([data[6], data[7]]) as f64 / 100.0,
+            u16::from_le_bytes([data[8], data[9]]) as f64 / 100.0,
+        ), u16::from_le_bytes([data[10], data[11]])))),
+        _ => None,
+    }
+}
+
+/// Both firmwares report the battery voltage in mV, stored in volts.
+fn with_voltage(mut measurement: Measurement, millivolts: u16) -> Measurement {
+    measurement.metrics.insert("battery_voltage".to_string(), millivolts as f64 / 1000.0);
+    measurement
+}
+
+fn format_mac<'a>(bytes: impl Iterator<Item = &'a u8>) -> String {
+    bytes.map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(":")
+}
+
+pub async fn run_ble(
+    mut subscriptions: Vec<BleSubscription>,
+    storage: SharedBackend,
+    statuses: Arc<Mutex<SensorStatuses>>) {
+    loop {
+        if let Err(e) = listen(&mut subscriptions, &storage, &statuses).await {
+            error!("BLE scanner failed: {}", e);
+        }
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    }
+}
+
+async fn listen(
+    subscriptions: &mut [BleSubscription],
+    storage: &SharedBackend,
+    statuses: &Arc<Mutex<SensorStatuses>>) -> Result<(), btleplug::Error> {
+    let manager = Manager::new().await?;
+    let adapter = manager.adapters().await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| btleplug::Error::Other("no bluetooth adapter found".into()))?;
+    let mut events = adapter.events().await?;
+    adapter.start_scan(ScanFilter { services: vec![ENVIRONMENTAL_SENSING] }).await?;
+    info!("Listening for BLE advertisements of {} thermometers", subscriptions.len());
+
+    // thermometers advertise every few seconds, store at most one reading per sampling interval
+    let mut last_stored: HashMap<String, Instant> = HashMap::new();
+    while let Some(event) = events.next().await {
+        let CentralEvent::ServiceDataAdvertisement { service_data, .. } = event else {
+            continue;
+        };
+        let Some((mac, val)) = service_data.get(&ENVIRONMENTAL_SENSING).and_then(|data| parse_advertisement(data)) else {
+            continue;
+        };
+        for subscription in subscriptions.iter_mut().filter(|s| s.mac == mac) {
+            if last_stored.get(&subscription.name)
+                .is_some_and(|time| time.elapsed() < Duration::from_secs(subscription.sampling_interval)) {
+                continue;
+            }
+            last_stored.insert(subscription.name.clone(), Instant::now());
+            let mut reading = val.clone();
+            reading.temperature = subscription.calibration.apply(val.temperature);
+            if let Err(reason) = subscription.filter.check(reading.temperature) {
+                warn!("{}: rejected reading: {} ({} rejected so far)",
+                    subscription.name, reason, subscription.filter.rejected());
+                if let Ok(mut statuses) = statuses.lock() {
+                    statuses.record_rejection(&subscription.name);
+                }
+                continue;
+            }
+            if let Ok(mut statuses) = statuses.lock() {
+                statuses.record_success(&subscription.name);
+            }
+            if let Ok(mut storage) = storage.write() {
+                if let Err(e) = storage.append(&subscription.name, reading.into_sample(SystemTime::now())) {
+                    error!("{}: failed to store sample: {}", subscription.name, e);
+                }
+            } else {
+                error!("failed to lock storage");
+            }
+        }
+    }
+    Ok(())
+}
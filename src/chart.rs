@@ -0,0 +1,159 @@
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+use plotters::prelude::*;
+use plotters::style::register_font;
+use crate::storage::{Bucket, HUMIDITY, TEMPERATURE};
+
+/// Text of the charts, built into the binary so a host without any fonts renders them too.
+const FONT: &[u8] = include_bytes!("../assets/DejaVuSans.ttf");
+const FONT_FAMILY: &str = "sans-serif";
+
+/// Colors of the lines in order, those of the dashboard.
+const COLORS: [RGBColor; 3] = [RGBColor(0xff, 0x6b, 0x6b), RGBColor(0x4d, 0xab, 0xf7), RGBColor(0x20, 0xc9, 0x97)];
+
+/// Bounds of the image width and height in pixels.
+pub const MIN_SIZE: u32 = 100;
+pub const MAX_SIZE: u32 = 4000;
+
+/// A named series of a chart.
+pub struct Line<'a> {
+    pub label: &'a str,
+    pub buckets: &'a [Bucket],
+}
+
+/// Line chart rendered to a PNG, for clients without JavaScript such as e-mails or e-ink displays.
+pub struct Chart<'a> {
+    pub title: &'a str,
+    /// Description of the y axis, e.g. `temperature (°C)`.
+    pub y_label: &'a str,
+    pub lines: Vec<Line<'a>>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Chart<'_> {
+    /// PNG of the lines over the `start` of their buckets, `time_label` formats the Unix seconds
+    /// along the x axis. A gap breaks a line; without any value there is nothing to draw.
+    pub fn render_png(&self, time_label: &dyn Fn(u64) -> String) -> Result<Vec<u8>, anyhow::Error> {
+        if !(MIN_SIZE..=MAX_SIZE).contains(&self.width) || !(MIN_SIZE..=MAX_SIZE).contains(&self.height) {
+            bail!("chart size must be between {} and {} pixels", MIN_SIZE, MAX_SIZE);
+        }
+        register_chart_font()?;
+        let values: Vec<(u64, f64)> = self.lines.iter()
+            .flat_map(|line| line.buckets.iter())
+            .filter_map(|bucket| bucket.value.map(|value| (bucket.start, value)))
+            .collect();
+        if values.is_empty() {
+            bail!("no values to draw");
+        }
+        let (first, last) = values.iter().fold((u64::MAX, u64::MIN), |(first, last), &(start, _)| (first.min(start), last.max(start)));
+        let (min, max) = values.iter().fold((f64::MAX, f64::MIN), |(min, max), &(_, value)| (min.min(value), max.max(value)));
+        // a flat line still gets some room above and below
+        let padding = ((max - min) * 0.05).max(0.5);
+
+        let mut pixels = vec![0; self.width as usize * self.height as usize * 3];
+        {
+            let root = BitMapBackend::with_buffer(&mut pixels, (self.width, self.height)).into_drawing_area();
+            root.fill(&WHITE)?;
+            let mut chart = ChartBuilder::on(&root)
+                .caption(self.title, (FONT_FAMILY, 20))
+                .margin(10)
+                .x_label_area_size(30)
+                .y_label_area_size(60)
+                .build_cartesian_2d(first..last.max(first + 1), (min - padding)..(max + padding))?;
+            chart.configure_mesh()
+                .x_labels(6)
+                .x_label_formatter(&|secs| time_label(*secs))
+                .y_desc(self.y_label)
+                .label_style((FONT_FAMILY, 12))
+                .axis_desc_style((FONT_FAMILY, 14))
+                .draw()?;
+
+            for (line, &color) in self.lines.iter().zip(COLORS.iter().cycle()) {
+                for (i, segment) in segments(line.buckets).into_iter().enumerate() {
+                    let series = chart.draw_series(LineSeries::new(segment, color.stroke_width(2)))?;
+                    if i == 0 {
+                        series.label(line.label)
+                            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+                    }
+                }
+            }
+            if self.lines.len() > 1 {
+                chart.configure_series_labels()
+                    .label_font((FONT_FAMILY, 12))
+                    .background_style(WHITE.mix(0.8))
+                    .border_style(BLACK)
+                    .draw()?;
+            }
+            root.present()?;
+        }
+        encode_png(&pixels, self.width, self.height)
+    }
+}
+
+/// Description of the y axis of a chart of `metric`.
+pub fn metric_label(metric: &str) -> String {
+    match metric {
+        TEMPERATURE => "temperature (°C)".to_string(),
+        HUMIDITY => "humidity (%)".to_string(),
+        _ => metric.to_string(),
+    }
+}
+
+/// `strftime` format of the x axis labels of a chart spanning `span`.
+pub fn time_format(span: Duration) -> &'static str {
+    match span.as_secs() {
+        0..=86400 => "%H:%M",
+        86401..=604800 => "%a %H:%M",
+        _ => "%b %d",
+    }
+}
+
+/// Unix seconds `secs` in the configured time zone, the system one when `None`.
+pub fn local_time(timezone: Option<Tz>, secs: u64, format: &str) -> String {
+    let time = DateTime::<Utc>::from(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+    match timezone {
+        Some(tz) => time.with_timezone(&tz).format(format).to_string(),
+        None => time.with_timezone(&Local).format(format).to_string(),
+    }
+}
+
+/// Runs of consecutive values of `buckets` as `(start, value)`, a gap ends one.
+fn segments(buckets: &[Bucket]) -> Vec<Vec<(u64, f64)>> {
+    let mut segments = Vec::new();
+    let mut current = Vec::new();
+    for bucket in buckets {
+        match bucket.value {
+            Some(value) => current.push((bucket.start, value)),
+            None if !current.is_empty() => segments.push(std::mem::take(&mut current)),
+            None => {}
+        }
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+    segments
+}
+
+fn register_chart_font() -> Result<(), anyhow::Error> {
+    static REGISTERED: OnceLock<bool> = OnceLock::new();
+    let registered = *REGISTERED.get_or_init(|| register_font(FONT_FAMILY, FontStyle::Normal, FONT).is_ok());
+    if !registered {
+        return Err(anyhow!("cannot load the chart font"));
+    }
+    Ok(())
+}
+
+fn encode_png(pixels: &[u8], width: u32, height: u32) -> Result<Vec<u8>, anyhow::Error> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .context("cannot encode the chart")?;
+    Ok(png)
+}
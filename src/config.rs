@@ -1,26 +1,1226 @@
+use std::collections::BTreeMap;
 use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
 use std::path::PathBuf;
-use serde::Deserialize;
+use std::time::{Duration, SystemTime};
+use anyhow::{anyhow, bail};
+use chrono::NaiveTime;
+use chrono_tz::Tz;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::alerts::{parse_time_range, AlertRule, Routing, StaleAlert};
+use crate::backlog::{BacklogFormat, Flushing, Rotation};
+use crate::downsampling::Tier;
+use crate::sample_filter::FilterConfig;
+use crate::storage::{is_valid_metric_name, DEFAULT_CARRY_FORWARD, DEFAULT_MAX_POINTS, DEFAULT_SERIES, MAX_POINTS, MIN_POINTS, TEMPERATURE};
 // use crate::temp_sensor::TempSensor;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SensorSource {
+    /// Polls the sensor web page and scrapes the measurement.
+    Http {
+        #[serde(serialize_with = "redacted_url")]
+        url: String,
+        /// Needs a `temperature` named group, `humidity` is optional.
+        #[serde(default = "default_scrape_regex")]
+        regex: String,
+        #[serde(flatten)]
+        auth: HttpAuth,
+    },
+    /// Fetches a JSON document and reads the values at the given JSON pointers.
+    Json {
+        #[serde(serialize_with = "redacted_url")]
+        url: String,
+        #[serde(default = "default_temperature_path")]
+        temperature_path: String,
+        #[serde(default = "default_humidity_path")]
+        humidity_path: String,
+        /// Further metrics by name, read from the JSON pointers, e.g. `co2 = "/co2"`.
+        #[serde(default)]
+        metrics: BTreeMap<String, String>,
+        #[serde(flatten)]
+        auth: HttpAuth,
+    },
+    /// Subscribes to a topic on the `[mqtt]` broker, payloads are JSON documents.
+    Mqtt {
+        topic: String,
+        #[serde(default = "default_temperature_path")]
+        temperature_path: String,
+        #[serde(default = "default_humidity_path")]
+        humidity_path: String,
+        /// Further metrics by name, read from the JSON pointers, e.g. `co2 = "/co2"`.
+        #[serde(default)]
+        metrics: BTreeMap<String, String>,
+    },
+    /// Zigbee2MQTT device, its state is published to `<base_topic>/<device>` on the `[mqtt]` broker.
+    Zigbee2mqtt {
+        device: String,
+        #[serde(default = "default_zigbee2mqtt_base_topic")]
+        base_topic: String,
+        /// Further metrics by name, read from the JSON pointers, e.g. `co2 = "/co2"`.
+        #[serde(default)]
+        metrics: BTreeMap<String, String>,
+    },
+    /// DHT22/AM2302 wired to a GPIO pin of the host (BCM numbering).
+    Dht22 {
+        pin: u8,
+        #[serde(default = "default_dht22_retries")]
+        retries: u32,
+    },
+    /// Xiaomi LYWSD03MMC (or similar) running the ATC/pvvx firmware, read from its BLE advertisements.
+    Ble {
+        mac: String,
+    },
+    /// Scrapes a Prometheus exposition endpoint, the sample is selected by metric name and labels.
+    Prometheus {
+        #[serde(serialize_with = "redacted_url")]
+        url: String,
+        metric: String,
+        humidity_metric: Option<String>,
+        #[serde(default)]
+        labels: BTreeMap<String, String>,
+        #[serde(flatten)]
+        auth: HttpAuth,
+    },
+    /// Tasmota device, polled via its `Status 10` HTTP command.
+    Tasmota {
+        /// Root of the device, e.g. `http://192.168.6.80`.
+        #[serde(serialize_with = "redacted_url")]
+        url: String,
+        /// Section of the status to read, e.g. `DS18B20-2`, defaults to the first one with a temperature.
+        sensor: Option<String>,
+        #[serde(flatten)]
+        auth: HttpAuth,
+    },
+    /// Reads an OID of an SNMPv2c agent, e.g. a rack or UPS temperature probe.
+    Snmp {
+        /// `host:port` of the agent, usually port 161.
+        address: String,
+        #[serde(default = "default_snmp_community", serialize_with = "redacted")]
+        community: String,
+        oid: String,
+        #[serde(default = "default_snmp_scale")]
+        scale: f64,
+        humidity_oid: Option<String>,
+    },
+    /// Generated sine wave plus noise, for running without real hardware.
+    Simulator(SimulatorConfig),
+    /// Current outdoor conditions at the given location from the open-meteo.com API.
+    OpenMeteo {
+        latitude: f64,
+        longitude: f64,
+    },
+    /// Receives measurements pushed to `POST /api/measurements`, never polled.
+    Push,
+    /// Shelly H&T reporting on wake to `GET /api/shelly`, `device` is the id it sends.
+    Shelly {
+        device: String,
+    },
+    /// Reads holding registers of a Modbus TCP or RTU device.
+    Modbus(ModbusSensorConfig),
+    /// Runs `command` (program and arguments, no shell) on every poll and parses its stdout,
+    /// with `regex` when set, as a JSON document otherwise.
+    Exec {
+        command: Vec<String>,
+        regex: Option<String>,
+        #[serde(default = "default_temperature_path")]
+        temperature_path: String,
+        #[serde(default = "default_humidity_path")]
+        humidity_path: String,
+        #[serde(default)]
+        metrics: BTreeMap<String, String>,
+    },
+    /// Line-oriented readings printed to a serial port, e.g. `T=21.3 H=45.1` by an Arduino.
+    Serial {
+        device: String,
+        #[serde(default = "default_serial_baud_rate")]
+        baud_rate: u32,
+        /// Needs a `temperature` named group, other named groups are stored as metrics.
+        #[serde(default = "default_serial_regex")]
+        regex: String,
+    },
+}
+
+/// Credentials and extra headers sent with every request to an HTTP sensor.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct HttpAuth {
+    /// Basic auth, `password` is optional.
+    pub username: Option<String>,
+    #[serde(serialize_with = "redacted_option")]
+    pub password: Option<String>,
+    /// e.g. `{ Authorization = "Bearer <token>" }`
+    #[serde(default, serialize_with = "redacted_values")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl HttpAuth {
+    /// Name of the first header that is not a valid HTTP header.
+    fn invalid_header(&self) -> Option<&str> {
+        self.headers.iter()
+            .find(|(name, value)| reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err()
+                || reqwest::header::HeaderValue::from_str(value).is_err())
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SimulatorConfig {
+    #[serde(default = "default_simulator_mean")]
+    pub mean: f64,
+    #[serde(default = "default_simulator_amplitude")]
+    pub amplitude: f64,
+    /// Seconds of one full wave.
+    #[serde(default = "default_simulator_period")]
+    pub period: u64,
+    /// Maximum deviation added to every reading.
+    #[serde(default = "default_simulator_noise")]
+    pub noise: f64,
+    #[serde(default = "default_simulator_humidity_mean")]
+    pub humidity_mean: f64,
+    #[serde(default = "default_simulator_humidity_amplitude")]
+    pub humidity_amplitude: f64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ModbusSensorConfig {
+    /// `ip:port` of a Modbus TCP device.
+    pub address: Option<String>,
+    /// Serial device of a Modbus RTU bus, e.g. `/dev/ttyUSB0`.
+    pub device: Option<String>,
+    #[serde(default = "default_modbus_baud_rate")]
+    pub baud_rate: u32,
+    #[serde(default = "default_modbus_unit_id")]
+    pub unit_id: u8,
+    /// Register holding the temperature as a signed 16-bit value.
+    pub register: u16,
+    #[serde(default = "default_modbus_scale")]
+    pub scale: f64,
+    pub humidity_register: Option<u16>,
+    #[serde(default = "default_modbus_scale")]
+    pub humidity_scale: f64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SensorConfig {
+    pub name: String,
+    /// Seconds a single query may take before it counts as failed.
+    #[serde(default = "default_query_timeout")]
+    pub timeout: u64,
+    /// Extra attempts after a failed query, the delay doubles after each one.
+    #[serde(default)]
+    pub query_retries: u32,
+    /// Seconds to wait before the first retry.
+    #[serde(default = "default_retry_backoff")]
+    pub retry_backoff: u64,
+    #[serde(default)]
+    pub calibration: Calibration,
+    #[serde(default)]
+    pub filter: FilterConfig,
+    /// Seconds between readings of this sensor, defaults to the global `sampling_interval`.
+    #[serde(default)]
+    pub sampling_interval: Option<u64>,
+    /// Queries per sampling interval, the median of them is stored.
+    #[serde(default = "default_oversampling")]
+    pub oversampling: u32,
+    #[serde(flatten)]
+    pub source: SensorSource,
+}
+
+/// Linear correction of the reported temperature: `temperature * scale + offset`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    #[serde(default)]
+    pub offset: f64,
+    #[serde(default = "default_calibration_scale")]
+    pub scale: f64,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self { offset: 0.0, scale: default_calibration_scale() }
+    }
+}
+
+impl Calibration {
+    pub fn apply(&self, temperature: f64) -> f64 {
+        temperature * self.scale + self.offset
+    }
+}
+
+/// Samples are kept at full resolution for `raw_days`, as 5-minute averages up to
+/// `five_minute_days` and as hourly averages beyond.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Downsampling {
+    pub raw_days: u64,
+    pub five_minute_days: u64,
+}
+
+impl Downsampling {
+    pub fn tiers(&self, now: SystemTime) -> [Tier; 2] {
+        let days = |days: u64| now - Duration::from_secs(days * 86400);
+        [
+            Tier::new(days(self.raw_days), Duration::from_secs(300)),
+            Tier::new(days(self.five_minute_days), Duration::from_secs(3600)),
+        ]
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    pub username: Option<String>,
+    #[serde(serialize_with = "redacted_option")]
+    pub password: Option<String>,
+}
+
+fn default_calibration_scale() -> f64 {
+    1.0
+}
+
+fn default_oversampling() -> u32 {
+    1
+}
+
+fn default_carry_forward_buckets() -> usize {
+    DEFAULT_CARRY_FORWARD
+}
+
+fn default_max_points() -> usize {
+    DEFAULT_MAX_POINTS
+}
+
+fn default_stale_after() -> u64 {
+    600
+}
+
+fn default_query_timeout() -> u64 {
+    10
+}
+
+fn default_retry_backoff() -> u64 {
+    1
+}
+
+fn default_scrape_regex() -> String {
+    r"teplota:\s*<b>\s*(?P<humidity>\d+\.\d+)\s*%\s*(?P<temperature>\d+\.\d+)\s*&deg;C".to_string()
+}
+
+fn default_temperature_path() -> String {
+    "/temperature".to_string()
+}
+
+fn default_humidity_path() -> String {
+    "/humidity".to_string()
+}
+
+fn default_zigbee2mqtt_base_topic() -> String {
+    "zigbee2mqtt".to_string()
+}
+
+fn default_dht22_retries() -> u32 {
+    3
+}
+
+fn default_serial_baud_rate() -> u32 {
+    9600
+}
+
+fn default_serial_regex() -> String {
+    r"T=(?P<temperature>-?\d+(?:\.\d+)?)(?:\s+H=(?P<humidity>\d+(?:\.\d+)?))?".to_string()
+}
+
+fn default_modbus_baud_rate() -> u32 {
+    9600
+}
+
+fn default_modbus_unit_id() -> u8 {
+    1
+}
+
+fn default_modbus_scale() -> f64 {
+    0.1
+}
+
+fn default_snmp_community() -> String {
+    "public".to_string()
+}
+
+fn default_snmp_scale() -> f64 {
+    1.0
+}
+
+fn default_simulator_mean() -> f64 {
+    21.0
+}
+
+fn default_simulator_amplitude() -> f64 {
+    2.0
+}
+
+fn default_simulator_period() -> u64 {
+    24 * 3600
+}
+
+fn default_simulator_noise() -> f64 {
+    0.2
+}
+
+fn default_simulator_humidity_mean() -> f64 {
+    45.0
+}
+
+fn default_simulator_humidity_amplitude() -> f64 {
+    5.0
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "heater-monitor".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct InfluxConfig {
+    #[serde(default = "default_influx_measurement")]
+    pub measurement: String,
+    /// Added to every line next to the `sensor` tag.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        Self { measurement: default_influx_measurement(), tags: BTreeMap::new() }
+    }
+}
+
+fn default_influx_measurement() -> String {
+    "heater_monitor".to_string()
+}
+
+/// `[[webhooks]]` entry, posted every alert event as JSON.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WebhookConfig {
+    #[serde(serialize_with = "redacted_url")]
+    pub url: String,
+    /// JSON body with `{rule}`, `{sensor}`, `{metric}`, `{state}`, `{value}`, `{since}`,
+    /// `{timestamp}`, `{priority}` and `{message}` in its strings replaced, the event itself when unset.
+    #[serde(default)]
+    pub payload: Option<serde_json::Value>,
+    #[serde(default = "default_query_timeout")]
+    pub timeout: u64,
+    #[serde(default = "default_webhook_retries")]
+    pub retries: u32,
+    /// Seconds before the first retry, doubled on each further one.
+    #[serde(default = "default_webhook_retry_backoff")]
+    pub retry_backoff: u64,
+    #[serde(flatten)]
+    pub auth: HttpAuth,
+    #[serde(flatten)]
+    pub routing: Routing,
+}
+
+fn default_webhook_retries() -> u32 {
+    3
+}
+
+fn default_webhook_retry_backoff() -> u64 {
+    5
+}
+
+/// `[email]` section.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    /// Defaults to 587 with `starttls`, 465 with `tls` and 25 with `none`.
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default, serialize_with = "redacted_option")]
+    pub password: Option<String>,
+    /// Sender, e.g. `Heater <heater@example.com>`.
+    pub from: String,
+    pub to: Vec<String>,
+    /// Mail the alert events, otherwise only the daily summary is sent.
+    #[serde(default = "default_true")]
+    pub alerts: bool,
+    /// Local time of the daily summary in the `timezone`, e.g. `07:00`, none when unset.
+    #[serde(default)]
+    pub daily_summary: Option<String>,
+    #[serde(flatten)]
+    pub routing: Routing,
+}
+
+/// Encryption of the connection to the SMTP server.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    /// Plain connection upgraded with STARTTLS, required.
+    #[default]
+    Starttls,
+    /// TLS from the start, "SMTPS".
+    Tls,
+    /// Unencrypted, for a relay on the local network.
+    None,
+}
+
+impl EmailConfig {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.security {
+            SmtpSecurity::Starttls => 587,
+            SmtpSecurity::Tls => 465,
+            SmtpSecurity::None => 25,
+        })
+    }
+
+    pub fn daily_summary_time(&self) -> Option<NaiveTime> {
+        self.daily_summary.as_deref().and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// `[telegram]` section.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TelegramConfig {
+    /// From @BotFather, e.g. `123456:ABC-DEF...`.
+    #[serde(serialize_with = "redacted")]
+    pub bot_token: String,
+    /// Chats the alerts are sent to and the only ones whose commands are answered.
+    pub chat_ids: Vec<i64>,
+    /// Send the alert events, otherwise the bot only answers commands.
+    #[serde(default = "default_true")]
+    pub alerts: bool,
+    /// Bot API server, e.g. a self-hosted one.
+    #[serde(default = "default_telegram_api_url")]
+    pub api_url: String,
+    #[serde(flatten)]
+    pub routing: Routing,
+}
+
+fn default_telegram_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+/// `[ntfy]` section, publishing the alert events to a topic of ntfy.sh or a self-hosted server.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NtfyConfig {
+    #[serde(default = "default_ntfy_url", serialize_with = "redacted_url")]
+    pub url: String,
+    pub topic: String,
+    /// An access token as `headers = { Authorization = "Bearer tk_..." }` or `username`/`password`.
+    #[serde(flatten)]
+    pub auth: HttpAuth,
+    #[serde(flatten)]
+    pub routing: Routing,
+}
+
+fn default_ntfy_url() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+/// `[pushover]` section.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PushoverConfig {
+    /// API token of the application registered at pushover.net.
+    #[serde(serialize_with = "redacted")]
+    pub token: String,
+    /// User or group key the alerts are sent to.
+    #[serde(serialize_with = "redacted")]
+    pub user: String,
+    /// Only this device of the user, all of them when unset.
+    #[serde(default)]
+    pub device: Option<String>,
+    /// Seconds between repeats of an `urgent` alert until it is acknowledged in the app, at least 30.
+    #[serde(default = "default_pushover_retry")]
+    pub retry: u64,
+    /// Seconds an `urgent` alert keeps repeating, at most 10800.
+    #[serde(default = "default_pushover_expire")]
+    pub expire: u64,
+    #[serde(default = "default_pushover_api_url")]
+    pub api_url: String,
+    #[serde(flatten)]
+    pub routing: Routing,
+}
+
+fn default_pushover_retry() -> u64 {
+    60
+}
+
+fn default_pushover_expire() -> u64 {
+    3600
+}
+
+fn default_pushover_api_url() -> String {
+    "https://api.pushover.net".to_string()
+}
+
+/// `[server]` section.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ServerConfig {
+    /// Cross-origin requests to the API, refused without it.
+    #[serde(default)]
+    pub cors: Option<CorsConfig>,
+    /// Keys of which one is required by every endpoint but the dashboard page, none when empty.
+    #[serde(default, serialize_with = "redacted_items")]
+    pub api_keys: Vec<String>,
+    /// HTTP basic auth of the dashboard page and its assets.
+    #[serde(default)]
+    pub dashboard_auth: Option<DashboardAuth>,
+    /// Serve HTTPS on `port` instead of HTTP.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Log method, path, status and duration of every request.
+    #[serde(default)]
+    pub access_log: bool,
+    /// Add the message and causes of an error to its JSON body, which may reveal paths and
+    /// internals, for debugging.
+    #[serde(default)]
+    pub detailed_errors: bool,
+}
+
+/// `[server.tls]`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain, e.g. `/etc/letsencrypt/live/<host>/fullchain.pem`.
+    pub cert: String,
+    /// PEM private key.
+    pub key: String,
+    /// Port answering plain HTTP requests with a redirect to HTTPS, e.g. 80.
+    #[serde(default)]
+    pub redirect_http_port: Option<u16>,
+}
+
+/// `[server.dashboard_auth]`, separate from the `api_keys` of machines.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DashboardAuth {
+    pub username: String,
+    /// bcrypt hash, e.g. from `htpasswd -nbB "" <password> | cut -d: -f2`.
+    #[serde(serialize_with = "redacted")]
+    pub password_hash: String,
+}
+
+/// `[server.cors]`, lets a dashboard hosted elsewhere call the JSON API from the browser.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CorsConfig {
+    /// e.g. `["http://homeassistant.local:8123"]`, or `["*"]` for any origin.
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_methods")]
+    pub allowed_methods: Vec<String>,
+}
+
+fn default_cors_methods() -> Vec<String> {
+    vec!["GET".to_string()]
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
-    pub temp_sensor_url: String,
+    #[serde(default, serialize_with = "redacted_url_option")]
+    pub temp_sensor_url: Option<String>,
+    #[serde(default)]
+    pub temp_sensor_regex: Option<String>,
+    #[serde(default)]
+    pub sensors: Vec<SensorConfig>,
     #[allow(dead_code)]
     pub max_capacity: Option<usize>,
+    /// Approximate MB the samples of all series may take in memory, the oldest are evicted beyond.
+    #[serde(default)]
+    pub max_memory_mb: Option<u64>,
     pub sampling_interval: u64,
     pub port: u16,
-    pub listen_address: String,
+    /// IP addresses the server binds, e.g. `0.0.0.0` for every IPv4 interface or `::` for IPv6
+    /// too; one address or a list of them, e.g. `["0.0.0.0", "::"]`.
+    #[serde(deserialize_with = "one_or_many")]
+    pub listen_address: Vec<String>,
     pub log_path: String,
     #[allow(dead_code)]
     pub backlog: Option<String>,
+    /// Format of a new backlog file, an existing one is appended in its own format.
+    #[serde(default)]
+    pub backlog_format: BacklogFormat,
+    /// Compress closed backlog segments with zstd, on rotation and on startup.
+    #[serde(default)]
+    pub compress_backlog: bool,
+    /// Size in MB at which the backlog is rotated into a dated segment.
+    #[serde(default)]
+    pub backlog_max_size_mb: Option<u64>,
+    /// Hours after its first sample the backlog is rotated into a dated segment.
+    #[serde(default)]
+    pub backlog_max_age_hours: Option<u64>,
+    /// File the samples in memory are written to on shutdown and restored from on startup.
+    #[serde(default)]
+    pub snapshot: Option<String>,
+    /// Seconds records are buffered before they are written to the backlog.
+    #[serde(default)]
+    pub backlog_flush_interval: Option<u64>,
+    /// Seconds between syncs of the backlog to the disk.
+    #[serde(default)]
+    pub backlog_fsync_interval: Option<u64>,
+    /// Days samples are kept in memory and in closed backlog segments.
+    #[serde(default)]
+    pub retention_days: Option<u64>,
+    #[serde(default)]
+    pub downsampling: Option<Downsampling>,
     pub averaging_interval: u32,
+    /// Empty averaging buckets that repeat the previous average before `/temps` reports a gap.
+    #[serde(default = "default_carry_forward_buckets")]
+    pub carry_forward_buckets: usize,
+    /// Values `/temps` returns at most without an explicit `interval`, longer ranges are
+    /// averaged over a coarser one.
+    #[serde(default = "default_max_points")]
+    pub max_points: usize,
+    /// Seconds without a new sample after which `/health` reports a sensor as stale.
+    #[serde(default = "default_stale_after")]
+    pub stale_after: u64,
     pub pid_file: String,
+    #[serde(default)]
+    pub mqtt: Option<MqttConfig>,
+    /// Browse mDNS for ESPHome, Shelly and Tasmota devices, listed by `/api/discovery`.
+    #[serde(default)]
+    pub discovery: bool,
+    /// User agent of the requests to HTTP sensors.
+    #[serde(default)]
+    pub http_user_agent: Option<String>,
+    /// Sensor whose series is returned next to the requested one by `/temps`.
+    #[serde(default)]
+    pub outdoor_sensor: Option<String>,
+    /// Bearer token required by `POST /api/measurements`.
+    #[serde(default, serialize_with = "redacted_option")]
+    pub push_token: Option<String>,
+    /// JSON-lines file the annotations of `POST /api/annotations` are kept in.
+    #[serde(default)]
+    pub annotations: Option<String>,
+    /// Bearer token required by `DELETE /api/samples`, `POST /api/annotations`, `/api/admin/*`
+    /// and `/api/config`, which are refused without it.
+    #[serde(default, serialize_with = "redacted_option")]
+    pub admin_token: Option<String>,
+    /// Base URL of another instance whose newer samples are copied at startup, e.g. `http://other-host:3000`.
+    #[serde(default, serialize_with = "redacted_url_option")]
+    pub backfill_from: Option<String>,
+    /// IANA time zone the days of `/daily` start in, e.g. `Europe/Prague`, defaults to the system one.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Line protocol of `/export?format=influx`.
+    #[serde(default)]
+    pub influx: InfluxConfig,
+    /// Thresholds checked against every new sample, `[[alerts]]` entries.
+    #[serde(default)]
+    pub alerts: Vec<AlertRule>,
+    /// Alert on sensors that stopped delivering samples.
+    #[serde(default)]
+    pub stale_alert: Option<StaleAlert>,
+    /// URLs the alert events are posted to.
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    /// SMTP server mailing the alert events and a daily summary.
+    #[serde(default)]
+    pub email: Option<EmailConfig>,
+    /// Telegram bot sending the alert events and answering commands.
+    #[serde(default)]
+    pub telegram: Option<TelegramConfig>,
+    /// ntfy topic the alert events are published to.
+    #[serde(default)]
+    pub ntfy: Option<NtfyConfig>,
+    /// Pushover user the alert events are pushed to.
+    #[serde(default)]
+    pub pushover: Option<PushoverConfig>,
+    #[serde(default)]
+    pub server: ServerConfig,
 }
 
 impl Config {
     pub fn read(path: PathBuf) -> Result<Config, anyhow::Error> {
         let contents = fs::read_to_string(path)?;
-        Ok(toml::from_str::<Config>(&contents)?)
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Config, anyhow::Error> {
+        let config = toml::from_str::<Config>(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Takes the settings `/api/admin/reload` applies from a re-read `config`, the others keep
+    /// the values the daemon was started with.
+    pub fn reload(&mut self, config: &Config) {
+        self.push_token = config.push_token.clone();
+        self.admin_token = config.admin_token.clone();
+        self.server.api_keys = config.server.api_keys.clone();
+        self.server.dashboard_auth = config.server.dashboard_auth.clone();
+        self.server.detailed_errors = config.server.detailed_errors;
+        self.outdoor_sensor = config.outdoor_sensor.clone();
+        self.stale_after = config.stale_after;
+        self.max_capacity = config.max_capacity;
+        self.max_memory_mb = config.max_memory_mb;
+        self.retention_days = config.retention_days;
+        self.downsampling = config.downsampling.clone();
+        self.averaging_interval = config.averaging_interval;
+        self.carry_forward_buckets = config.carry_forward_buckets;
+        self.max_points = config.max_points;
+    }
+
+    /// All sensors to poll; the legacy `temp_sensor_url` is listed first as the default series.
+    pub fn sensors(&self) -> Vec<SensorConfig> {
+        let mut sensors = Vec::new();
+        if let Some(url) = &self.temp_sensor_url {
+            sensors.push(SensorConfig {
+                name: DEFAULT_SERIES.to_string(),
+                timeout: default_query_timeout(),
+                query_retries: 0,
+                retry_backoff: default_retry_backoff(),
+                calibration: Calibration::default(),
+                filter: FilterConfig::default(),
+                oversampling: default_oversampling(),
+                sampling_interval: None,
+                source: SensorSource::Http {
+                    url: url.clone(),
+                    regex: self.temp_sensor_regex.clone().unwrap_or_else(default_scrape_regex),
+                    auth: HttpAuth::default(),
+                },
+            });
+        }
+        sensors.extend(self.sensors.iter().cloned());
+        sensors
+    }
+
+    pub fn user_agent(&self) -> String {
+        self.http_user_agent.clone()
+            .unwrap_or_else(|| format!("heater-monitor/{}", env!("CARGO_PKG_VERSION")))
+    }
+
+    pub fn backlog_rotation(&self) -> Rotation {
+        Rotation {
+            max_size: self.backlog_max_size_mb.map(|mb| mb.saturating_mul(1024 * 1024)),
+            max_age: self.backlog_max_age_hours.map(|hours| Duration::from_secs(hours * 3600)),
+            compress: self.compress_backlog,
+        }
+    }
+
+    pub fn backlog_flushing(&self) -> Flushing {
+        Flushing {
+            flush_interval: self.backlog_flush_interval.map(Duration::from_secs),
+            fsync_interval: self.backlog_fsync_interval.map(Duration::from_secs),
+        }
+    }
+
+    /// Addresses and port of the HTTP server, one listener each.
+    pub fn listen_addrs(&self) -> Result<Vec<SocketAddr>, anyhow::Error> {
+        if self.listen_address.is_empty() {
+            bail!("listen_address needs at least one address");
+        }
+        let mut addrs: Vec<SocketAddr> = Vec::new();
+        for address in &self.listen_address {
+            let ip = address.trim().parse::<IpAddr>()
+                .map_err(|_| anyhow!("listen_address {:?} is not an IP address, use e.g. \"0.0.0.0\" for all interfaces or \"127.0.0.1\" for this host only", address))?;
+            let addr = SocketAddr::new(ip, self.port);
+            if addrs.contains(&addr) {
+                bail!("duplicate listen_address {:?}", address);
+            }
+            addrs.push(addr);
+        }
+        Ok(addrs)
+    }
+
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.max_memory_mb.map(|mb| (mb * 1024 * 1024) as usize)
+    }
+
+    pub fn time_zone(&self) -> Option<Tz> {
+        self.timezone.as_deref().and_then(|name| name.parse().ok())
+    }
+
+    pub fn retention(&self) -> Option<Duration> {
+        self.retention_days.map(|days| Duration::from_secs(days * 86400))
+    }
+
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        let sensors = self.sensors();
+        if sensors.is_empty() {
+            bail!("no sensor configured, set temp_sensor_url or add a [[sensors]] entry");
+        }
+        if self.backlog_max_size_mb == Some(0) || self.backlog_max_age_hours == Some(0) {
+            bail!("backlog_max_size_mb and backlog_max_age_hours must be at least 1");
+        }
+        if self.backlog_max_age_hours.is_some_and(|hours| hours > MAX_DAYS * 24) {
+            bail!("backlog_max_age_hours must be at most {}", MAX_DAYS * 24);
+        }
+        if self.influx.measurement.is_empty() || self.influx.tags.iter().any(|(key, value)| key.is_empty() || value.is_empty()) {
+            bail!("influx measurement and tags must not be empty");
+        }
+        self.listen_addrs()?;
+        if self.retention_days.is_some_and(|days| !(1..=MAX_DAYS).contains(&days)) {
+            bail!("retention_days must be between 1 and {}", MAX_DAYS);
+        }
+        if self.stale_after == 0 {
+            bail!("stale_after must be at least 1 second");
+        }
+        if self.max_memory_mb == Some(0) {
+            bail!("max_memory_mb must be at least 1");
+        }
+        if !(MIN_POINTS..=MAX_POINTS).contains(&self.max_points) {
+            bail!("max_points must be between {} and {}", MIN_POINTS, MAX_POINTS);
+        }
+        if let Some(downsampling) = &self.downsampling {
+            if downsampling.raw_days == 0 || downsampling.five_minute_days < downsampling.raw_days {
+                bail!("downsampling needs raw_days of at least 1 and five_minute_days not below raw_days");
+            }
+            if downsampling.five_minute_days > MAX_DAYS {
+                bail!("downsampling five_minute_days must be at most {}", MAX_DAYS);
+            }
+        }
+        if let Some(peer) = &self.backfill_from {
+            if !peer.starts_with("http://") && !peer.starts_with("https://") {
+                bail!("backfill_from {:?} must be an http:// or https:// URL", peer);
+            }
+        }
+        for webhook in &self.webhooks {
+            if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+                bail!("webhook {:?} must be an http:// or https:// URL", redact_url(&webhook.url));
+            }
+            if webhook.timeout == 0 {
+                bail!("webhook {:?} timeout must be at least 1 second", redact_url(&webhook.url));
+            }
+            if let Some(name) = webhook.auth.invalid_header() {
+                bail!("webhook {:?} has invalid header {:?}", redact_url(&webhook.url), name);
+            }
+            if webhook.auth.password.is_some() && webhook.auth.username.is_none() {
+                bail!("webhook {:?} has a password but no username", redact_url(&webhook.url));
+            }
+        }
+        if let Some(email) = &self.email {
+            if email.smtp_host.trim().is_empty() {
+                bail!("email.smtp_host must not be empty");
+            }
+            for address in std::iter::once(&email.from).chain(&email.to) {
+                if address.parse::<lettre::message::Mailbox>().is_err() {
+                    bail!("email address {:?} is invalid", address);
+                }
+            }
+            if email.to.is_empty() {
+                bail!("email needs at least one recipient in to");
+            }
+            if email.password.is_some() && email.username.is_none() {
+                bail!("email has a password but no username");
+            }
+            if email.daily_summary.is_some() && email.daily_summary_time().is_none() {
+                bail!("email.daily_summary {:?} must be a time like 07:00", email.daily_summary.as_deref().unwrap_or_default());
+            }
+        }
+        if let Some(telegram) = &self.telegram {
+            if telegram.bot_token.trim().is_empty() || telegram.bot_token.contains('/') {
+                bail!("telegram.bot_token must be the token of @BotFather, e.g. 123456:ABC-DEF");
+            }
+            if telegram.chat_ids.is_empty() {
+                bail!("telegram needs at least one chat in chat_ids");
+            }
+            if !telegram.api_url.starts_with("http://") && !telegram.api_url.starts_with("https://") {
+                bail!("telegram.api_url {:?} must be an http:// or https:// URL", telegram.api_url);
+            }
+        }
+        if let Some(ntfy) = &self.ntfy {
+            if !ntfy.url.starts_with("http://") && !ntfy.url.starts_with("https://") {
+                bail!("ntfy.url {:?} must be an http:// or https:// URL", redact_url(&ntfy.url));
+            }
+            if ntfy.topic.is_empty() || !ntfy.topic.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                bail!("ntfy.topic {:?} must be letters, digits, - and _", ntfy.topic);
+            }
+            if let Some(name) = ntfy.auth.invalid_header() {
+                bail!("ntfy has invalid header {:?}", name);
+            }
+            if ntfy.auth.password.is_some() && ntfy.auth.username.is_none() {
+                bail!("ntfy has a password but no username");
+            }
+        }
+        if let Some(pushover) = &self.pushover {
+            if pushover.token.trim().is_empty() || pushover.user.trim().is_empty() {
+                bail!("pushover needs the token of the application and the user key");
+            }
+            if pushover.retry < 30 || !(1..=10800).contains(&pushover.expire) {
+                bail!("pushover needs a retry of at least 30 seconds and an expire of at most 10800");
+            }
+            if !pushover.api_url.starts_with("http://") && !pushover.api_url.starts_with("https://") {
+                bail!("pushover.api_url {:?} must be an http:// or https:// URL", pushover.api_url);
+            }
+        }
+        if let Some(name) = &self.timezone {
+            if name.parse::<Tz>().is_err() {
+                bail!("timezone {:?} is not an IANA time zone, e.g. Europe/Prague", name);
+            }
+        }
+        if self.server.api_keys.iter().any(|key| key.trim().is_empty()) {
+            bail!("server.api_keys must not be empty");
+        }
+        if let Some(auth) = &self.server.dashboard_auth {
+            if auth.username.is_empty() || auth.username.contains(':') {
+                bail!("server.dashboard_auth username must be non-empty without ':'");
+            }
+            if auth.password_hash.parse::<bcrypt::HashParts>().is_err() {
+                bail!("server.dashboard_auth password_hash is not a bcrypt hash");
+            }
+        }
+        if let Some(tls) = &self.server.tls {
+            if tls.redirect_http_port == Some(self.port) {
+                bail!("server.tls redirect_http_port must differ from port {}", self.port);
+            }
+        }
+        if let Some(cors) = &self.server.cors {
+            if cors.allowed_origins.is_empty() {
+                bail!("server.cors needs at least one allowed origin");
+            }
+            for origin in &cors.allowed_origins {
+                let valid = origin.split_once("://")
+                    .is_some_and(|(scheme, host)| (scheme == "http" || scheme == "https") && !host.is_empty() && !host.contains('/'));
+                if origin != "*" && !valid {
+                    bail!("server.cors origin {:?} must be \"*\" or a scheme and host without path, e.g. http://homeassistant.local:8123", origin);
+                }
+            }
+            for method in &cors.allowed_methods {
+                if !["GET", "POST", "PUT", "DELETE", "PATCH", "HEAD", "OPTIONS"].contains(&method.to_uppercase().as_str()) {
+                    bail!("server.cors has invalid method {:?}", method);
+                }
+            }
+        }
+        for (i, rule) in self.alerts.iter().enumerate() {
+            if rule.name.trim().is_empty() {
+                bail!("alert rule needs a name");
+            }
+            if self.alerts[..i].iter().any(|other| other.name == rule.name) {
+                bail!("duplicate alert rule {:?}", rule.name);
+            }
+            if rule.below.is_none() && rule.above.is_none() && rule.drop.is_none() && rule.rise.is_none() && rule.projected_below.is_none()
+                && rule.falling_buckets.is_none() {
+                bail!("alert rule {:?} needs below, above, drop, rise, projected_below or falling_buckets", rule.name);
+            }
+            if rule.command.as_ref().is_some_and(|command| command.is_empty()) {
+                bail!("alert rule {:?} has an empty command", rule.name);
+            }
+            if rule.falling_buckets == Some(0) || rule.bucket == 0 {
+                bail!("alert rule {:?} falling_buckets and bucket must be at least 1", rule.name);
+            }
+            if rule.heating_hours.as_deref().is_some_and(|range| parse_time_range(range).is_none()) {
+                bail!("alert rule {:?} heating_hours must be a range like 06:00-22:00", rule.name);
+            }
+            if rule.projected_below.is_some() && rule.horizon == 0 {
+                bail!("alert rule {:?} horizon must be at least 1 second", rule.name);
+            }
+            if rule.drop.into_iter().chain(rule.rise).any(|change| !change.is_finite() || change <= rule.hysteresis) {
+                bail!("alert rule {:?} drop and rise must be over its hysteresis", rule.name);
+            }
+            if rule.window == 0 {
+                bail!("alert rule {:?} window must be at least 1 second", rule.name);
+            }
+            if !rule.hysteresis.is_finite() || rule.hysteresis < 0.0 {
+                bail!("alert rule {:?} hysteresis must not be negative", rule.name);
+            }
+            if let (Some(below), Some(above)) = (rule.below, rule.above) {
+                if below + rule.hysteresis > above - rule.hysteresis {
+                    bail!("alert rule {:?} can never clear, below + hysteresis is over above - hysteresis", rule.name);
+                }
+            }
+            if rule.metric != TEMPERATURE && !is_valid_metric_name(&rule.metric) {
+                bail!("alert rule {:?} has invalid metric name {:?}", rule.name, rule.metric);
+            }
+            if let Some(sensor) = &rule.sensor {
+                if !sensors.iter().any(|configured| &configured.name == sensor) {
+                    bail!("alert rule {:?} watches {:?}, which is not a configured sensor", rule.name, sensor);
+                }
+            }
+        }
+        let routings = self.webhooks.iter().map(|webhook| ("webhooks", &webhook.routing))
+            .chain(self.email.iter().map(|email| ("email", &email.routing)))
+            .chain(self.telegram.iter().map(|telegram| ("telegram", &telegram.routing)))
+            .chain(self.ntfy.iter().map(|ntfy| ("ntfy", &ntfy.routing)))
+            .chain(self.pushover.iter().map(|pushover| ("pushover", &pushover.routing)));
+        for (channel, routing) in routings {
+            if routing.quiet_hours.is_some() && routing.quiet_hours().is_none() {
+                bail!("{} quiet_hours {:?} must be a range like 22:00-07:00", channel, routing.quiet_hours.as_deref().unwrap_or_default());
+            }
+        }
+        if let Some(stale_alert) = &self.stale_alert {
+            if stale_alert.intervals == 0 {
+                bail!("stale_alert.intervals must be at least 1");
+            }
+        }
+        if let Some(outdoor) = &self.outdoor_sensor {
+            if !sensors.iter().any(|sensor| &sensor.name == outdoor) {
+                bail!("outdoor_sensor {:?} is not a configured sensor", outdoor);
+            }
+        }
+        for (i, sensor) in sensors.iter().enumerate() {
+            if sensor.name.is_empty() || sensor.name.contains(char::is_whitespace) {
+                bail!("invalid sensor name {:?}, must be non-empty without whitespace", sensor.name);
+            }
+            if sensors[..i].iter().any(|other| other.name == sensor.name) {
+                bail!("duplicate sensor name {:?}", sensor.name);
+            }
+            if matches!(sensor.source, SensorSource::Mqtt { .. } | SensorSource::Zigbee2mqtt { .. }) && self.mqtt.is_none() {
+                bail!("sensor {:?} uses mqtt but no [mqtt] section is configured", sensor.name);
+            }
+            if let SensorSource::Json { metrics, .. }
+                | SensorSource::Exec { metrics, .. }
+                | SensorSource::Mqtt { metrics, .. }
+                | SensorSource::Zigbee2mqtt { metrics, .. } = &sensor.source {
+                if let Some(metric) = metrics.keys().find(|metric| !is_valid_metric_name(metric)) {
+                    bail!("sensor {:?} has invalid metric name {:?}", sensor.name, metric);
+                }
+            }
+            if sensor.oversampling == 0 {
+                bail!("sensor {:?} oversampling must be at least 1", sensor.name);
+            }
+            if sensor.sampling_interval == Some(0) {
+                bail!("sensor {:?} sampling_interval must be at least 1 second", sensor.name);
+            }
+            if sensor.timeout == 0 {
+                bail!("sensor {:?} timeout must be at least 1 second", sensor.name);
+            }
+            if let SensorSource::Http { regex, .. } | SensorSource::Serial { regex, .. } = &sensor.source {
+                let re = Regex::new(regex)
+                    .map_err(|e| anyhow!("sensor {:?} has invalid regex: {}", sensor.name, e))?;
+                if !re.capture_names().any(|name| name == Some("temperature")) {
+                    bail!("sensor {:?} regex has no (?P<temperature>...) group", sensor.name);
+                }
+            }
+            if let SensorSource::Http { auth, .. }
+                | SensorSource::Json { auth, .. }
+                | SensorSource::Prometheus { auth, .. }
+                | SensorSource::Tasmota { auth, .. } = &sensor.source {
+                if let Some(name) = auth.invalid_header() {
+                    bail!("sensor {:?} has invalid header {:?}", sensor.name, name);
+                }
+                if auth.password.is_some() && auth.username.is_none() {
+                    bail!("sensor {:?} has a password but no username", sensor.name);
+                }
+            }
+            if let SensorSource::Exec { command, regex, .. } = &sensor.source {
+                if command.is_empty() {
+                    bail!("sensor {:?} has an empty command", sensor.name);
+                }
+                if let Some(regex) = regex {
+                    let re = Regex::new(regex)
+                        .map_err(|e| anyhow!("sensor {:?} has invalid regex: {}", sensor.name, e))?;
+                    if !re.capture_names().any(|name| name == Some("temperature")) {
+                        bail!("sensor {:?} regex has no (?P<temperature>...) group", sensor.name);
+                    }
+                }
+            }
+            if matches!(sensor.source, SensorSource::Push | SensorSource::Shelly { .. })
+                && self.push_token.as_deref().unwrap_or_default().is_empty() {
+                bail!("sensor {:?} accepts pushed measurements but no push_token is configured", sensor.name);
+            }
+            if let SensorSource::Ble { mac } = &sensor.source {
+                if !cfg!(feature = "ble") {
+                    bail!("sensor {:?} needs BLE support, build with --features ble", sensor.name);
+                }
+                let valid = mac.split(':').count() == 6
+                    && mac.split(':').all(|part| part.len() == 2 && u8::from_str_radix(part, 16).is_ok());
+                if !valid {
+                    bail!("sensor {:?} has invalid MAC address {:?}", sensor.name, mac);
+                }
+            }
+            if let SensorSource::Snmp { oid, humidity_oid, .. } = &sensor.source {
+                for oid in std::iter::once(oid).chain(humidity_oid) {
+                    if snmp2::Oid::from_str(oid).is_err() {
+                        bail!("sensor {:?} has invalid OID {:?}", sensor.name, oid);
+                    }
+                }
+            }
+            if let SensorSource::Simulator(simulator) = &sensor.source {
+                if simulator.period == 0 || simulator.noise.is_nan() || simulator.noise < 0.0 {
+                    bail!("sensor {:?} needs a positive period and a non-negative noise", sensor.name);
+                }
+            }
+            if let SensorSource::Modbus(modbus) = &sensor.source {
+                match (&modbus.address, &modbus.device) {
+                    (Some(address), None) => {
+                        if address.parse::<SocketAddr>().is_err() {
+                            bail!("sensor {:?} has invalid modbus address {:?}, expected ip:port", sensor.name, address);
+                        }
+                    }
+                    (None, Some(_)) => {}
+                    _ => bail!("sensor {:?} must set exactly one of modbus address or device", sensor.name),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Stand-in for a secret in the configuration shown by `/api/config`.
+pub const REDACTED: &str = "<redacted>";
+
+/// Longest `retention_days`, `[downsampling]` and `backlog_max_age_hours` span accepted, a
+/// century, far from overflowing a time when subtracted from now.
+const MAX_DAYS: u64 = 36500;
+
+/// `url` with the password of its user info replaced by `REDACTED`, e.g.
+/// `http://admin:<redacted>@192.168.6.75/`.
+pub fn redact_url(url: &str) -> String {
+    let Some(scheme_end) = url.find("://").map(|i| i + 3) else {
+        return url.to_string();
+    };
+    let authority_end = url[scheme_end..].find(['/', '?', '#']).map_or(url.len(), |i| scheme_end + i);
+    let Some(at) = url[scheme_end..authority_end].rfind('@').map(|i| scheme_end + i) else {
+        return url.to_string();
+    };
+    match url[scheme_end..at].find(':') {
+        Some(colon) => format!("{}{}{}", &url[..scheme_end + colon + 1], REDACTED, &url[at..]),
+        None => url.to_string(),
+    }
+}
+
+/// A single string or a list of strings.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+fn redacted<T, S: Serializer>(_: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(REDACTED)
+}
+
+fn redacted_option<T, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(_) => serializer.serialize_some(REDACTED),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn redacted_items<S: Serializer>(values: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(values.iter().map(|_| REDACTED))
+}
+
+fn redacted_values<S: Serializer>(map: &BTreeMap<String, String>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.keys().map(|key| (key, REDACTED)))
+}
+
+fn redacted_url<S: Serializer>(url: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&redact_url(url))
+}
+
+fn redacted_url_option<S: Serializer>(url: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    match url {
+        Some(url) => serializer.serialize_some(&redact_url(url)),
+        None => serializer.serialize_none(),
     }
 }
@@ -0,0 +1,36 @@
+use crate::storage::{Sample, HUMIDITY};
+
+pub const DEW_POINT: &str = "dew_point";
+
+/// A metric computed on query from the stored ones of a reading.
+pub struct DerivedMetric {
+    pub name: &'static str,
+    pub compute: fn(&Sample) -> Option<f64>,
+}
+
+pub const DERIVED_METRICS: &[DerivedMetric] = &[
+    DerivedMetric { name: DEW_POINT, compute: sample_dew_point },
+];
+
+/// Value of the derived `metric` of `sample`, `None` when it is not a derived metric or the
+/// reading lacks its inputs.
+pub fn derived_value(sample: &Sample, metric: &str) -> Option<f64> {
+    DERIVED_METRICS.iter()
+        .find(|derived| derived.name == metric)
+        .and_then(|derived| (derived.compute)(sample))
+}
+
+fn sample_dew_point(sample: &Sample) -> Option<f64> {
+    dew_point(sample.temperature, *sample.metrics.get(HUMIDITY)?)
+}
+
+/// Dew point in °C by the Magnus formula, valid for -45 to 60 °C.
+pub fn dew_point(temperature: f64, humidity: f64) -> Option<f64> {
+    const A: f64 = 17.62;
+    const B: f64 = 243.12;
+    if !(humidity > 0.0 && humidity <= 100.0 && temperature.is_finite()) {
+        return None;
+    }
+    let gamma = (humidity / 100.0).ln() + A * temperature / (B + temperature);
+    Some(B * gamma / (A - gamma))
+}
@@ -0,0 +1,103 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use log::debug;
+use rppal::gpio::{Bias, Gpio, IoPin, Level, Mode};
+use crate::app_error::AppError;
+use crate::temp_sensor::Measurement;
+
+// The sensor needs at least 2 seconds between two readings.
+const RETRY_DELAY: Duration = Duration::from_millis(2100);
+const PULSE_TIMEOUT: Duration = Duration::from_micros(200);
+// A "0" bit is a ~27us high pulse, a "1" bit ~70us.
+const ONE_THRESHOLD: Duration = Duration::from_micros(48);
+
+/// DHT22/AM2302 connected directly to a GPIO pin (BCM numbering).
+pub struct Dht22Sensor {
+    pin: u8,
+    retries: u32,
+}
+
+impl Dht22Sensor {
+    pub fn new(pin: u8, retries: u32) -> Self {
+        Self { pin, retries }
+    }
+
+    pub async fn query(&self) -> Result<Measurement, AppError> {
+        let (pin, retries) = (self.pin, self.retries);
+        tokio::task::spawn_blocking(move || read_with_retries(pin, retries))
+            .await
+            .map_err(|e| AppError::InternalError(format!("DHT22 reader task failed: {}", e)))?
+    }
+}
+
+fn read_with_retries(pin: u8, retries: u32) -> Result<Measurement, AppError> {
+    let gpio = Gpio::new()
+        .map_err(|e| AppError::TemperatureSensorError(format!("GPIO unavailable: {}", e)))?;
+    let mut pin = gpio.get(pin)
+        .map_err(|e| AppError::TemperatureSensorError(format!("GPIO pin {} unavailable: {}", pin, e)))?
+        .into_io(Mode::Output);
+
+    let mut attempt = 0;
+    loop {
+        match read_once(&mut pin) {
+            Ok(measurement) => return Ok(measurement),
+            Err(e) if attempt < retries => {
+                debug!("DHT22 read attempt {} failed: {}", attempt + 1, e);
+                attempt += 1;
+                thread::sleep(RETRY_DELAY);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn read_once(pin: &mut IoPin) -> Result<Measurement, AppError> {
+    // start signal: hold the line low for >1ms, then release it to the pull-up
+    pin.set_mode(Mode::Output);
+    pin.set_low();
+    thread::sleep(Duration::from_millis(2));
+    pin.set_high();
+    pin.set_mode(Mode::Input);
+    pin.set_bias(Bias::PullUp);
+
+    // sensor response: ~80us low followed by ~80us high
+    wait_for(pin, Level::Low)?;
+    wait_for(pin, Level::High)?;
+    wait_for(pin, Level::Low)?;
+
+    let mut data = [0u8; 5];
+    for bit in 0..40 {
+        wait_for(pin, Level::High)?;
+        let high = wait_for(pin, Level::Low)?;
+        if high > ONE_THRESHOLD {
+            data[bit / 8] |= 1 << (7 - bit % 8);
+        }
+    }
+
+    decode(&data)
+}
+
+/// Busy-waits until the pin reaches `level`, returns how long it took.
+fn wait_for(pin: &IoPin, level: Level) -> Result<Duration, AppError> {
+    let start = Instant::now();
+    while pin.read() != level {
+        if start.elapsed() > PULSE_TIMEOUT {
+            return Err(AppError::TemperatureSensorError("DHT22 timed out".to_string()));
+        }
+    }
+    Ok(start.elapsed())
+}
+
+fn decode(data: &[u8; 5]) -> Result<Measurement, AppError> {
+    let checksum = data[..4].iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+    if checksum != data[4] {
+        return Err(AppError::TemperatureSensorError("DHT22 checksum mismatch".to_string()));
+    }
+
+    let humidity = u16::from_be_bytes([data[0], data[1]]) as f64 / 10.0;
+    // the temperature is sign-magnitude, the highest bit marks values below zero
+    let raw_temperature = u16::from_be_bytes([data[2] & 0x7f, data[3]]) as f64 / 10.0;
+    let temperature = if data[2] & 0x80 != 0 { -raw_temperature } else { raw_temperature };
+
+    Ok(Measurement::new(temperature, humidity))
+}
@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use log::{error, info};
+use mdns_sd::{Receiver, ServiceDaemon, ServiceEvent};
+use serde::Serialize;
+
+/// Service types browsed for sensors, Shelly Gen1 and Tasmota only announce plain `_http`.
+const SERVICE_TYPES: [&str; 3] = ["_esphomelib._tcp.local.", "_shelly._tcp.local.", "_http._tcp.local."];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceKind {
+    Esphome,
+    Shelly,
+    Tasmota,
+}
+
+/// A device found by mDNS, not polled until it is added to `[[sensors]]`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredDevice {
+    pub name: String,
+    pub kind: DeviceKind,
+    pub host: String,
+    pub address: Option<String>,
+    pub port: u16,
+    /// `[[sensors]]` entry to start from.
+    pub suggested_config: String,
+}
+
+impl DiscoveredDevice {
+    pub fn new(name: &str, kind: DeviceKind, host: &str, address: Option<String>, port: u16) -> Self {
+        let target = address.as_deref().unwrap_or(host.trim_end_matches('.'));
+        let url = if port == 80 { format!("http://{}", target) } else { format!("http://{}:{}", target, port) };
+        let source = match kind {
+            DeviceKind::Tasmota => format!("type = \"tasmota\"\nurl = \"{}\"", url),
+            DeviceKind::Shelly => format!("type = \"shelly\"\ndevice = \"{}\"", name),
+            // the web_server component serves every entity as {"id": ..., "value": ...}
+            DeviceKind::Esphome => format!("type = \"json\"\nurl = \"{}/sensor/temperature\"\ntemperature_path = \"/value\"", url),
+        };
+        Self {
+            suggested_config: format!("[[sensors]]\nname = \"{}\"\n{}\n", name.to_lowercase(), source),
+            name: name.to_string(),
+            kind,
+            host: host.to_string(),
+            address,
+            port,
+        }
+    }
+}
+
+/// Kind of the device announcing `instance` under `service_type`, if it is a known sensor.
+pub fn classify(service_type: &str, instance: &str) -> Option<DeviceKind> {
+    let instance = instance.to_lowercase();
+    match service_type {
+        "_esphomelib._tcp.local." => Some(DeviceKind::Esphome),
+        "_shelly._tcp.local." => Some(DeviceKind::Shelly),
+        "_http._tcp.local." if instance.starts_with("shelly") => Some(DeviceKind::Shelly),
+        "_http._tcp.local." if instance.starts_with("tasmota") => Some(DeviceKind::Tasmota),
+        _ => None,
+    }
+}
+
+/// Devices found so far by their mDNS full name.
+#[derive(Debug, Default)]
+pub struct Discovered {
+    devices: BTreeMap<String, DiscoveredDevice>,
+}
+
+impl Discovered {
+    pub fn insert(&mut self, fullname: &str, device: DiscoveredDevice) {
+        self.devices.insert(fullname.to_string(), device);
+    }
+
+    pub fn remove(&mut self, fullname: &str) {
+        self.devices.remove(fullname);
+    }
+
+    pub fn devices(&self) -> impl Iterator<Item = &DiscoveredDevice> {
+        self.devices.values()
+    }
+}
+
+pub fn run_discovery(discovered: Arc<Mutex<Discovered>>) -> Result<(), mdns_sd::Error> {
+    let mdns = ServiceDaemon::new()?;
+    for service_type in SERVICE_TYPES {
+        let receiver = mdns.browse(service_type)?;
+        tokio::spawn(browse(mdns.clone(), receiver, discovered.clone()));
+    }
+    Ok(())
+}
+
+async fn browse(_mdns: ServiceDaemon, receiver: Receiver<ServiceEvent>, discovered: Arc<Mutex<Discovered>>) {
+    while let Ok(event) = receiver.recv_async().await {
+        match event {
+            ServiceEvent::ServiceResolved(service) => {
+                let instance = service.fullname
+                    .strip_suffix(&format!(".{}", service.ty_domain))
+                    .unwrap_or(&service.fullname);
+                let Some(kind) = classify(&service.ty_domain, instance) else {
+                    continue;
+                };
+                let address = service.addresses.iter()
+                    .find(|address| address.is_ipv4())
+                    .map(|address| address.to_ip_addr().to_string());
+                info!("Discovered {:?} device {} at {}", kind, instance, service.host);
+                let device = DiscoveredDevice::new(instance, kind, &service.host, address, service.port);
+                match discovered.lock() {
+                    Ok(mut discovered) => discovered.insert(&service.fullname, device),
+                    Err(_) => error!("failed to lock discovered devices"),
+                }
+            }
+            ServiceEvent::ServiceRemoved(_, fullname) => {
+                if let Ok(mut discovered) = discovered.lock() {
+                    discovered.remove(&fullname);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+use crate::backlog::Record;
+use crate::storage::Sample;
+
+/// Samples taken before `before` are averaged into `bucket` long rollups.
+#[derive(Debug, Clone, Copy)]
+pub struct Tier {
+    pub before: SystemTime,
+    pub bucket: Duration,
+}
+
+impl Tier {
+    /// `before` is aligned down to a bucket boundary, so no bucket straddles two tiers.
+    pub fn new(before: SystemTime, bucket: Duration) -> Self {
+        Self { before: align(before, bucket), bucket }
+    }
+}
+
+fn align(time: SystemTime, bucket: Duration) -> SystemTime {
+    let secs = time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs - secs % bucket.as_secs().max(1))
+}
+
+/// Replaces the samples of one series, oldest first, by averages over the bucket of the
+/// coarsest tier they fall in. Rollups at least as coarse as their tier are kept as they are.
+pub fn roll_up(samples: Vec<Sample>, tiers: &[Tier]) -> Vec<Sample> {
+    let mut rolled = Vec::with_capacity(samples.len());
+    let mut bucket: Option<Bucket> = None;
+    for sample in samples {
+        let tier = tiers.iter()
+            .filter(|tier| sample.timestamp < tier.before)
+            .max_by_key(|tier| tier.bucket);
+        let Some(tier) = tier.filter(|tier| sample.span.is_none_or(|span| span < tier.bucket)) else {
+            rolled.extend(bucket.take().map(Bucket::into_sample));
+            rolled.push(sample);
+            continue;
+        };
+
+        let start = align(sample.timestamp, tier.bucket);
+        if bucket.as_ref().is_some_and(|bucket| bucket.start != start || bucket.span != tier.bucket) {
+            rolled.extend(bucket.take().map(Bucket::into_sample));
+        }
+        bucket.get_or_insert_with(|| Bucket::new(start, tier.bucket)).add(&sample);
+    }
+    rolled.extend(bucket.map(Bucket::into_sample));
+    rolled
+}
+
+/// `roll_up` of the records of a backlog segment, series by series.
+pub fn roll_up_records(records: Vec<Record>, tiers: &[Tier]) -> Vec<Record> {
+    let mut series: BTreeMap<String, Vec<Sample>> = BTreeMap::new();
+    for (name, sample) in records {
+        series.entry(name).or_default().push(sample);
+    }
+    let mut rolled: Vec<Record> = series.into_iter()
+        .flat_map(|(name, samples)| {
+            roll_up(samples, tiers).into_iter().map(move |sample| (name.clone(), sample))
+        })
+        .collect();
+    rolled.sort_by_key(|(_, sample)| sample.timestamp);
+    rolled
+}
+
+struct Bucket {
+    start: SystemTime,
+    span: Duration,
+    temperature: f64,
+    count: usize,
+    metrics: BTreeMap<String, (f64, usize)>,
+}
+
+impl Bucket {
+    fn new(start: SystemTime, span: Duration) -> Self {
+        Self { start, span, temperature: 0.0, count: 0, metrics: BTreeMap::new() }
+    }
+
+    fn add(&mut self, sample: &Sample) {
+        self.temperature += sample.temperature;
+        self.count += 1;
+        for (metric, value) in &sample.metrics {
+            let (sum, count) = self.metrics.entry(metric.clone()).or_default();
+            *sum += value;
+            *count += 1;
+        }
+    }
+
+    fn into_sample(self) -> Sample {
+        let mut sample = Sample::new(self.start, self.temperature / self.count as f64);
+        sample.metrics = self.metrics.into_iter()
+            .map(|(metric, (sum, count))| (metric, sum / count as f64))
+            .collect();
+        sample.span = Some(self.span);
+        sample
+    }
+}
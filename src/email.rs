@@ -0,0 +1,177 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use anyhow::Context;
+use chrono::{DateTime, Days, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use log::{error, info, warn};
+use tokio::sync::broadcast;
+use crate::chart::local_time;
+use crate::alerts::{next_event, retry, unix_secs, AlertEvent, Alerts, Delivery, DeliveryLog, Routing};
+use crate::config::{EmailConfig, SmtpSecurity};
+use crate::storage::{Storage, TEMPERATURE};
+
+/// Seconds the SMTP server gets per mail.
+const SMTP_TIMEOUT_SECS: u64 = 30;
+/// Further attempts at a mail the server refused or could not be reached for, a minute apart
+/// and doubling, so a short outage of the mail provider doesn't lose an alert.
+const RETRIES: u32 = 3;
+const RETRY_BACKOFF_SECS: u64 = 60;
+/// Times in the mails, e.g. `2025-09-19 17:13 CEST`.
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M %Z";
+
+/// Sends plain-text mails to the `[email]` recipients.
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: String,
+    to: Vec<String>,
+}
+
+impl Mailer {
+    pub fn new(config: &EmailConfig) -> Result<Self, anyhow::Error> {
+        let mut builder = match config.security {
+            SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host),
+        };
+        builder = builder.port(config.port()).timeout(Some(Duration::from_secs(SMTP_TIMEOUT_SECS)));
+        if let Some(username) = &config.username {
+            builder = builder.credentials(Credentials::new(username.clone(), config.password.clone().unwrap_or_default()));
+        }
+        Ok(Self { transport: builder.build(), from: config.from.clone(), to: config.to.clone() })
+    }
+
+    /// The recipients, for the delivery log.
+    pub fn recipients(&self) -> String {
+        self.to.join(", ")
+    }
+
+    pub async fn send(&self, subject: &str, body: String) -> Result<(), anyhow::Error> {
+        let mut message = Message::builder()
+            .from(self.from.parse()?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            message = message.to(to.parse()?);
+        }
+        self.transport.send(message.body(body)?).await.context("cannot send the mail")?;
+        Ok(())
+    }
+
+    /// `send` retried `RETRIES` times, returns the attempts made and the last error.
+    async fn send_with_retry(&self, subject: &str, body: &str) -> (u32, Option<anyhow::Error>) {
+        let (attempts, result) = retry(RETRIES, Duration::from_secs(RETRY_BACKOFF_SECS), || async {
+            self.send(subject, body.to_string()).await
+                .inspect_err(|e| warn!("failed to mail {:?}: {:#}", subject, e))
+        }).await;
+        (attempts, result.err())
+    }
+}
+
+/// Mails the alert events `routing` allows, one at a time, with the times in `timezone`, the
+/// system one when `None`.
+pub async fn run_email_alerts(mailer: Arc<Mailer>, mut events: broadcast::Receiver<AlertEvent>, log: Arc<Mutex<DeliveryLog>>, routing: Routing, timezone: Option<Tz>) {
+    while let Some(event) = next_event(&mut events, "email", &routing, timezone).await {
+        let (subject, body) = alert_mail(&event, &|secs| local_time(timezone, secs, TIME_FORMAT));
+        let (attempts, error) = mailer.send_with_retry(&subject, &body).await;
+        match &error {
+            None => info!("Mailed alert {} of {} to {}", event.rule, event.sensor, mailer.recipients()),
+            Some(e) => error!("failed to mail alert {} of {} after {} attempts: {:#}", event.rule, event.sensor, attempts, e),
+        }
+        if let Ok(mut log) = log.lock() {
+            log.record(Delivery {
+                channel: "email".to_string(),
+                target: mailer.recipients(),
+                rule: event.rule,
+                sensor: event.sensor,
+                state: event.state,
+                sent_at: unix_secs(SystemTime::now()),
+                attempts,
+                status: None,
+                error: error.map(|e| format!("{:#}", e)),
+            });
+        }
+    }
+}
+
+/// Mails `summary_mail` every day at `at` in `tz`.
+pub async fn run_daily_summary<Z: TimeZone>(mailer: Arc<Mailer>, at: NaiveTime, tz: Z, storage: Arc<RwLock<Storage>>, alerts: Arc<Mutex<Alerts>>) {
+    loop {
+        tokio::time::sleep(until_next(&tz, SystemTime::now(), at)).await;
+        let (subject, body) = {
+            let (Ok(storage), Ok(alerts)) = (storage.read(), alerts.lock()) else {
+                error!("failed to lock storage and alerts for the daily summary");
+                continue;
+            };
+            let active: Vec<AlertEvent> = alerts.active().map(|alert| alert.event.clone()).collect();
+            summary_mail(&storage, &active, SystemTime::now())
+        };
+        match mailer.send_with_retry(&subject, &body).await {
+            (_, None) => info!("Mailed the daily summary to {}", mailer.recipients()),
+            (attempts, Some(e)) => error!("failed to mail the daily summary after {} attempts: {:#}", attempts, e),
+        }
+        // don't send twice within the minute of `at`
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+/// Time from `now` to the next `at` in `tz`; a time skipped by a DST change is taken an hour later.
+pub fn until_next<Z: TimeZone>(tz: &Z, now: SystemTime, at: NaiveTime) -> Duration {
+    let today = DateTime::<Utc>::from(now).with_timezone(tz).date_naive();
+    (0..=2)
+        .filter_map(|days| today.checked_add_days(Days::new(days)))
+        .filter_map(|date| {
+            let local = date.and_time(at);
+            tz.from_local_datetime(&local).earliest()
+                .or_else(|| tz.from_local_datetime(&(local + chrono::Duration::hours(1))).earliest())
+        })
+        .map(SystemTime::from)
+        .find(|time| *time > now)
+        .and_then(|time| time.duration_since(now).ok())
+        .unwrap_or(Duration::from_secs(86400))
+}
+
+/// Subject and text of the mail of an alert event, `time_label` formats its Unix seconds.
+pub fn alert_mail(event: &AlertEvent, time_label: &dyn Fn(u64) -> String) -> (String, String) {
+    let subject = format!("heater-monitor: {}", event.message());
+    let body = format!(
+        "Alert {} is {} for sensor {}.\n\n{}: {:.1}\nThreshold crossed since: {}\nSample time: {}\n",
+        event.rule, event.state.as_str(), event.sensor, event.metric, event.value,
+        time_label(event.since), time_label(event.timestamp));
+    (subject, body)
+}
+
+
+/// Subject and text of the daily summary: the temperature of every sensor now and over the
+/// last 24 hours, and the `active` alerts.
+pub fn summary_mail(storage: &Storage, active: &[AlertEvent], now: SystemTime) -> (String, String) {
+    let mut body = String::new();
+    for name in storage.series_names() {
+        let Some(series) = storage.series(name) else {
+            continue;
+        };
+        let day = series.stats(now - Duration::from_secs(24 * 3600), now).ok()
+            .and_then(|mut stats| stats.remove(TEMPERATURE));
+        match (series.latest_sample(), day) {
+            (Some(last), Some(day)) => body.push_str(&format!(
+                "{}: {:.1} °C now, {:.1} to {:.1} °C over the last 24 hours, mean {:.1} °C\n",
+                name, last.temperature, day.min, day.max, day.mean)),
+            _ => body.push_str(&format!("{}: no samples in the last 24 hours\n", name)),
+        }
+    }
+    if active.is_empty() {
+        body.push_str("\nNo active alerts.\n");
+    } else {
+        body.push_str("\nActive alerts:\n");
+        for event in active {
+            body.push_str(&format!("- {}\n", event.message()));
+        }
+    }
+    let subject = match active.len() {
+        0 => "heater-monitor daily summary".to_string(),
+        count => format!("heater-monitor daily summary, {} active alerts", count),
+    };
+    (subject, body)
+}
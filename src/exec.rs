@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+use regex::Regex;
+use tokio::process::Command;
+use crate::app_error::AppError;
+use crate::temp_sensor::{parse_json, parse_regex, Measurement};
+
+/// How the output of the command is read.
+pub enum ExecOutput {
+    Regex(Regex),
+    Json {
+        temperature_path: String,
+        humidity_path: String,
+        metrics: BTreeMap<String, String>,
+    },
+}
+
+/// Runs a command on every poll and parses its stdout.
+pub struct ExecSensor {
+    command: Vec<String>,
+    output: ExecOutput,
+}
+
+impl ExecSensor {
+    pub fn new(command: &[String], output: ExecOutput) -> Self {
+        Self { command: command.to_vec(), output }
+    }
+
+    pub async fn query(&self) -> Result<Measurement, AppError> {
+        let (program, args) = self.command.split_first()
+            .ok_or_else(|| AppError::TemperatureSensorError("empty command".to_string()))?;
+        // the retry policy timeout drops the future, which must not leave the process behind
+        let output = Command::new(program)
+            .args(args)
+            .kill_on_drop(true)
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(AppError::TemperatureSensorError(
+                format!("{} exited with {}: {}", program, output.status, stderr.trim())));
+        }
+        match &self.output {
+            ExecOutput::Regex(re) => parse_regex(re, &String::from_utf8_lossy(&output.stdout)),
+            ExecOutput::Json { temperature_path, humidity_path, metrics } =>
+                parse_json(&output.stdout, temperature_path, humidity_path, metrics),
+        }
+    }
+}
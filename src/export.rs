@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::SystemTime;
+use serde::Deserialize;
+use utoipa::ToSchema;
+use crate::storage::Sample;
+
+/// Samples per chunk of the streamed response.
+const CHUNK_SAMPLES: usize = 1000;
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// `timestamp,temperature,<metric>...` with Unix seconds, readable by the `import` command.
+    #[default]
+    Csv,
+    /// An array of `{"timestamp": <secs>, "temperature": ..., "<metric>": ...}` objects.
+    Json,
+    /// InfluxDB line protocol with nanosecond timestamps, one line per sample.
+    Influx,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Influx => "text/plain",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Influx => "lp",
+        }
+    }
+}
+
+/// Raw samples of a series encoded chunk by chunk, so a long range is not built in one string.
+pub struct Export {
+    format: ExportFormat,
+    metrics: Vec<String>,
+    samples: Vec<Sample>,
+    /// Measurement and escaped tags starting every line protocol line.
+    series_key: String,
+}
+
+impl Export {
+    pub fn new(format: ExportFormat, samples: Vec<Sample>) -> Self {
+        let metrics: BTreeSet<&String> = samples.iter().flat_map(|sample| sample.metrics.keys()).collect();
+        Self {
+            format,
+            metrics: metrics.into_iter().cloned().collect(),
+            samples,
+            series_key: String::new(),
+        }
+    }
+
+    /// Measurement and tags of the `Influx` format, `tags` are sorted as Influx prefers.
+    pub fn with_series_key(mut self, measurement: &str, tags: &BTreeMap<String, String>) -> Self {
+        self.series_key = escape(measurement, &[',', ' ']);
+        for (key, value) in tags {
+            self.series_key.push_str(&format!(",{}={}", escape(key, &[',', '=', ' ']), escape(value, &[',', '=', ' '])));
+        }
+        self
+    }
+
+    /// The header, the samples in chunks of `CHUNK_SAMPLES` and the footer.
+    pub fn into_chunks(self) -> impl Iterator<Item = String> {
+        let header = match self.format {
+            ExportFormat::Csv => {
+                let mut header = String::from("timestamp,temperature");
+                for metric in &self.metrics {
+                    header.push(',');
+                    header.push_str(metric);
+                }
+                header.push('\n');
+                header
+            }
+            ExportFormat::Json => String::from("["),
+            ExportFormat::Influx => String::new(),
+        };
+        let footer = match self.format {
+            ExportFormat::Csv | ExportFormat::Influx => String::new(),
+            ExportFormat::Json => String::from("]"),
+        };
+        let chunks = (0..self.samples.len()).step_by(CHUNK_SAMPLES)
+            .map(move |start| {
+                let end = (start + CHUNK_SAMPLES).min(self.samples.len());
+                let mut chunk = String::new();
+                for (i, sample) in self.samples[start..end].iter().enumerate() {
+                    match self.format {
+                        ExportFormat::Csv => self.csv_row(&mut chunk, sample),
+                        ExportFormat::Json => {
+                            if start + i > 0 {
+                                chunk.push(',');
+                            }
+                            json_object(&mut chunk, sample);
+                        }
+                        ExportFormat::Influx => self.influx_line(&mut chunk, sample),
+                    }
+                }
+                chunk
+            });
+        std::iter::once(header).chain(chunks).chain(std::iter::once(footer))
+    }
+
+    fn csv_row(&self, out: &mut String, sample: &Sample) {
+        out.push_str(&format!("{},{}", unix_secs(sample.timestamp), sample.temperature));
+        for metric in &self.metrics {
+            out.push(',');
+            if let Some(value) = sample.metrics.get(metric) {
+                out.push_str(&value.to_string());
+            }
+        }
+        out.push('\n');
+    }
+
+    fn influx_line(&self, out: &mut String, sample: &Sample) {
+        out.push_str(&format!("{} temperature={}", self.series_key, sample.temperature));
+        for (metric, value) in &sample.metrics {
+            out.push_str(&format!(",{}={}", metric, value));
+        }
+        let nanos = sample.timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos();
+        out.push_str(&format!(" {}\n", nanos));
+    }
+}
+
+/// Backslash-escapes the `special` characters of a line protocol name.
+fn escape(name: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if special.contains(&c) || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn json_object(out: &mut String, sample: &Sample) {
+    let mut object = serde_json::Map::new();
+    object.insert("timestamp".to_string(), unix_secs(sample.timestamp).into());
+    object.insert("temperature".to_string(), sample.temperature.into());
+    for (metric, value) in &sample.metrics {
+        object.insert(metric.clone(), (*value).into());
+    }
+    out.push_str(&serde_json::Value::Object(object).to_string());
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
@@ -0,0 +1,67 @@
+use std::time::{Duration, SystemTime};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use crate::app_error::AppError;
+use crate::storage::{Sample, HUMIDITY};
+
+/// Column header names accepted for each value, compared case-insensitively.
+const TIMESTAMP_COLUMNS: [&str; 4] = ["timestamp", "time", "date", "datetime"];
+const TEMPERATURE_COLUMNS: [&str; 2] = ["temperature", "temp"];
+const HUMIDITY_COLUMNS: [&str; 2] = ["humidity", "hum"];
+
+/// Parses a CSV export of another logger into samples, oldest first.
+///
+/// The header row names the `timestamp`, `temperature` and optional `humidity` columns,
+/// other columns are ignored. Fields are separated by `,` or, when the header has no comma,
+/// by `;`. Timestamps are Unix seconds, RFC 3339 or local `YYYY-mm-dd HH:MM[:SS]`.
+pub fn parse_csv(text: &str) -> Result<Vec<Sample>, AppError> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next()
+        .ok_or_else(|| AppError::ParseError("empty CSV".to_string()))?;
+    let separator = if header.contains(',') { ',' } else { ';' };
+    let columns: Vec<String> = split(header, separator).map(str::to_lowercase).collect();
+    let find = |names: &[&str]| columns.iter().position(|column| names.contains(&column.as_str()));
+    let timestamp_column = find(&TIMESTAMP_COLUMNS)
+        .ok_or_else(|| AppError::ParseError("no timestamp column in CSV header".to_string()))?;
+    let temperature_column = find(&TEMPERATURE_COLUMNS)
+        .ok_or_else(|| AppError::ParseError("no temperature column in CSV header".to_string()))?;
+    let humidity_column = find(&HUMIDITY_COLUMNS);
+
+    let mut samples = Vec::new();
+    for (i, line) in lines {
+        let fields: Vec<&str> = split(line, separator).collect();
+        let field = |column: usize| fields.get(column).copied().unwrap_or("");
+        let error = |what: &str| AppError::ParseError(format!("line {}: invalid {} in {:?}", i + 1, what, line));
+
+        let timestamp = parse_timestamp(field(timestamp_column)).ok_or_else(|| error("timestamp"))?;
+        let temperature = field(temperature_column).parse::<f64>().ok()
+            .filter(|temperature| temperature.is_finite())
+            .ok_or_else(|| error("temperature"))?;
+        let mut sample = Sample::new(timestamp, temperature);
+        if let Some(column) = humidity_column.filter(|&column| !field(column).is_empty()) {
+            let humidity = field(column).parse::<f64>().ok()
+                .filter(|humidity| humidity.is_finite())
+                .ok_or_else(|| error("humidity"))?;
+            sample.metrics.insert(HUMIDITY.to_string(), humidity);
+        }
+        samples.push(sample);
+    }
+    samples.sort_by_key(|sample| sample.timestamp);
+    Ok(samples)
+}
+
+fn split(line: &str, separator: char) -> impl Iterator<Item = &str> {
+    line.split(separator).map(|field| field.trim().trim_matches('"').trim())
+}
+
+fn parse_timestamp(value: &str) -> Option<SystemTime> {
+    if let Ok(secs) = value.parse::<u64>() {
+        return SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs));
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.into());
+    }
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"].iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        .map(SystemTime::from)
+}
@@ -1,4 +1,24 @@
 pub mod storage;
+pub mod backend;
+pub mod backlog;
+pub mod downsampling;
+pub mod snapshot;
+pub mod import;
+pub mod export;
+pub mod backfill;
+pub mod stats;
+pub mod chart;
+pub mod derived;
+pub mod annotations;
+pub mod alerts;
+pub mod webhook;
+pub mod email;
+pub mod telegram;
+pub mod push;
+pub mod alert_command;
 pub mod app_error;
 pub mod config;
-pub mod server;
\ No newline at end of file
+pub mod server;
+pub mod sensor_status;
+pub mod sample_filter;
+pub mod discovery;
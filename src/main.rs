@@ -3,20 +3,85 @@ mod config;
 mod app_error;
 mod temp_sensor;
 mod storage;
+mod backend;
+mod backlog;
+mod downsampling;
+mod snapshot;
+mod import;
+mod export;
+mod backfill;
+mod stats;
+mod chart;
+mod derived;
+mod annotations;
+mod alerts;
+mod webhook;
+mod email;
+mod telegram;
+mod push;
+mod alert_command;
 mod server;
+mod mqtt;
+mod dht22;
+mod exec;
+mod modbus;
+mod snmp;
+mod prometheus;
+mod simulator;
+mod tasmota;
+mod sensor_status;
+mod sample_filter;
+mod discovery;
+mod serial;
+mod supervisor;
+#[cfg(feature = "ble")]
+mod ble;
 
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
-use crate::config::Config;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use crate::config::{Calibration, Config, HttpAuth, SensorSource};
+use crate::mqtt::{run_mqtt, MqttSubscription};
+#[cfg(feature = "ble")]
+use crate::ble::{run_ble, BleSubscription};
 use anyhow::Result;
-use log::{error, info};
+use log::{error, info, warn};
 use std::fs::OpenOptions;
 use crate::server::run_server;
-use crate::temp_sensor::TempSensor;
-use crate::storage::Storage;
-use clap::Parser;
+use crate::app_error::AppError;
+use crate::temp_sensor::{http_client, JsonSensor, Measurement, PolledSensor, RetryPolicy, TempSensor};
+use crate::dht22::Dht22Sensor;
+use crate::exec::{ExecOutput, ExecSensor};
+use crate::modbus::ModbusSensor;
+use crate::snmp::SnmpSensor;
+use crate::prometheus::PrometheusSensor;
+use crate::simulator::SimulatedSensor;
+use crate::tasmota::TasmotaSensor;
+use crate::serial::{run_serial, SerialSensor};
+use crate::supervisor::supervise;
+use crate::backlog::Backlog;
+use crate::import::parse_csv;
+use crate::backfill::backfill;
+use crate::storage::{Storage, DEFAULT_SERIES};
+use crate::backend::SharedBackend;
+use crate::sensor_status::SensorStatuses;
+use crate::alerts::{AlertEvent, AlertState, Alerts, DeliveryLog};
+use crate::webhook::run_webhook;
+use crate::email::{run_daily_summary, run_email_alerts, Mailer};
+use crate::telegram::{run_telegram_alerts, run_telegram_commands, Sources, Telegram};
+use crate::push::{run_push, Push};
+use crate::alert_command::run_alert_commands;
+use crate::sample_filter::{median, SampleFilter};
+use crate::discovery::{run_discovery, Discovered};
+use clap::{Parser, Subcommand};
+use tokio::sync::broadcast;
+use regex::Regex;
 use daemonize::Daemonize;
 
+/// Longest time between two checks of `[stale_alert]`.
+const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
 #[derive(Parser)]
 #[command(name = "heater-monitor")]
 #[command(about = "A temperature and humidity monitoring system")]
@@ -29,48 +94,471 @@ struct Args {
 
     #[arg(short = 'd', long = "daemon")]
     daemon: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Import samples from a CSV file into the backlog, loaded on the next start
+    Import {
+        /// CSV with a header row naming timestamp, temperature and optional humidity columns
+        #[arg(long)]
+        csv: PathBuf,
+        /// Series the samples are imported to
+        #[arg(long, default_value = DEFAULT_SERIES)]
+        sensor: String,
+    },
+}
 
+/// Writes the samples of `csv` to a closed segment of the configured backlog.
+fn import_csv(config: &Config, csv: &Path, sensor: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let backlog = config.backlog.as_ref().ok_or("import needs a backlog configured")?;
+    let records: Vec<_> = parse_csv(&std::fs::read_to_string(csv)?)?
+        .into_iter()
+        .map(|sample| (sensor.to_string(), sample))
+        .collect();
+    let segment = Backlog::write_segment(backlog, config.backlog_format, &records)?;
+    println!("Imported {} samples of {} to {}", records.len(), sensor, segment.display());
+    Ok(())
+}
 
-async fn run_app(config: Config) -> Result<(), Box<dyn std::error::Error>> {
 
-    let storage = Arc::new(Mutex::new(
-        Storage::new(&config)?
-    ));
-    info!("Storage initialized");
 
-    {
-        let temp_sensor = TempSensor::new(&config.temp_sensor_url);
-        let sampling_interval = config.sampling_interval;
-        let storage = storage.clone();
-        info!("Starting temperature monitoring task with {}s interval", sampling_interval);
-        let _handle = tokio::spawn(async move {
-            let mut cnt: usize = 0;
-            loop {
-                if let Ok(val) = temp_sensor.query().await {
-                    if cnt % 50 == 0 {
+/// A sensor polled by its own task, cloned for every restart by the supervisor.
+#[derive(Clone)]
+struct SensorTask {
+    name: String,
+    sensor: Arc<PolledSensor>,
+    policy: RetryPolicy,
+    calibration: Calibration,
+    filter: SampleFilter,
+    oversampling: u32,
+    sampling_interval: u64,
+}
+
+impl SensorTask {
+    /// Queries the sensor `oversampling` times spread over the sampling interval
+    /// and returns the median, fails only when every query failed.
+    async fn sample(&self) -> Result<Measurement, AppError> {
+        if self.oversampling <= 1 {
+            return self.sensor.query_with_retry(&self.policy).await;
+        }
+        let mut temperatures = Vec::new();
+        let mut humidities = Vec::new();
+        let mut metrics: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        let mut last_error = None;
+        for i in 0..self.oversampling {
+            if i > 0 {
+                tokio::time::sleep(self.query_spacing()).await;
+            }
+            match self.sensor.query_with_retry(&self.policy).await {
+                Ok(val) => {
+                    temperatures.push(val.temperature);
+                    humidities.push(val.humidity);
+                    for (metric, value) in val.metrics {
+                        metrics.entry(metric).or_default().push(value);
+                    }
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match median(&temperatures) {
+            Some(temperature) => {
+                let mut measurement = Measurement::new(temperature, median(&humidities).unwrap_or(f64::NAN));
+                for (metric, values) in metrics {
+                    if let Some(value) = median(&values) {
+                        measurement.metrics.insert(metric, value);
+                    }
+                }
+                Ok(measurement)
+            }
+            None => Err(last_error.unwrap_or_else(||
+                AppError::TemperatureSensorError("no valid reading".to_string()))),
+        }
+    }
+
+    fn query_spacing(&self) -> Duration {
+        Duration::from_secs(self.sampling_interval) / self.oversampling
+    }
+
+    async fn run(mut self, storage: SharedBackend, statuses: Arc<Mutex<SensorStatuses>>) {
+        let name = self.name.clone();
+        let mut cnt: usize = 0;
+        let mut last_failure: Option<String> = None;
+        loop {
+            match self.sample().await {
+                Ok(mut val) => {
+                    val.temperature = self.calibration.apply(val.temperature);
+                    if let Some(reason) = last_failure.take() {
+                        info!("{}: sensor recovered, last failure: {}", name, reason);
+                    }
+                    if let Err(reason) = self.filter.check(val.temperature) {
+                        warn!("{}: rejected reading: {} ({} rejected so far)", name, reason, self.filter.rejected());
+                        if let Ok(mut statuses) = statuses.lock() {
+                            statuses.record_rejection(&name);
+                        }
+                        tokio::time::sleep(self.query_spacing()).await;
+                        continue;
+                    }
+                    if let Ok(mut statuses) = statuses.lock() {
+                        statuses.record_success(&name);
+                    }
+                    if cnt.is_multiple_of(50) {
                         // log every 50th measurement
-                        info!("Measurements: {}, Temperature: {}°C, Humidity: {}%",
-                            cnt, val.temperature, val.humidity);
+                        info!("{}: Measurements: {}, Temperature: {}°C, Humidity: {}%",
+                            name, cnt, val.temperature, val.humidity);
                     }
-                    
-                    if let Ok(mut storage) = storage.lock() {
+
+                    if let Ok(mut storage) = storage.write() {
                         cnt += 1;
-                        storage.add_measurement(val.temperature, val.humidity);
+                        if let Err(e) = storage.append(&name, val.into_sample(SystemTime::now())) {
+                            error!("{}: failed to store sample: {}", name, e);
+                        }
                     } else {
                         error!("failed to lock storage");
                     }
-                } else {
-                    error!("failed to query temperature sensor");
                 }
-                tokio::time::sleep(std::time::Duration::from_secs(sampling_interval as u64)).await;
+                Err(e) => {
+                    error!("failed to query temperature sensor {}: {}", name, e);
+                    if let Ok(mut statuses) = statuses.lock() {
+                        statuses.record_failure(&name, &e.to_string());
+                    }
+                    last_failure = Some(e.to_string());
+                }
+            }
+            tokio::time::sleep(self.query_spacing()).await;
+        }
+    }
+}
+
+/// Applies `retention_days` and `[downsampling]` to the storage once an hour.
+async fn run_maintenance(storage: Arc<RwLock<Storage>>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(3600));
+    loop {
+        interval.tick().await;
+        let Ok(mut storage) = storage.write() else {
+            error!("failed to lock storage");
+            continue;
+        };
+        let now = SystemTime::now();
+        match storage.apply_retention(now) {
+            Ok(0) => {}
+            Ok(evicted) => info!("Evicted {} samples past the retention period", evicted),
+            Err(e) => error!("failed to apply retention: {}", e),
+        }
+        match storage.downsample(now) {
+            Ok(0) => {}
+            Ok(shrunk) => info!("Downsampling saved {} samples", shrunk),
+            Err(e) => error!("failed to downsample: {}", e),
+        }
+    }
+}
+
+/// Checks every stored sample, from any source, against the `[[alerts]]` rules.
+async fn run_alerts(storage: Arc<RwLock<Storage>>, alerts: Arc<Mutex<Alerts>>) {
+    let Ok(mut stored) = storage.read().map(|storage| storage.subscribe()) else {
+        error!("failed to lock storage");
+        return;
+    };
+    loop {
+        let update = match stored.recv().await {
+            Ok(update) => update,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("alert rules skipped {} samples", missed);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let Ok(mut alerts) = alerts.lock() else {
+            error!("failed to lock alerts");
+            continue;
+        };
+        for event in alerts.evaluate(&update.series, &update.sample) {
+            log_alert(&event);
+        }
+    }
+}
+
+/// Checks every `interval` whether the sensors, with their sampling intervals, still store
+/// samples; one without any counts from the start.
+async fn run_stale_alerts(storage: Arc<RwLock<Storage>>, alerts: Arc<Mutex<Alerts>>, sensors: Vec<(String, Duration)>, interval: Duration) {
+    let started = SystemTime::now();
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let now = SystemTime::now();
+        let (Ok(storage), Ok(mut alerts)) = (storage.read(), alerts.lock()) else {
+            error!("failed to lock storage and alerts");
+            continue;
+        };
+        for (sensor, sampling_interval) in &sensors {
+            let newest = storage.series(sensor)
+                .and_then(|series| series.latest_sample())
+                .map_or(started, |sample| sample.timestamp.max(started));
+            if let Some(event) = alerts.check_stale(sensor, newest, *sampling_interval, now) {
+                log_alert(&event);
+            }
+        }
+    }
+}
+
+fn log_alert(event: &AlertEvent) {
+    match event.state {
+        AlertState::Active => warn!("alert {}", event.message()),
+        AlertState::Resolved => info!("alert {}", event.message()),
+    }
+}
+
+/// Writes buffered backlog records every `interval` while no samples arrive.
+async fn run_backlog_flush(storage: Arc<RwLock<Storage>>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let result = match storage.write() {
+            Ok(mut storage) => storage.flush_backlog(),
+            Err(_) => {
+                error!("failed to lock storage");
+                continue;
             }
-        });
+        };
+        if let Err(e) = result {
+            error!("failed to flush backlog: {}", e);
+        }
+    }
+}
+
+async fn run_app(config: Config, config_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+
+    let storage = Arc::new(RwLock::new(
+        Storage::new(&config)?
+    ));
+    info!("Storage initialized");
+
+    let sensors = config.sensors();
+    let statuses = Arc::new(Mutex::new(
+        SensorStatuses::new(sensors.iter().map(|sensor| sensor.name.as_str()))
+    ));
+
+    let user_agent = config.user_agent();
+    if let Some(peer) = &config.backfill_from {
+        let names: Vec<String> = sensors.iter().map(|sensor| sensor.name.clone()).collect();
+        backfill(&storage, &http_client(&user_agent, &HttpAuth::default())?, peer, &names).await;
+    }
+    let mut subscriptions = Vec::new();
+    #[cfg(feature = "ble")]
+    let mut ble_subscriptions = Vec::new();
+    for sensor in sensors {
+        let sampling_interval = sensor.sampling_interval.unwrap_or(config.sampling_interval);
+        let temp_sensor = match sensor.source {
+            SensorSource::Http { url, regex, auth } =>
+                PolledSensor::Http(TempSensor::new(&url, &regex, http_client(&user_agent, &auth)?)?),
+            SensorSource::Json { url, temperature_path, humidity_path, metrics, auth } =>
+                PolledSensor::Json(JsonSensor::new(&url, &temperature_path, &humidity_path, &metrics, http_client(&user_agent, &auth)?)),
+            SensorSource::OpenMeteo { latitude, longitude } =>
+                PolledSensor::Json(JsonSensor::open_meteo(latitude, longitude, http_client(&user_agent, &HttpAuth::default())?)),
+            SensorSource::Dht22 { pin, retries } => PolledSensor::Dht22(Dht22Sensor::new(pin, retries)),
+            SensorSource::Simulator(simulator) => PolledSensor::Simulator(SimulatedSensor::new(&simulator)),
+            SensorSource::Modbus(modbus) => PolledSensor::Modbus(ModbusSensor::new(&modbus)),
+            SensorSource::Snmp { address, community, oid, scale, humidity_oid } =>
+                PolledSensor::Snmp(SnmpSensor::new(&address, &community, &oid, scale, humidity_oid.as_deref())?),
+            SensorSource::Prometheus { url, metric, humidity_metric, labels, auth } =>
+                PolledSensor::Prometheus(PrometheusSensor::new(&url, &metric, humidity_metric.as_deref(), &labels, http_client(&user_agent, &auth)?)),
+            SensorSource::Tasmota { url, sensor: section, auth } =>
+                PolledSensor::Tasmota(TasmotaSensor::new(&url, section.as_deref(), http_client(&user_agent, &auth)?)),
+            SensorSource::Exec { command, regex, temperature_path, humidity_path, metrics } => {
+                let output = match regex {
+                    Some(regex) => ExecOutput::Regex(Regex::new(&regex)?),
+                    None => ExecOutput::Json { temperature_path, humidity_path, metrics },
+                };
+                PolledSensor::Exec(ExecSensor::new(&command, output))
+            }
+            SensorSource::Mqtt { topic, temperature_path, humidity_path, metrics } => {
+                subscriptions.push(MqttSubscription {
+                    name: sensor.name,
+                    topic,
+                    temperature_path,
+                    humidity_path,
+                    metrics,
+                    battery_path: None,
+                    calibration: sensor.calibration,
+                    filter: SampleFilter::new(sensor.filter),
+                });
+                continue;
+            }
+            SensorSource::Zigbee2mqtt { device, base_topic, metrics } => {
+                subscriptions.push(MqttSubscription {
+                    name: sensor.name,
+                    topic: format!("{}/{}", base_topic, device),
+                    temperature_path: "/temperature".to_string(),
+                    humidity_path: "/humidity".to_string(),
+                    metrics,
+                    battery_path: Some("/battery".to_string()),
+                    calibration: sensor.calibration,
+                    filter: SampleFilter::new(sensor.filter),
+                });
+                continue;
+            }
+            SensorSource::Serial { device, baud_rate, regex } => {
+                let serial = SerialSensor {
+                    name: sensor.name,
+                    device,
+                    baud_rate,
+                    sampling_interval,
+                    re: Regex::new(&regex)?,
+                    calibration: sensor.calibration,
+                    filter: SampleFilter::new(sensor.filter),
+                };
+                info!("Starting serial reader for {} on {}", serial.name, serial.device);
+                let (storage, statuses) = (storage.clone(), statuses.clone());
+                tokio::spawn(supervise(serial.name.clone(), statuses.clone(),
+                    move || run_serial(serial.clone(), storage.clone(), statuses.clone())));
+                continue;
+            }
+            // fed by the server
+            SensorSource::Push | SensorSource::Shelly { .. } => continue,
+            #[cfg(feature = "ble")]
+            SensorSource::Ble { mac } => {
+                ble_subscriptions.push(BleSubscription {
+                    name: sensor.name,
+                    mac: mac.to_uppercase(),
+                    sampling_interval,
+                    calibration: sensor.calibration,
+                    filter: SampleFilter::new(sensor.filter),
+                });
+                continue;
+            }
+            #[cfg(not(feature = "ble"))]
+            SensorSource::Ble { .. } => {
+                error!("sensor {} needs BLE support, build with --features ble", sensor.name);
+                continue;
+            }
+        };
+        let policy = RetryPolicy {
+            timeout: Duration::from_secs(sensor.timeout),
+            retries: sensor.query_retries,
+            backoff: Duration::from_secs(sensor.retry_backoff),
+        };
+        info!("Starting temperature monitoring task for {} with {}s interval", sensor.name, sampling_interval);
+        let task = SensorTask {
+            name: sensor.name,
+            sensor: Arc::new(temp_sensor),
+            policy,
+            calibration: sensor.calibration,
+            filter: SampleFilter::new(sensor.filter),
+            oversampling: sensor.oversampling,
+            sampling_interval,
+        };
+        let (storage, statuses) = (storage.clone(), statuses.clone());
+        tokio::spawn(supervise(task.name.clone(), statuses.clone(),
+            move || task.clone().run(storage.clone(), statuses.clone())));
+    }
+
+    if !subscriptions.is_empty() {
+        if let Some(mqtt_config) = config.mqtt.clone() {
+            info!("Starting MQTT subscriber for {} sensors", subscriptions.len());
+            tokio::spawn(run_mqtt(mqtt_config, subscriptions, storage.clone(), statuses.clone()));
+        }
+    }
+
+    #[cfg(feature = "ble")]
+    if !ble_subscriptions.is_empty() {
+        info!("Starting BLE scanner for {} sensors", ble_subscriptions.len());
+        tokio::spawn(run_ble(ble_subscriptions, storage.clone(), statuses.clone()));
+    }
+
+    if config.retention_days.is_some() || config.downsampling.is_some() {
+        tokio::spawn(run_maintenance(storage.clone()));
+    }
+
+    let flushing = config.backlog_flushing();
+    if let Some(interval) = flushing.flush_interval.into_iter().chain(flushing.fsync_interval).min() {
+        tokio::spawn(run_backlog_flush(storage.clone(), interval));
+    }
+
+    let alerts = Alerts::new(config.alerts.clone())
+        .with_timezone(config.time_zone())
+        .with_stale_alert(config.stale_alert.clone());
+    let deliveries = Arc::new(Mutex::new(DeliveryLog::default()));
+    for webhook in &config.webhooks {
+        let client = http_client(&user_agent, &webhook.auth)?;
+        tokio::spawn(run_webhook(webhook.clone(), client, alerts.subscribe(), deliveries.clone(), config.time_zone()));
+    }
+    let mailer = config.email.as_ref().map(Mailer::new).transpose()?.map(Arc::new);
+    if let (Some(mailer), Some(email)) = (&mailer, &config.email) {
+        if email.alerts {
+            tokio::spawn(run_email_alerts(mailer.clone(), alerts.subscribe(), deliveries.clone(), email.routing.clone(), config.time_zone()));
+        }
+    }
+    let telegram = match &config.telegram {
+        Some(telegram) => Some(Arc::new(Telegram::new(telegram, http_client(&user_agent, &HttpAuth::default())?))),
+        None => None,
+    };
+    if let (Some(bot), Some(telegram)) = (&telegram, &config.telegram) {
+        if telegram.alerts {
+            tokio::spawn(run_telegram_alerts(bot.clone(), alerts.subscribe(), deliveries.clone(), config.time_zone()));
+        }
+    }
+    let pushes = config.ntfy.iter().map(|ntfy| (Push::Ntfy(ntfy.clone()), ntfy.auth.clone()))
+        .chain(config.pushover.iter().map(|pushover| (Push::Pushover(pushover.clone()), HttpAuth::default())));
+    for (push, auth) in pushes {
+        let client = http_client(&user_agent, &auth)?;
+        tokio::spawn(run_push(push, client, alerts.subscribe(), deliveries.clone(), config.time_zone()));
+    }
+    let commands: BTreeMap<String, Vec<String>> = config.alerts.iter()
+        .filter_map(|rule| Some((rule.name.clone(), rule.command.clone()?)))
+        .collect();
+    if !commands.is_empty() {
+        tokio::spawn(run_alert_commands(commands, alerts.subscribe(), deliveries.clone()));
+    }
+    let alerts = Arc::new(Mutex::new(alerts));
+    if let Some(bot) = telegram {
+        info!("Starting Telegram bot for chats {}", bot.recipients());
+        let sources = Sources {
+            storage: storage.clone(),
+            alerts: alerts.clone(),
+            outdoor_sensor: config.outdoor_sensor.clone(),
+            default_sensor: config.sensors().first().map(|sensor| sensor.name.clone()).unwrap_or_default(),
+            timezone: config.time_zone(),
+        };
+        tokio::spawn(run_telegram_commands(bot, sources));
+    }
+    if !config.alerts.is_empty() {
+        info!("Evaluating {} alert rules", config.alerts.len());
+        tokio::spawn(run_alerts(storage.clone(), alerts.clone()));
+    }
+    if config.stale_alert.is_some() {
+        let sensors: Vec<(String, Duration)> = config.sensors().into_iter()
+            .map(|sensor| {
+                let sampling_interval = sensor.sampling_interval.unwrap_or(config.sampling_interval);
+                (sensor.name, Duration::from_secs(sampling_interval))
+            })
+            .collect();
+        let interval = sensors.iter().map(|(_, interval)| *interval).min().unwrap_or(STALE_CHECK_INTERVAL).min(STALE_CHECK_INTERVAL);
+        tokio::spawn(run_stale_alerts(storage.clone(), alerts.clone(), sensors, interval));
+    }
+    if let Some((mailer, at)) = mailer.zip(config.email.as_ref().and_then(|email| email.daily_summary_time())) {
+        info!("Mailing a daily summary at {}", at);
+        match config.time_zone() {
+            Some(tz) => tokio::spawn(run_daily_summary(mailer, at, tz, storage.clone(), alerts.clone())),
+            None => tokio::spawn(run_daily_summary(mailer, at, chrono::Local, storage.clone(), alerts.clone())),
+        };
+    }
+
+    let discovered = Arc::new(Mutex::new(Discovered::default()));
+    if config.discovery {
+        info!("Starting mDNS discovery");
+        if let Err(e) = run_discovery(discovered.clone()) {
+            error!("failed to start mDNS discovery: {}", e);
+        }
     }
 
     info!("Starting HTTP server on port {}", config.port);
-    let _res = run_server(storage, &config).await?;
+    run_server(storage.clone(), statuses, alerts, deliveries, discovered, &config, config_path).await?;
+
+    info!("Shutting down");
+    storage.write().map_err(|_| "failed to lock storage")?.write_snapshot()?;
     Ok(())
 }
 
@@ -88,7 +576,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(not(debug_assertions))]
     let config_path = args.config_path.clone();
 
-    let config = Config::read(config_path)?;
+    let config = Config::read(config_path.clone())?;
+    // absolute for /admin/reload and /config, daemonizing changes to /tmp
+    let config_path = std::fs::canonicalize(&config_path)?;
+
+    if let Some(Command::Import { csv, sensor }) = &args.command {
+        return import_csv(&config, csv, sensor);
+    }
 
     // In debug mode, force foreground operation
     #[cfg(debug_assertions)]
@@ -133,6 +627,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("Running in foreground mode");
     }
 
+    // written by daemonize, left behind it would make the service look running
+    let pid_file = daemon_mode.then(|| config.pid_file.clone());
     let rt = tokio::runtime::Runtime::new()?;
-    rt.block_on(run_app(config))
+    let result = rt.block_on(run_app(config, config_path));
+    if let Some(pid_file) = pid_file {
+        if let Err(e) = std::fs::remove_file(&pid_file) {
+            warn!("failed to remove PID file {}: {}", pid_file, e);
+        }
+    }
+    result
 }
@@ -0,0 +1,65 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio_modbus::client::{rtu, tcp, Context, Reader};
+use tokio_modbus::Slave;
+use tokio_serial::SerialPortBuilderExt;
+use crate::app_error::AppError;
+use crate::config::ModbusSensorConfig;
+use crate::temp_sensor::Measurement;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Temperature transmitter on a Modbus TCP or RTU bus.
+pub struct ModbusSensor {
+    config: ModbusSensorConfig,
+}
+
+impl ModbusSensor {
+    pub fn new(config: &ModbusSensorConfig) -> Self {
+        Self { config: config.clone() }
+    }
+
+    pub async fn query(&self) -> Result<Measurement, AppError> {
+        tokio::time::timeout(TIMEOUT, self.read())
+            .await
+            .map_err(|_| AppError::TemperatureSensorError("modbus request timed out".to_string()))?
+    }
+
+    async fn read(&self) -> Result<Measurement, AppError> {
+        // a fresh connection per poll, a broken link can't poison later readings
+        let mut ctx = self.connect().await?;
+        let temperature = read_register(&mut ctx, self.config.register).await? as f64 * self.config.scale;
+        let humidity = match self.config.humidity_register {
+            Some(register) => read_register(&mut ctx, register).await? as f64 * self.config.humidity_scale,
+            None => f64::NAN,
+        };
+        Ok(Measurement::new(temperature, humidity))
+    }
+
+    async fn connect(&self) -> Result<Context, AppError> {
+        let slave = Slave(self.config.unit_id);
+        if let Some(address) = &self.config.address {
+            let address: SocketAddr = address.parse()
+                .map_err(|e| AppError::TemperatureSensorError(format!("invalid modbus address {}: {}", address, e)))?;
+            Ok(tcp::connect_slave(address, slave).await?)
+        } else if let Some(device) = &self.config.device {
+            let port = tokio_serial::new(device, self.config.baud_rate)
+                .open_native_async()
+                .map_err(|e| AppError::TemperatureSensorError(format!("failed to open {}: {}", device, e)))?;
+            Ok(rtu::attach_slave(port, slave))
+        } else {
+            Err(AppError::TemperatureSensorError("no modbus address or device configured".to_string()))
+        }
+    }
+}
+
+async fn read_register(ctx: &mut Context, register: u16) -> Result<i16, AppError> {
+    let words = ctx.read_holding_registers(register, 1)
+        .await
+        .map_err(|e| AppError::TemperatureSensorError(format!("modbus error: {}", e)))?
+        .map_err(|e| AppError::TemperatureSensorError(format!("modbus exception reading register {}: {}", register, e)))?;
+    // transmitters report temperatures below zero as two's complement
+    words.first()
+        .map(|word| *word as i16)
+        .ok_or_else(|| AppError::TemperatureSensorError(format!("empty response for register {}", register)))
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use log::{error, info, warn};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use serde_json::Value;
+use crate::app_error::AppError;
+use crate::config::{Calibration, MqttConfig};
+use crate::backend::SharedBackend;
+use crate::sensor_status::SensorStatuses;
+use crate::sample_filter::SampleFilter;
+use crate::temp_sensor::{parse_json, Measurement};
+
+/// A sensor fed by messages published to `topic`.
+pub struct MqttSubscription {
+    pub name: String,
+    pub topic: String,
+    pub temperature_path: String,
+    pub humidity_path: String,
+    /// Further metrics by name and JSON pointer.
+    pub metrics: BTreeMap<String, String>,
+    /// JSON pointer of the battery level in percent, if the device reports one.
+    pub battery_path: Option<String>,
+    pub calibration: Calibration,
+    pub filter: SampleFilter,
+}
+
+impl MqttSubscription {
+    pub fn parse(&self, payload: &[u8]) -> Result<Measurement, AppError> {
+        let mut measurement = parse_json(payload, &self.temperature_path, &self.humidity_path, &self.metrics)?;
+        measurement.temperature = self.calibration.apply(measurement.temperature);
+        Ok(measurement)
+    }
+
+    pub fn parse_battery(&self, payload: &[u8]) -> Option<f64> {
+        let path = self.battery_path.as_ref()?;
+        serde_json::from_slice::<Value>(payload).ok()?
+            .pointer(path)?
+            .as_f64()
+    }
+}
+
+pub async fn run_mqtt(
+    config: MqttConfig,
+    mut subscriptions: Vec<MqttSubscription>,
+    storage: SharedBackend,
+    statuses: Arc<Mutex<SensorStatuses>>) {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+    info!("Connecting to MQTT broker {}:{}", config.host, config.port);
+
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                // subscriptions don't survive a clean session reconnect
+                for subscription in &subscriptions {
+                    info!("Subscribing {} to {}", subscription.name, subscription.topic);
+                    if let Err(e) = client.try_subscribe(&subscription.topic, QoS::AtLeastOnce) {
+                        error!("failed to subscribe to {}: {}", subscription.topic, e);
+                    }
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                for subscription in subscriptions.iter_mut().filter(|s| rumqttc::matches(&publish.topic, &s.topic)) {
+                    match subscription.parse(&publish.payload) {
+                        Ok(val) => {
+                            if let Err(reason) = subscription.filter.check(val.temperature) {
+                                warn!("{}: rejected reading: {} ({} rejected so far)",
+                                    subscription.name, reason, subscription.filter.rejected());
+                                if let Ok(mut statuses) = statuses.lock() {
+                                    statuses.record_rejection(&subscription.name);
+                                }
+                                continue;
+                            }
+                            if let Ok(mut statuses) = statuses.lock() {
+                                statuses.record_success(&subscription.name);
+                                if let Some(battery) = subscription.parse_battery(&publish.payload) {
+                                    statuses.record_battery(&subscription.name, battery);
+                                }
+                            }
+                            if let Ok(mut storage) = storage.write() {
+                                if let Err(e) = storage.append(&subscription.name, val.into_sample(SystemTime::now())) {
+                                    error!("{}: failed to store sample: {}", subscription.name, e);
+                                }
+                            } else {
+                                error!("failed to lock storage");
+                            }
+                        }
+                        Err(e) => {
+                            warn!("{}: invalid payload on {}: {}", subscription.name, publish.topic, e);
+                            if let Ok(mut statuses) = statuses.lock() {
+                                statuses.record_failure(&subscription.name, &e.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("MQTT connection error: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use crate::app_error::AppError;
+use crate::temp_sensor::Measurement;
+
+/// Scrapes a Prometheus exposition endpoint and picks the samples matching a metric name and labels.
+pub struct PrometheusSensor {
+    url: String,
+    metric: String,
+    humidity_metric: Option<String>,
+    labels: BTreeMap<String, String>,
+    client: reqwest::Client,
+}
+
+impl PrometheusSensor {
+    pub fn new(
+        url: &str,
+        metric: &str,
+        humidity_metric: Option<&str>,
+        labels: &BTreeMap<String, String>,
+        client: reqwest::Client) -> Self {
+        Self {
+            url: url.to_string(),
+            metric: metric.to_string(),
+            humidity_metric: humidity_metric.map(str::to_string),
+            labels: labels.clone(),
+            client,
+        }
+    }
+
+    pub async fn query(&self) -> Result<Measurement, AppError> {
+        let text = self.client.get(&self.url).send().await?.text().await?;
+        let temperature = find_sample(&text, &self.metric, &self.labels)
+            .ok_or_else(|| AppError::TemperatureSensorError(format!("no sample of {} in {}", self.metric, self.url)))?;
+        let humidity = self.humidity_metric.as_ref()
+            .and_then(|metric| find_sample(&text, metric, &self.labels))
+            .unwrap_or(f64::NAN);
+        Ok(Measurement::new(temperature, humidity))
+    }
+}
+
+/// Value of the first sample of `metric` carrying all of `labels`.
+fn find_sample(text: &str, metric: &str, labels: &BTreeMap<String, String>) -> Option<f64> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(parse_line)
+        .find(|(name, sample_labels, _)| *name == metric
+            && labels.iter().all(|(key, value)| sample_labels.get(key) == Some(value)))
+        .map(|(_, _, value)| value)
+}
+
+/// Splits `name{label="value",...} value [timestamp]` into its parts.
+fn parse_line(line: &str) -> Option<(&str, BTreeMap<String, String>, f64)> {
+    let name_end = line.find(|c: char| c == '{' || c.is_whitespace())?;
+    let name = &line[..name_end];
+    let mut rest = &line[name_end..];
+    let mut labels = BTreeMap::new();
+
+    if let Some(body) = rest.strip_prefix('{') {
+        let mut chars = body.char_indices();
+        let mut key = String::new();
+        loop {
+            let (i, c) = chars.next()?;
+            match c {
+                '}' => {
+                    rest = &body[i + 1..];
+                    break;
+                }
+                ',' | ' ' => {}
+                '=' => {
+                    if chars.next()?.1 != '"' {
+                        return None;
+                    }
+                    let mut value = String::new();
+                    loop {
+                        match chars.next()?.1 {
+                            '\\' => match chars.next()?.1 {
+                                'n' => value.push('\n'),
+                                other => value.push(other),
+                            },
+                            '"' => break,
+                            other => value.push(other),
+                        }
+                    }
+                    labels.insert(std::mem::take(&mut key), value);
+                }
+                other => key.push(other),
+            }
+        }
+    }
+
+    let value = rest.split_whitespace().next()?.parse().ok()?;
+    Some((name, labels, value))
+}
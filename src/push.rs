@@ -0,0 +1,143 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use chrono_tz::Tz;
+use log::{info, warn};
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+use crate::alerts::{next_event, retry, unix_secs, AlertEvent, AlertState, Delivery, DeliveryLog, Priority, Routing};
+use crate::config::{redact_url, NtfyConfig, PushoverConfig};
+use crate::webhook::post_json;
+
+/// Seconds the push service gets per request.
+const TIMEOUT_SECS: u64 = 10;
+/// Further attempts at an event, 5 seconds apart and doubling.
+const RETRIES: u32 = 3;
+const RETRY_BACKOFF_SECS: u64 = 5;
+
+/// A phone push notification service.
+pub enum Push {
+    Ntfy(NtfyConfig),
+    Pushover(PushoverConfig),
+}
+
+impl Push {
+    pub fn channel(&self) -> &'static str {
+        match self {
+            Push::Ntfy(_) => "ntfy",
+            Push::Pushover(_) => "pushover",
+        }
+    }
+
+    pub fn routing(&self) -> &Routing {
+        match self {
+            Push::Ntfy(ntfy) => &ntfy.routing,
+            Push::Pushover(pushover) => &pushover.routing,
+        }
+    }
+
+    /// Where the events go, for the log; the Pushover user key is a secret.
+    pub fn target(&self) -> String {
+        match self {
+            Push::Ntfy(ntfy) => format!("{}/{}", redact_url(&ntfy.url).trim_end_matches('/'), ntfy.topic),
+            Push::Pushover(pushover) => pushover.device.clone().unwrap_or_else(|| "all devices".to_string()),
+        }
+    }
+
+    /// URL and JSON body of the request pushing `event`.
+    pub fn request(&self, event: &AlertEvent) -> (String, Value) {
+        match self {
+            Push::Ntfy(ntfy) => (ntfy.url.clone(), ntfy_message(ntfy, event)),
+            Push::Pushover(pushover) => (format!("{}/1/messages.json", pushover.api_url.trim_end_matches('/')), pushover_message(pushover, event)),
+        }
+    }
+}
+
+/// Priority `event` is pushed with: the rule's while active, at most `normal` once resolved, so
+/// the all-clear doesn't wake anyone.
+pub fn priority(event: &AlertEvent) -> Priority {
+    match event.state {
+        AlertState::Active => event.priority,
+        AlertState::Resolved => event.priority.min(Priority::Normal),
+    }
+}
+
+/// Body of ntfy's JSON publishing, its priority from 2 (low) to 5 (urgent).
+pub fn ntfy_message(ntfy: &NtfyConfig, event: &AlertEvent) -> Value {
+    let (priority, tag) = match (priority(event), event.state) {
+        (_, AlertState::Resolved) => (3, "white_check_mark"),
+        (Priority::Low, _) => (2, "warning"),
+        (Priority::Normal, _) => (3, "warning"),
+        (Priority::High, _) => (4, "warning"),
+        (Priority::Urgent, _) => (5, "rotating_light"),
+    };
+    json!({
+        "topic": ntfy.topic,
+        "title": format!("heater-monitor: {}", event.rule),
+        "message": event.message(),
+        "priority": priority,
+        "tags": [tag],
+    })
+}
+
+/// Body of a Pushover message, its priority from -1 (low, quiet) to 2 (urgent, an emergency
+/// repeated until acknowledged).
+pub fn pushover_message(pushover: &PushoverConfig, event: &AlertEvent) -> Value {
+    let mut message = json!({
+        "token": pushover.token,
+        "user": pushover.user,
+        "title": format!("heater-monitor: {}", event.rule),
+        "message": event.message(),
+        "timestamp": event.timestamp,
+    });
+    let priority = match priority(event) {
+        Priority::Low => -1,
+        Priority::Normal => 0,
+        Priority::High => 1,
+        Priority::Urgent => {
+            message["retry"] = json!(pushover.retry);
+            message["expire"] = json!(pushover.expire);
+            2
+        }
+    };
+    message["priority"] = json!(priority);
+    if let Some(device) = &pushover.device {
+        message["device"] = json!(device);
+    }
+    message
+}
+
+/// Pushes the alert events its routing allows at the time in `timezone`, one at a time so they
+/// arrive in order.
+pub async fn run_push(push: Push, client: reqwest::Client, mut events: broadcast::Receiver<AlertEvent>, log: Arc<Mutex<DeliveryLog>>, timezone: Option<Tz>) {
+    while let Some(event) = next_event(&mut events, push.channel(), push.routing(), timezone).await {
+        let delivery = deliver(&push, &client, &event).await;
+        match &delivery.error {
+            None => info!("Pushed alert {} of {} to {}", event.rule, event.sensor, push.channel()),
+            Some(error) => warn!("failed to push alert {} of {} to {} after {} attempts: {}",
+                event.rule, event.sensor, push.channel(), delivery.attempts, error),
+        }
+        if let Ok(mut log) = log.lock() {
+            log.record(delivery);
+        }
+    }
+}
+
+async fn deliver(push: &Push, client: &reqwest::Client, event: &AlertEvent) -> Delivery {
+    let (url, body) = push.request(event);
+    let (attempts, result) = retry(RETRIES, Duration::from_secs(RETRY_BACKOFF_SECS), || post_json(client, &url, &body, TIMEOUT_SECS)).await;
+    let (status, error) = match result {
+        Ok(status) => (Some(status), None),
+        Err((status, error)) => (status, Some(error)),
+    };
+    Delivery {
+        channel: push.channel().to_string(),
+        target: push.target(),
+        rule: event.rule.clone(),
+        sensor: event.sensor.clone(),
+        state: event.state,
+        sent_at: unix_secs(SystemTime::now()),
+        attempts,
+        status,
+        error,
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+
+/// After this many rejections in a row the next valid reading is accepted as the new reference,
+/// so a real step change doesn't lock the sensor out for good.
+const MAX_CONSECUTIVE_JUMPS: u32 = 5;
+
+/// Limits a reading has to fit in to be stored.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+pub struct FilterConfig {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Largest accepted change against the previous accepted reading.
+    pub max_jump: Option<f64>,
+}
+
+/// Drops implausible readings of a single sensor before they reach `Storage`.
+#[derive(Debug, Default, Clone)]
+pub struct SampleFilter {
+    config: FilterConfig,
+    last: Option<f64>,
+    consecutive_jumps: u32,
+    rejected: u64,
+}
+
+impl SampleFilter {
+    pub fn new(config: FilterConfig) -> Self {
+        Self { config, ..Default::default() }
+    }
+
+    /// Checks the temperature, returns why it was rejected.
+    pub fn check(&mut self, temperature: f64) -> Result<(), String> {
+        let result = self.validate(temperature);
+        match result {
+            Ok(()) => {
+                self.last = Some(temperature);
+                self.consecutive_jumps = 0;
+            }
+            Err(_) => self.rejected += 1,
+        }
+        result
+    }
+
+    fn validate(&mut self, temperature: f64) -> Result<(), String> {
+        if !temperature.is_finite() {
+            return Err(format!("{} is not a number", temperature));
+        }
+        if self.config.min.is_some_and(|min| temperature < min) || self.config.max.is_some_and(|max| temperature > max) {
+            return Err(format!("{} is out of range", temperature));
+        }
+        if let (Some(max_jump), Some(last)) = (self.config.max_jump, self.last) {
+            if (temperature - last).abs() > max_jump && self.consecutive_jumps < MAX_CONSECUTIVE_JUMPS {
+                self.consecutive_jumps += 1;
+                return Err(format!("{} jumps too far from {}", temperature, last));
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of readings rejected since start.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+}
+
+/// Median of the finite values, `None` when there is none.
+pub fn median(values: &[f64]) -> Option<f64> {
+    let mut values: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[mid - 1] + values[mid]) / 2.0)
+    } else {
+        Some(values[mid])
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Alerts a live subscriber may fall behind by before it misses some.
+const SUBSCRIBER_BACKLOG: usize = 64;
+
+/// Health of a single sensor as seen by its sampling task.
+#[derive(Debug, Clone, Default)]
+pub struct SensorStatus {
+    pub last_success: Option<SystemTime>,
+    pub consecutive_failures: u32,
+    /// Failures since startup, not reset by a success.
+    pub total_failures: u64,
+    pub last_error: Option<String>,
+    /// Battery level in percent, for devices reporting one.
+    pub battery: Option<f64>,
+    /// Readings dropped by the sample filter.
+    pub rejected: u64,
+}
+
+impl SensorStatus {
+    pub fn record_success(&mut self, timestamp: SystemTime) {
+        self.last_success = Some(timestamp);
+        self.consecutive_failures = 0;
+    }
+
+    /// The error is kept after recovery so operators can still see what went wrong last.
+    pub fn record_failure(&mut self, error: &str) {
+        self.consecutive_failures += 1;
+        self.total_failures += 1;
+        self.last_error = Some(error.to_string());
+    }
+}
+
+/// A sensor starting to fail or recovering, sent to the subscribers of `SensorStatuses::subscribe`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SensorAlert {
+    Failing { sensor: String, error: String },
+    Recovered { sensor: String },
+}
+
+impl SensorAlert {
+    pub fn sensor(&self) -> &str {
+        match self {
+            SensorAlert::Failing { sensor, .. } | SensorAlert::Recovered { sensor } => sensor,
+        }
+    }
+}
+
+/// Status of every configured sensor, shared by the sampling tasks and the server.
+#[derive(Debug)]
+pub struct SensorStatuses {
+    statuses: BTreeMap<String, SensorStatus>,
+    alerts: broadcast::Sender<SensorAlert>,
+}
+
+impl Default for SensorStatuses {
+    fn default() -> Self {
+        Self::new([])
+    }
+}
+
+impl SensorStatuses {
+    pub fn new<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            statuses: names.into_iter()
+                .map(|name| (name.to_string(), SensorStatus::default()))
+                .collect(),
+            alerts: broadcast::channel(SUBSCRIBER_BACKLOG).0,
+        }
+    }
+
+    /// Receives an alert whenever a sensor starts failing or recovers from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<SensorAlert> {
+        self.alerts.subscribe()
+    }
+
+    pub fn record_success(&mut self, name: &str) {
+        let status = self.statuses.entry(name.to_string()).or_default();
+        let recovered = status.consecutive_failures > 0;
+        status.record_success(SystemTime::now());
+        if recovered {
+            // nobody listening is not an error
+            let _ = self.alerts.send(SensorAlert::Recovered { sensor: name.to_string() });
+        }
+    }
+
+    pub fn record_failure(&mut self, name: &str, error: &str) {
+        let status = self.statuses.entry(name.to_string()).or_default();
+        let failing = status.consecutive_failures == 0;
+        status.record_failure(error);
+        if failing {
+            let _ = self.alerts.send(SensorAlert::Failing { sensor: name.to_string(), error: error.to_string() });
+        }
+    }
+
+    pub fn record_rejection(&mut self, name: &str) {
+        self.statuses.entry(name.to_string()).or_default().rejected += 1;
+    }
+
+    pub fn record_battery(&mut self, name: &str, level: f64) {
+        self.statuses.entry(name.to_string()).or_default().battery = Some(level);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SensorStatus> {
+        self.statuses.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &SensorStatus)> {
+        self.statuses.iter().map(|(name, status)| (name.as_str(), status))
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use log::{error, info, warn};
+use regex::Regex;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio_serial::SerialPortBuilderExt;
+use crate::app_error::AppError;
+use crate::config::Calibration;
+use crate::sensor_status::SensorStatuses;
+use crate::sample_filter::SampleFilter;
+use crate::backend::SharedBackend;
+use crate::temp_sensor::parse_regex;
+
+/// A device printing one reading per line to a serial port, e.g. an Arduino.
+#[derive(Clone)]
+pub struct SerialSensor {
+    pub name: String,
+    pub device: String,
+    pub baud_rate: u32,
+    /// Seconds between stored readings.
+    pub sampling_interval: u64,
+    /// Needs a `temperature` named group, `humidity` and any other group are stored as metrics.
+    pub re: Regex,
+    pub calibration: Calibration,
+    pub filter: SampleFilter,
+}
+
+pub async fn run_serial(
+    mut sensor: SerialSensor,
+    storage: SharedBackend,
+    statuses: Arc<Mutex<SensorStatuses>>) {
+    loop {
+        if let Err(e) = listen(&mut sensor, &storage, &statuses).await {
+            error!("{}: serial port {} failed: {}", sensor.name, sensor.device, e);
+            if let Ok(mut statuses) = statuses.lock() {
+                statuses.record_failure(&sensor.name, &e.to_string());
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    }
+}
+
+async fn listen(
+    sensor: &mut SerialSensor,
+    storage: &SharedBackend,
+    statuses: &Arc<Mutex<SensorStatuses>>) -> Result<(), AppError> {
+    // the port stays open, reopening it resets most Arduinos
+    let port = tokio_serial::new(&sensor.device, sensor.baud_rate)
+        .open_native_async()
+        .map_err(|e| AppError::TemperatureSensorError(format!("failed to open {}: {}", sensor.device, e)))?;
+    info!("{}: reading {} at {} baud", sensor.name, sensor.device, sensor.baud_rate);
+
+    // devices print faster than the sampling interval, store at most one reading per interval
+    let mut last_stored: Option<Instant> = None;
+    let mut lines = BufReader::new(port).lines();
+    while let Some(line) = lines.next_line().await? {
+        if last_stored.is_some_and(|time| time.elapsed() < Duration::from_secs(sensor.sampling_interval)) {
+            continue;
+        }
+        let mut val = match parse_regex(&sensor.re, line.trim()) {
+            Ok(val) => val,
+            Err(e) => {
+                // boot messages and partial lines after opening the port
+                warn!("{}: {} in {:?}", sensor.name, e, line);
+                continue;
+            }
+        };
+        last_stored = Some(Instant::now());
+        val.temperature = sensor.calibration.apply(val.temperature);
+        if let Err(reason) = sensor.filter.check(val.temperature) {
+            warn!("{}: rejected reading: {} ({} rejected so far)", sensor.name, reason, sensor.filter.rejected());
+            if let Ok(mut statuses) = statuses.lock() {
+                statuses.record_rejection(&sensor.name);
+            }
+            continue;
+        }
+        if let Ok(mut statuses) = statuses.lock() {
+            statuses.record_success(&sensor.name);
+        }
+        if let Ok(mut storage) = storage.write() {
+            if let Err(e) = storage.append(&sensor.name, val.into_sample(SystemTime::now())) {
+                error!("{}: failed to store sample: {}", sensor.name, e);
+            }
+        } else {
+            error!("failed to lock storage");
+        }
+    }
+    Err(AppError::TemperatureSensorError(format!("{} closed", sensor.device)))
+}
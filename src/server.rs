@@ -1,117 +1,971 @@
 
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
-use crate::config::Config;
-use crate::app_error::AppError;
-use crate::storage::{Storage, StorageError, Sample};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use crate::config::{Config, CorsConfig, DashboardAuth, InfluxConfig, SensorSource, TlsConfig};
+use crate::app_error::{AppError, ErrorDetails};
+use crate::backend::StorageBackend;
+use crate::annotations::{Annotation, Annotations};
+use crate::backlog::Backlog;
+use crate::storage::{is_valid_metric_name, Bucket, Cursor, Day, Rate, Storage, StoredSample, Sample, HUMIDITY, MAX_POINTS, TEMPERATURE};
+use crate::sensor_status::{SensorStatus, SensorStatuses};
+use crate::alerts::{ActiveAlert, Alerts, Delivery, DeliveryLog};
+use crate::discovery::Discovered;
+use crate::export::{Export, ExportFormat};
+use crate::stats::{Stats, Trend};
+use crate::chart::{local_time, metric_label, time_format, Chart, Line, MAX_SIZE, MIN_SIZE};
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme};
 
-use axum::{routing::{get}, extract::{State, Query, Path}, Router, Json};
-use axum::response::{Html, Response};
-use axum::http::{StatusCode, header};
+use axum::{routing::{get, post}, extract::{State, Query, Path}, Router, Json};
+use axum::body::Body;
+use axum::extract::Request;
+use axum::middleware::{self, Next};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures_util::Stream;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri, header};
+use axum::http::uri::Authority;
+use axum_server::Handle;
+use axum_server::tls_rustls::RustlsConfig;
 // use axum::serve;
 use tokio::net::TcpListener;
-use chrono::{DateTime, Local};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinSet;
+use socket2::{Domain, Protocol, Socket, Type};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use chrono::{DateTime, Days, Local, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Bounds of the `/temps` `interval`, so a query cannot make the server average millions of buckets.
+const MIN_INTERVAL_SECS: u64 = 10;
+const MAX_INTERVAL_SECS: u64 = 86400;
+const MAX_BUCKETS: u64 = MAX_POINTS as u64;
+/// Further metrics a `/temps` query may average alongside its `metric`.
+const MAX_EXTRA_METRICS: usize = 8;
+/// Size of `/chart.png` in pixels by default.
+const DEFAULT_CHART_WIDTH: u32 = 800;
+const DEFAULT_CHART_HEIGHT: u32 = 400;
+/// Longest `/daily` range, a year.
+const MAX_DAYS: u64 = 366;
+/// Seconds between the comments keeping an idle `/events` stream open.
+const HEARTBEAT_SECS: u64 = 15;
+/// Samples per `/api/samples` page by default and at most.
+const DEFAULT_PAGE_SIZE: usize = 1000;
+const MAX_PAGE_SIZE: usize = 10_000;
+/// Seconds in-flight requests get to complete after SIGTERM or SIGINT before the server stops anyway.
+const SHUTDOWN_GRACE_SECS: u64 = 10;
+/// Major version of the `/api/v1` endpoints. Within it fields are only added; renaming or
+/// removing one, or changing its meaning, needs a new `/api/v2` namespace.
+const API_VERSION: &str = "1";
+const API_VERSION_HEADER: &str = "api-version";
+/// Header carrying one of the `api_keys`.
+const API_KEY_HEADER: &str = "x-api-key";
 
 #[derive(Clone)]
 struct AppState {
-    storage: Arc<Mutex<Storage>>,
+    storage: Arc<RwLock<Storage>>,
+    statuses: Arc<Mutex<SensorStatuses>>,
+    alerts: Arc<Mutex<Alerts>>,
+    deliveries: Arc<Mutex<DeliveryLog>>,
+    discovered: Arc<Mutex<Discovered>>,
+    discovery: bool,
+    default_sensor: String,
+    settings: Arc<RwLock<Settings>>,
+    /// Configuration file re-read by `/api/admin/reload`.
+    config_path: PathBuf,
+    running: Arc<RwLock<RunningConfig>>,
+    push_sensors: Vec<String>,
+    /// Shelly device id to sensor name.
+    shelly_devices: BTreeMap<String, String>,
+    influx: InfluxConfig,
+    annotations: Arc<Mutex<Annotations>>,
+    /// Zone of the `/daily` days, the system one when `None`.
+    timezone: Option<Tz>,
+    started: Instant,
+    /// When the binary with the built-in dashboard files was written, their `Last-Modified`.
+    assets_modified: Option<SystemTime>,
+    /// Turns `true` on SIGTERM or SIGINT and ends the `/ws` and `/events` streams.
+    shutdown: watch::Receiver<bool>,
+}
+
+/// The configuration the server runs with, shown by `/api/config`: the file read at startup
+/// with the settings of every reload applied.
+struct RunningConfig {
+    config: Config,
+    loaded_at: SystemTime,
+    reloaded_at: Option<SystemTime>,
+}
+
+/// Settings `/api/admin/reload` replaces without a restart.
+struct Settings {
+    push_token: Option<String>,
+    admin_token: Option<String>,
+    api_keys: Vec<String>,
+    dashboard_auth: Option<DashboardAuth>,
+    outdoor_sensor: Option<String>,
+    /// Age of the newest sample beyond which `/health` fails.
+    stale_after: Duration,
+    detailed_errors: bool,
+}
+
+impl From<&Config> for Settings {
+    fn from(config: &Config) -> Self {
+        Self {
+            push_token: config.push_token.clone(),
+            admin_token: config.admin_token.clone(),
+            api_keys: config.server.api_keys.clone(),
+            dashboard_auth: config.server.dashboard_auth.clone(),
+            outdoor_sensor: config.outdoor_sensor.clone(),
+            stale_after: Duration::from_secs(config.stale_after),
+            detailed_errors: config.server.detailed_errors,
+        }
+    }
+}
+
+/// Settings applied by `/api/admin/reload`, those of `Settings` and `Storage::reload`; any
+/// other change needs a restart.
+const RELOADED_SETTINGS: &[&str] = &[
+    "push_token", "admin_token", "server.api_keys", "server.dashboard_auth", "outdoor_sensor", "stale_after", "server.detailed_errors",
+    "max_capacity", "max_memory_mb", "retention_days", "downsampling", "averaging_interval", "carry_forward_buckets", "max_points",
+];
+
+#[derive(Deserialize, ToSchema)]
+struct PushedMeasurement {
+    sensor: Option<String>,
+    temperature: f64,
+    humidity: Option<f64>,
+    /// Further metrics by name, e.g. `co2`.
+    #[serde(default)]
+    metrics: BTreeMap<String, f64>,
+    /// Unix seconds, defaults to the time of the request.
+    timestamp: Option<u64>,
 }
 
+/// Query of a Shelly H&T report, Gen1 appends `id`, `temp` and `hum` to the action URL.
 #[derive(Deserialize)]
+struct ShellyReport {
+    token: Option<String>,
+    id: String,
+    temp: Option<f64>,
+    hum: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct ApiKeyQuery {
+    api_key: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct TempsQuery {
     hours: Option<u64>,
+    /// Start of the range, Unix seconds or RFC 3339, instead of `hours` before `to`.
+    from: Option<String>,
+    /// End of the range, Unix seconds or RFC 3339, defaults to now.
+    to: Option<String>,
+    sensor: Option<String>,
+    metric: Option<String>,
+    /// Averaging bucket in seconds, defaults to `averaging_interval`, coarser for a range it
+    /// would split into more than `max_points` values.
+    interval: Option<u64>,
+    /// Further metrics averaged over the same buckets, comma-separated, e.g. `humidity,dew_point`.
+    metrics: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExportQuery {
+    sensor: Option<String>,
+    /// Unix seconds, default: the oldest sample.
+    from: Option<u64>,
+    /// Unix seconds, default: now.
+    to: Option<u64>,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct NewAnnotation {
+    text: String,
+    sensor: Option<String>,
+    /// Unix seconds, defaults to the time of the request.
+    timestamp: Option<u64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SamplesQuery {
+    sensor: Option<String>,
+    /// Unix seconds, default: the oldest sample.
+    from: Option<u64>,
+    /// Unix seconds, default: now.
+    to: Option<u64>,
+    /// Samples per page, defaults to `DEFAULT_PAGE_SIZE`.
+    limit: Option<usize>,
+    /// `next_cursor` of the previous page, continues after it instead of at `from`.
+    cursor: Option<String>,
+}
+
+/// A raw sample of `/api/samples`.
+#[derive(Serialize, ToSchema)]
+struct RawSample {
+    /// Unix seconds.
+    timestamp: f64,
+    temperature: f64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metrics: BTreeMap<String, f64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SamplesResponse {
+    sensor: String,
+    samples: Vec<RawSample>,
+    /// Continues after the last sample, also to fetch samples stored later.
+    next_cursor: Option<String>,
+    /// More samples in the range follow.
+    more: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DeleteQuery {
+    sensor: Option<String>,
+    /// Unix seconds, both inclusive.
+    from: u64,
+    to: u64,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PurgeQuery {
+    /// Only this sensor, default: all of them.
+    sensor: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct FlushResponse {
+    status: &'static str,
+    /// Bytes of the backlog file after the flush, `null` without a backlog.
+    backlog_bytes: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PurgeResponse {
+    status: &'static str,
+    /// Samples dropped from memory.
+    purged: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ReloadResponse {
+    status: &'static str,
+    /// Settings taken from the re-read file, any other change needs a restart.
+    reloaded: Vec<&'static str>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ConfigResponse {
+    /// The configuration file, re-read by `/api/admin/reload`.
+    path: String,
+    /// Unix seconds the file was read at startup.
+    loaded_at: u64,
+    /// Unix seconds of the last `/api/admin/reload`.
+    reloaded_at: Option<u64>,
+    /// The settings in effect, passwords, tokens and keys as `"<redacted>"`.
+    #[schema(value_type = Object)]
+    config: Config,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ChartQuery {
+    hours: Option<u64>,
+    /// Start of the range, Unix seconds or RFC 3339, instead of `hours` before `to`.
+    from: Option<String>,
+    /// End of the range, Unix seconds or RFC 3339, defaults to now.
+    to: Option<String>,
+    sensor: Option<String>,
+    metric: Option<String>,
+    /// Averaging bucket in seconds, chosen for the range like by `/temps` by default.
+    interval: Option<u64>,
+    /// Image size in pixels, 800 × 400 by default.
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RateQuery {
+    hours: Option<u64>,
+    /// Minutes per rate, defaults to 60.
+    window: Option<u64>,
+    sensor: Option<String>,
+    metric: Option<String>,
 }
 
+#[derive(Serialize, ToSchema)]
+struct RateResponse {
+    sensor: String,
+    metric: String,
+    window_minutes: u64,
+    rates: Vec<Rate>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DailyQuery {
+    /// Days including today, defaults to 7.
+    days: Option<u64>,
+    sensor: Option<String>,
+    metric: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DailyResponse {
+    sensor: String,
+    metric: String,
+    timezone: String,
+    days: Vec<Day>,
+}
+
+#[derive(Deserialize)]
+struct LiveQuery {
+    /// Only samples and alerts of this sensor, default: all of them.
+    sensor: Option<String>,
+}
+
+/// A newly stored sample as pushed by `/ws`.
 #[derive(Serialize)]
+struct LiveSample {
+    sensor: String,
+    /// Unix seconds.
+    timestamp: u64,
+    temperature: f64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metrics: BTreeMap<String, f64>,
+}
+
+impl From<StoredSample> for LiveSample {
+    fn from(stored: StoredSample) -> Self {
+        Self {
+            timestamp: unix_secs(&stored.sample),
+            temperature: stored.sample.temperature,
+            metrics: stored.sample.metrics,
+            sensor: stored.series,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SummaryQuery {
+    sensor: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SummaryResponse {
+    sensor: String,
+    /// Temperature of the newest sample.
+    current: Option<f64>,
+    /// Unix seconds of the newest sample.
+    current_at: Option<u64>,
+    /// Temperature over the last 24 hours.
+    min: Option<f64>,
+    max: Option<f64>,
+    mean: Option<f64>,
+    /// Change over the last hour.
+    trend: Option<Trend>,
+    trend_per_hour: Option<f64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct StatsQuery {
+    hours: Option<u64>,
+    sensor: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct StatsResponse {
+    sensor: String,
+    hours: u64,
+    /// Statistics of `temperature`, `humidity` and any further metric in the range.
+    stats: BTreeMap<String, Stats>,
+}
+
+#[derive(Serialize, ToSchema)]
 struct TempsResponse {
+    sensor: String,
+    metric: String,
+    /// Unix seconds of the queried range.
+    from: u64,
+    to: u64,
     temperatures: Vec<Option<f64>>,
+    /// `temperatures` with the readings per bucket and explicit gap flags.
+    buckets: Vec<Bucket>,
+    /// Values of each of the requested `metrics`, index by index with `buckets`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metrics: BTreeMap<String, Vec<Option<f64>>>,
     latest_time: Option<u64>,
     oldest_time: Option<u64>,
+    /// Seconds between the values of `temperatures`, the requested `interval` or the one
+    /// chosen for the range.
+    interval: u64,
     interval_minutes: u64,
     count: usize,
     last_t: Option<String>,
     last: Option<Sample>,
+    status: Option<SensorStatusResponse>,
+    outdoor: Option<OutdoorResponse>,
+    /// Annotations of the sensor or of all sensors in the range, oldest first.
+    annotations: Vec<Annotation>,
+}
+
+/// The outdoor reference series over the same range, for comparing against the indoor curve.
+#[derive(Serialize, ToSchema)]
+struct OutdoorResponse {
+    sensor: String,
+    temperatures: Vec<Option<f64>>,
+    buckets: Vec<Bucket>,
+    latest_time: Option<u64>,
+    oldest_time: Option<u64>,
+    last: Option<Sample>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SensorResponse {
+    name: String,
+    #[serde(flatten)]
+    status: SensorStatusResponse,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SensorsResponse {
+    sensors: Vec<SensorResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct AlertsResponse {
+    /// Alerts active now, as they fired.
+    alerts: Vec<ActiveAlert>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DeliveriesResponse {
+    /// Newest first.
+    deliveries: Vec<Delivery>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct HealthResponse {
+    /// `ok`, or `stale` when a sensor has no sample newer than `stale_after`.
+    status: &'static str,
+    uptime_secs: u64,
+    /// Samples of all series in memory.
+    samples: usize,
+    memory_bytes: usize,
+    sensors: Vec<SensorHealth>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SensorHealth {
+    name: String,
+    last_poll_ok: bool,
+    last_success: Option<u64>,
+    newest_sample_age_secs: Option<u64>,
+    stale: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SensorStatusResponse {
+    last_success: Option<u64>,
+    consecutive_failures: u32,
+    total_failures: u64,
+    last_error: Option<String>,
+    battery: Option<f64>,
+    rejected: u64,
+}
+
+impl From<&SensorStatus> for SensorStatusResponse {
+    fn from(status: &SensorStatus) -> Self {
+        Self {
+            last_success: status.last_success.map(|time| time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()),
+            consecutive_failures: status.consecutive_failures,
+            total_failures: status.total_failures,
+            last_error: status.last_error.clone(),
+            battery: status.battery,
+            rejected: status.rejected,
+        }
+    }
+}
+
+/// The JSON API as served at `/api/v1/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "heater-monitor", description = "Temperature and humidity readings of the configured sensors."),
+    paths(
+        temps, chart_png, rate, daily, stats, summary, health, export,
+        samples, delete_samples, push_measurement, add_annotation, sensors, active_alerts, acknowledge_alert, alert_deliveries, discovered_devices,
+        flush, purge, reload, running_config,
+    ),
+    modifiers(&SecuritySchemes),
+    security((), ("api_key" = [])),
+)]
+struct ApiDoc;
+
+/// The `X-API-Key` of `api_keys` and the bearer tokens of the push and admin endpoints.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("api_key", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))));
+        components.add_security_scheme("push_token", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+        components.add_security_scheme("admin_token", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
 }
 
 pub async fn run_server(
-    storage: Arc<Mutex<Storage>>,
-    config: &Config) -> Result<(), AppError> {
-    let state = AppState { storage };
+    storage: Arc<RwLock<Storage>>,
+    statuses: Arc<Mutex<SensorStatuses>>,
+    alerts: Arc<Mutex<Alerts>>,
+    deliveries: Arc<Mutex<DeliveryLog>>,
+    discovered: Arc<Mutex<Discovered>>,
+    config: &Config,
+    config_path: PathBuf) -> Result<(), AppError> {
+    let default_sensor = config.sensors()
+        .first()
+        .map(|sensor| sensor.name.clone())
+        .unwrap_or_default();
+    let push_sensors = config.sensors()
+        .into_iter()
+        .filter(|sensor| matches!(sensor.source, SensorSource::Push))
+        .map(|sensor| sensor.name)
+        .collect();
+    let shelly_devices = config.sensors()
+        .into_iter()
+        .filter_map(|sensor| match sensor.source {
+            SensorSource::Shelly { device } => Some((device, sensor.name)),
+            _ => None,
+        })
+        .collect();
+    let (stop, shutdown) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        log::info!("Stopping the server, waiting for running requests");
+        let _ = stop.send(true);
+    });
+    let state = AppState {
+        storage,
+        statuses,
+        alerts,
+        deliveries,
+        discovered,
+        discovery: config.discovery,
+        default_sensor,
+        settings: Arc::new(RwLock::new(Settings::from(config))),
+        config_path,
+        running: Arc::new(RwLock::new(RunningConfig {
+            config: config.clone(),
+            loaded_at: SystemTime::now(),
+            reloaded_at: None,
+        })),
+        push_sensors,
+        shelly_devices,
+        influx: config.influx.clone(),
+        annotations: Arc::new(Mutex::new(Annotations::open(config.annotations.as_deref())?)),
+        timezone: config.time_zone(),
+        started: Instant::now(),
+        assets_modified: std::env::current_exe()
+            .and_then(|exe| exe.metadata())
+            .and_then(|metadata| metadata.modified())
+            .ok(),
+        shutdown: shutdown.clone(),
+    };
     let app = Router::new()
         .route("/", get(index))
-        .route("/temps", get(temps))
+        .route("/api/docs", get(api_docs))
+        .route("/health", get(health))
         .route("/assets/{*file}", get(serve_asset))
+        .nest("/api/v1", api_routes().layer(middleware::from_fn(api_version)))
+        .merge(legacy_routes().layer(middleware::from_fn(deprecated)))
         .fallback(get(fallback))
+        .layer(middleware::from_fn_with_state(state.clone(), require_api_key))
+        .layer(middleware::from_fn_with_state(state.clone(), require_dashboard_login))
+        .layer(middleware::from_fn_with_state(state.clone(), detail_errors))
+        // gzip, deflate or brotli as the client accepts; skips small bodies, images and SSE
+        .layer(CompressionLayer::new())
         .with_state(state);
+    let app = if config.server.access_log {
+        app.layer(middleware::from_fn(log_access))
+    } else {
+        app
+    };
+    let app = match &config.server.cors {
+        Some(cors) => app.layer(cors_layer(cors)),
+        None => app,
+    };
 
 
-    let addr = format!("{}:{}", config.listen_address, config.port);
-    let listener = TcpListener::bind(&addr).await?;
+    // every address is bound before serving any, so a taken port fails the startup
+    let addrs = config.listen_addrs()?;
+    let v6_only = addrs.len() > 1;
+    let listeners = addrs.into_iter()
+        .map(|addr| Ok((addr, bind(addr, v6_only)?)))
+        .collect::<Result<Vec<_>, AppError>>()?;
+    if let Some(tls) = &config.server.tls {
+        return serve_tls(app, listeners, tls, shutdown).await;
+    }
 
-    axum::serve(listener, app)
-        .await
-        .context("Server error")?;
+    let mut servers = JoinSet::new();
+    for (addr, listener) in listeners {
+        let listener = TcpListener::from_std(listener)
+            .with_context(|| format!("cannot listen on {}", addr))?;
+        log::info!("Listening on http://{}", addr);
+        let server = axum::serve(listener, app.clone()).with_graceful_shutdown(stopped(shutdown.clone()));
+        servers.spawn(async move { server.await });
+    }
+    tokio::select! {
+        result = join_servers(&mut servers) => result?,
+        _ = grace_period(shutdown) => log::warn!("Requests still running after {}s, stopping anyway", SHUTDOWN_GRACE_SECS),
+    }
     Ok(())
 }
 
-async fn index() -> Html<&'static str> {
-    Html(include_str!("../assets/index.html"))
+/// Listening socket on `addr`. With `v6_only` an IPv6 address leaves IPv4 to a listener of its
+/// own, e.g. `::` next to `0.0.0.0`, which would otherwise both take the IPv4 connections.
+fn bind(addr: SocketAddr, v6_only: bool) -> Result<std::net::TcpListener, AppError> {
+    let listen = || -> std::io::Result<std::net::TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        if addr.is_ipv6() && v6_only {
+            socket.set_only_v6(true)?;
+        }
+        // like `TcpListener::bind`, a restart need not wait for the old connections to time out
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(1024)?;
+        Ok(socket.into())
+    };
+    Ok(listen().with_context(|| format!("cannot listen on {}", addr))?)
+}
+
+/// Waits for the server of every listener, the first failing one fails the whole.
+async fn join_servers(servers: &mut JoinSet<std::io::Result<()>>) -> Result<(), AppError> {
+    while let Some(result) = servers.join_next().await {
+        result.context("Server task failed")?.context("Server error")?;
+    }
+    Ok(())
+}
+
+/// Resolves once the shutdown signal arrived.
+async fn stopped(mut shutdown: watch::Receiver<bool>) {
+    // a dropped sender has sent `true` already
+    let _ = shutdown.wait_for(|stopped| *stopped).await;
+}
+
+/// Resolves `SHUTDOWN_GRACE_SECS` after the shutdown signal.
+async fn grace_period(shutdown: watch::Receiver<bool>) {
+    stopped(shutdown).await;
+    tokio::time::sleep(Duration::from_secs(SHUTDOWN_GRACE_SECS)).await;
+}
+
+/// The machine endpoints, served under `/api/v1`.
+fn api_routes() -> Router<AppState> {
+    Router::new()
+        .route("/temps", get(temps))
+        .route("/temps.csv", get(temps_csv))
+        .route("/chart.png", get(chart_png))
+        .route("/measurements", post(push_measurement))
+        .route("/samples", get(samples).delete(delete_samples))
+        .route("/annotations", post(add_annotation))
+        .route("/shelly", get(shelly_report))
+        .route("/discovery", get(discovered_devices))
+        .route("/sensors", get(sensors))
+        .route("/alerts", get(active_alerts))
+        .route("/alerts/deliveries", get(alert_deliveries))
+        .route("/alerts/{id}/ack", post(acknowledge_alert))
+        .route("/admin/flush", post(flush))
+        .route("/admin/purge", post(purge))
+        .route("/admin/reload", post(reload))
+        .route("/config", get(running_config))
+        .route("/openapi.json", get(openapi))
+        .route("/ws", get(live_updates))
+        .route("/events", get(events))
+        .route("/export", get(export))
+        .route("/stats", get(stats))
+        .route("/rate", get(rate))
+        .route("/daily", get(daily))
+        .route("/summary", get(summary))
+}
+
+/// The paths of the machine endpoints before `/api/v1`, kept for existing scripts and devices.
+fn legacy_routes() -> Router<AppState> {
+    Router::new()
+        .route("/temps", get(temps))
+        .route("/temps.csv", get(temps_csv))
+        .route("/api/measurements", post(push_measurement))
+        .route("/api/samples", get(samples).delete(delete_samples))
+        .route("/api/annotations", post(add_annotation))
+        .route("/api/shelly", get(shelly_report))
+        .route("/api/discovery", get(discovered_devices))
+        .route("/api/sensors", get(sensors))
+        .route("/api/admin/flush", post(flush))
+        .route("/api/admin/purge", post(purge))
+        .route("/api/admin/reload", post(reload))
+        .route("/api/openapi.json", get(openapi))
+        .route("/ws", get(live_updates))
+        .route("/events", get(events))
+        .route("/export", get(export))
+        .route("/stats", get(stats))
+        .route("/rate", get(rate))
+        .route("/daily", get(daily))
+        .route("/summary", get(summary))
+}
+
+/// Sends `API_VERSION` with every `/api/v1` response.
+async fn api_version(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(HeaderName::from_static(API_VERSION_HEADER), HeaderValue::from_static(API_VERSION));
+    response
+}
+
+/// Marks a response of a legacy path deprecated and links its `/api/v1` successor.
+async fn deprecated(request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    let successor = format!("</api/v1{}>; rel=\"successor-version\"", path.strip_prefix("/api").unwrap_or(path));
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(HeaderName::from_static("deprecation"), HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        response.headers_mut().insert(header::LINK, link);
+    }
+    response
+}
+
+/// Serves `app` over HTTPS on every listener, and redirects plain HTTP on the same addresses to
+/// it when `redirect_http_port` is set.
+async fn serve_tls(app: Router, listeners: Vec<(SocketAddr, std::net::TcpListener)>, tls: &TlsConfig, shutdown: watch::Receiver<bool>) -> Result<(), AppError> {
+    let rustls = RustlsConfig::from_pem_file(&tls.cert, &tls.key).await
+        .with_context(|| format!("cannot load TLS certificate {} and key {}", tls.cert, tls.key))?;
+    let v6_only = listeners.len() > 1;
+    let mut servers = JoinSet::new();
+    for (addr, listener) in listeners {
+        if let Some(port) = tls.redirect_http_port {
+            let redirect_addr = SocketAddr::new(addr.ip(), port);
+            let listener = TcpListener::from_std(bind(redirect_addr, v6_only)?)
+                .with_context(|| format!("cannot listen on {}", redirect_addr))?;
+            let https_port = addr.port();
+            let redirect = Router::new().fallback(move |headers: HeaderMap, uri: Uri| redirect_to_https(headers, uri, https_port));
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                if let Err(e) = axum::serve(listener, redirect).with_graceful_shutdown(stopped(shutdown)).await {
+                    log::error!("HTTP redirect server failed: {}", e);
+                }
+            });
+            log::info!("Redirecting http://{} to HTTPS", redirect_addr);
+        }
+
+        let handle = Handle::new();
+        tokio::spawn({
+            let handle = handle.clone();
+            let shutdown = shutdown.clone();
+            async move {
+                stopped(shutdown).await;
+                handle.graceful_shutdown(Some(Duration::from_secs(SHUTDOWN_GRACE_SECS)));
+            }
+        });
+        log::info!("Listening on https://{}", addr);
+        servers.spawn(axum_server::from_tcp_rustls(listener, rustls.clone())
+            .handle(handle)
+            .serve(app.clone().into_make_service()));
+    }
+    join_servers(&mut servers).await
+}
+
+/// The same host and path on HTTPS `port`.
+async fn redirect_to_https(headers: HeaderMap, uri: Uri, port: u16) -> Result<Redirect, StatusCode> {
+    let authority = headers.get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let path = uri.path_and_query().map(|path| path.as_str()).unwrap_or("/");
+    let location = match port {
+        443 => format!("https://{}{}", authority.host(), path),
+        port => format!("https://{}:{}{}", authority.host(), port, path),
+    };
+    Ok(Redirect::permanent(&location))
 }
 
-async fn serve_asset(Path(file): Path<String>) -> Result<Response, StatusCode> {
+/// Allows the configured origins to call the API with the configured methods, sending the
+/// `Authorization`, `Content-Type` and `X-API-Key` headers.
+fn cors_layer(cors: &CorsConfig) -> CorsLayer {
+    let origins = if cors.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(cors.allowed_origins.iter().filter_map(|origin| origin.parse::<HeaderValue>().ok()))
+    };
+    let methods: Vec<Method> = cors.allowed_methods.iter()
+        .filter_map(|method| method.to_uppercase().parse().ok())
+        .collect();
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, HeaderName::from_static(API_KEY_HEADER)])
+}
+
+/// Resolves on SIGINT or SIGTERM, so running requests complete and buffered backlog records
+/// are written before exit.
+async fn shutdown_signal() {
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                log::error!("failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+async fn index(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    // revalidated on every load, so a new version shows up right after an upgrade
+    static_file(&headers, include_str!("../assets/index.html"), "text/html; charset=utf-8", "no-cache", state.assets_modified)
+}
+
+/// Swagger UI of `/api/v1/openapi.json`.
+async fn api_docs(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    static_file(&headers, include_str!("../assets/api-docs.html"), "text/html; charset=utf-8", "no-cache", state.assets_modified)
+}
+
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+async fn serve_asset(State(state): State<AppState>, headers: HeaderMap, Path(file): Path<String>) -> Result<Response, StatusCode> {
     match file.as_str() {
         "chartjs-adapter-date-fns.bundle.min.js" => {
             let content = include_str!("../assets/chartjs-adapter-date-fns.bundle.min.js");
-            Ok(Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, "application/javascript")
-                .body(content.into())
-                .unwrap())
+            Ok(static_file(&headers, content, "application/javascript", "public, max-age=86400", state.assets_modified))
         }
         _ => Err(StatusCode::NOT_FOUND)
     }
 }
 
+/// A file built into the binary with an `ETag` of its content and the binary's modification
+/// time as `Last-Modified`, or 304 when the browser's copy is still current.
+fn static_file(headers: &HeaderMap, content: &'static str, content_type: &str, cache_control: &str, modified: Option<SystemTime>) -> Response {
+    let etag = format!("\"{:08x}\"", crc32fast::hash(content.as_bytes()));
+    let last_modified = modified.map(|modified| DateTime::<Utc>::from(modified).format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+    let not_modified = match headers.get(header::IF_NONE_MATCH).and_then(|value| value.to_str().ok()) {
+        Some(tags) => tags.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"),
+        None => headers.get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            .zip(modified)
+            .is_some_and(|(since, modified)| DateTime::<Utc>::from(modified).timestamp() <= since.timestamp()),
+    };
+    let mut response = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, cache_control);
+    if let Some(last_modified) = &last_modified {
+        response = response.header(header::LAST_MODIFIED, last_modified);
+    }
+    let response = if not_modified {
+        response.status(StatusCode::NOT_MODIFIED).body(Body::empty())
+    } else {
+        response.header(header::CONTENT_TYPE, content_type).body(Body::from(content))
+    };
+    response.unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+#[utoipa::path(get, path = "/api/v1/temps", params(TempsQuery), responses(
+    (status = 200, description = "Values of a metric averaged per interval", body = TempsResponse),
+    (status = 400, description = "Invalid range or interval"),
+    (status = 404, description = "Unknown sensor or a range outside the stored samples"),
+))]
 async fn temps(
     State(state): State<AppState>,
     Query(params): Query<TempsQuery>
 ) -> Result<Json<TempsResponse>, AppError> {
-    let hours = params.hours.unwrap_or(3);
-    let now = SystemTime::now();
-    let from = now - Duration::from_secs(hours * 3600);
-    
-    let storage = state.storage.lock()?;
-    let temperatures = storage.per_minute_avg_fill(from, now)
-        .map_err(|e| match e {
-            StorageError::InvalidTimeRange => AppError::InternalError("Invalid time range".to_string()),
-            StorageError::NoDataAvailable => AppError::InternalError("No data available for the requested time range".to_string()),
-        })?;
-    let last_sample = storage.get_last_sample().cloned();
+    let sensor = params.sensor.clone().unwrap_or(state.default_sensor);
+    let metric = params.metric.clone().unwrap_or_else(|| TEMPERATURE.to_string());
+    let storage = state.storage.read()?;
+    let TempsBuckets { from, to, interval, buckets, metrics } = temps_buckets(&params, &storage, &sensor, &metric, SystemTime::now())?;
+    let (from_secs, to_secs) = (from.duration_since(SystemTime::UNIX_EPOCH)?.as_secs(), to.duration_since(SystemTime::UNIX_EPOCH)?.as_secs());
+    let series = storage.series(&sensor)
+        .ok_or_else(|| AppError::NotFound(format!("unknown sensor {}", sensor)))?;
+    let temperatures = bucket_values(&buckets);
+    let last_sample = series.get_last_sample().cloned();
     
     // Get the timestamps of the latest and oldest actual measurements
-    let latest_time = storage.latest_sample()
-        .map(|sample| sample.timestamp
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs());
+    let latest_time = series.latest_sample().map(unix_secs);
     
-    let oldest_time = storage.oldest_sample()
-        .map(|sample| sample.timestamp
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs());
+    let oldest_time = series.oldest_sample().map(unix_secs);
 
 
 
+    let status = state.statuses.lock()?
+        .get(&sensor)
+        .map(SensorStatusResponse::from);
+    let annotations = state.annotations.lock()?
+        .in_range(&sensor, from_secs, to_secs);
+
+    let outdoor = state.settings.read()?.outdoor_sensor.clone()
+        .filter(|outdoor| *outdoor != sensor)
+        .and_then(|outdoor| {
+            let series = storage.series(&outdoor)?;
+            // no outdoor data in the range is not an error for the indoor curve
+            let buckets = series.metric_buckets(&metric, from, to, interval, storage.carry_forward()).ok()?;
+            Some(OutdoorResponse {
+                temperatures: bucket_values(&buckets),
+                buckets,
+                latest_time: series.latest_sample().map(unix_secs),
+                oldest_time: series.oldest_sample().map(unix_secs),
+                last: series.get_last_sample().cloned(),
+                sensor: outdoor,
+            })
+        });
+
     let response = TempsResponse {
+        sensor,
+        metric,
+        from: from_secs,
+        to: to_secs,
         count: temperatures.len(),
         latest_time,
         oldest_time,
-        interval_minutes: 1,
+        interval: interval.as_secs(),
+        interval_minutes: interval.as_secs() / 60,
         temperatures,
+        buckets,
+        metrics: metrics.into_iter()
+            .map(|(name, buckets)| (name, bucket_values(&buckets)))
+            .collect(),
         last: last_sample.clone(),
+        status,
+        outdoor,
+        annotations,
         last_t: if let Some(last) = last_sample {
             let datetime: DateTime<Local> = DateTime::from(last.timestamp);
             Some(datetime.format("%Y-%m-%d %H:%M:%S").to_string())
@@ -124,6 +978,944 @@ async fn temps(
     Ok(Json(response))
 }
 
+fn bucket_values(buckets: &[Bucket]) -> Vec<Option<f64>> {
+    buckets.iter().map(|bucket| bucket.value).collect()
+}
+
+/// Change per hour of a metric in consecutive windows, e.g. how fast the house heats up.
+#[utoipa::path(get, path = "/api/v1/rate", params(RateQuery), responses(
+    (status = 200, description = "Change per hour of each window", body = RateResponse),
+    (status = 400, description = "Invalid window"),
+    (status = 404, description = "Unknown sensor"),
+))]
+async fn rate(
+    State(state): State<AppState>,
+    Query(params): Query<RateQuery>,
+) -> Result<Json<RateResponse>, AppError> {
+    let hours = params.hours.unwrap_or(24);
+    let window_minutes = params.window.unwrap_or(60);
+    let window = query_interval(window_minutes.saturating_mul(60), hours)?;
+    let sensor = params.sensor.unwrap_or(state.default_sensor);
+    let metric = params.metric.unwrap_or_else(|| TEMPERATURE.to_string());
+    let now = SystemTime::now();
+    let from = now - Duration::from_secs(hours * 3600);
+
+    let storage = state.storage.read()?;
+    let series = storage.series(&sensor)
+        .ok_or_else(|| AppError::NotFound(format!("unknown sensor {}", sensor)))?;
+    let rates = series.rate_of_change(&metric, from, now, window)?;
+    Ok(Json(RateResponse { sensor, metric, window_minutes, rates }))
+}
+
+/// Min, max and mean of a metric per calendar day of the configured time zone.
+#[utoipa::path(get, path = "/api/v1/daily", params(DailyQuery), responses(
+    (status = 200, description = "Statistics of each day, oldest first", body = DailyResponse),
+    (status = 400, description = "Invalid number of days"),
+    (status = 404, description = "Unknown sensor"),
+))]
+async fn daily(
+    State(state): State<AppState>,
+    Query(params): Query<DailyQuery>,
+) -> Result<Json<DailyResponse>, AppError> {
+    let days = params.days.unwrap_or(7);
+    if !(1..=MAX_DAYS).contains(&days) {
+        return Err(AppError::BadRequest(format!("days must be between 1 and {}", MAX_DAYS)));
+    }
+    let sensor = params.sensor.unwrap_or(state.default_sensor);
+    let metric = params.metric.unwrap_or_else(|| TEMPERATURE.to_string());
+    let now = SystemTime::now();
+
+    let storage = state.storage.read()?;
+    let series = storage.series(&sensor)
+        .ok_or_else(|| AppError::NotFound(format!("unknown sensor {}", sensor)))?;
+    let (timezone, days) = match state.timezone {
+        Some(tz) => (tz.name().to_string(), series.daily(&metric, days_start(&tz, now, days), now, &tz)?),
+        None => ("local".to_string(), series.daily(&metric, days_start(&Local, now, days), now, &Local)?),
+    };
+    Ok(Json(DailyResponse { sensor, metric, timezone, days }))
+}
+
+/// Midnight in `tz` starting the last `days` days up to `now`. A midnight skipped by a DST
+/// change falls back to counting whole days of 24 hours.
+fn days_start<Z: TimeZone>(tz: &Z, now: SystemTime, days: u64) -> SystemTime {
+    let today = DateTime::<Utc>::from(now).with_timezone(tz).date_naive();
+    today.checked_sub_days(Days::new(days - 1))
+        .and_then(|date| tz.from_local_datetime(&date.and_time(NaiveTime::MIN)).earliest())
+        .map(SystemTime::from)
+        .unwrap_or_else(|| now - Duration::from_secs(days * 86400))
+}
+
+/// Averages of a `/temps` query.
+struct TempsBuckets {
+    from: SystemTime,
+    to: SystemTime,
+    interval: Duration,
+    buckets: Vec<Bucket>,
+    /// Buckets of the further `metrics`, starting with those of `buckets`.
+    metrics: BTreeMap<String, Vec<Bucket>>,
+}
+
+/// Averages `metric` and the further `metrics` of `sensor` over the range and `interval` of
+/// the `/temps` parameters.
+fn temps_buckets(params: &TempsQuery, storage: &Storage, sensor: &str, metric: &str, now: SystemTime) -> Result<TempsBuckets, AppError> {
+    let (from, to) = temps_range(params.from.as_deref(), params.to.as_deref(), params.hours, now)?;
+    let extra_metrics = extra_metrics(params.metrics.as_deref(), metric)?;
+    let span = to.duration_since(from).unwrap_or_default();
+    let interval = match params.interval {
+        Some(secs) => query_interval(secs, span.as_secs().div_ceil(3600))?,
+        None => storage.resolution(span),
+    };
+    let series = storage.series(sensor)
+        .ok_or_else(|| AppError::NotFound(format!("unknown sensor {}", sensor)))?;
+    if params.from.is_some() || params.to.is_some() {
+        match (series.oldest_sample(), series.latest_sample()) {
+            (Some(oldest), Some(latest)) if to < oldest.timestamp || from > latest.timestamp => {
+                return Err(AppError::NotFound(format!("no samples of {} between {} and {}, data is available from {} to {}",
+                    sensor, from.duration_since(SystemTime::UNIX_EPOCH)?.as_secs(), to.duration_since(SystemTime::UNIX_EPOCH)?.as_secs(),
+                    unix_secs(oldest), unix_secs(latest))));
+            }
+            (None, _) | (_, None) => return Err(AppError::NotFound(format!("no samples of {}", sensor))),
+            _ => {}
+        }
+    }
+    let buckets = series.metric_buckets(metric, from, to, interval, storage.carry_forward())?;
+    let metrics = extra_metrics.into_iter()
+        .map(|name| {
+            let buckets = series.metric_buckets(&name, from, to, interval, storage.carry_forward())?;
+            Ok((name, buckets))
+        })
+        .collect::<Result<_, AppError>>()?;
+    Ok(TempsBuckets { from, to, interval, buckets, metrics })
+}
+
+/// The comma-separated `metrics` of a `/temps` query without `metric` itself and repeats.
+fn extra_metrics(metrics: Option<&str>, metric: &str) -> Result<Vec<String>, AppError> {
+    let mut names: Vec<String> = Vec::new();
+    for name in metrics.unwrap_or_default().split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        if name != TEMPERATURE && !is_valid_metric_name(name) {
+            return Err(AppError::BadRequest(format!("invalid metric name {:?}", name)));
+        }
+        if name != metric && !names.iter().any(|known| known == name) {
+            names.push(name.to_string());
+        }
+    }
+    if names.len() > MAX_EXTRA_METRICS {
+        return Err(AppError::BadRequest(format!("at most {} metrics besides metric", MAX_EXTRA_METRICS)));
+    }
+    Ok(names)
+}
+
+/// The `/temps` averages as CSV, `timestamp,<metric>,count` and a column for each of the further
+/// `metrics`, with the Unix seconds each bucket starts at and an empty value in a gap.
+async fn temps_csv(
+    State(state): State<AppState>,
+    Query(params): Query<TempsQuery>
+) -> Result<Response, AppError> {
+    let sensor = params.sensor.clone().unwrap_or(state.default_sensor);
+    let metric = params.metric.clone().unwrap_or_else(|| TEMPERATURE.to_string());
+    let storage = state.storage.read()?;
+    let TempsBuckets { buckets, metrics, .. } = temps_buckets(&params, &storage, &sensor, &metric, SystemTime::now())?;
+
+    let cell = |bucket: Option<&Bucket>| bucket.and_then(|bucket| bucket.value).map(|value| value.to_string()).unwrap_or_default();
+    let mut csv = format!("timestamp,{},count", metric);
+    for name in metrics.keys() {
+        csv.push_str(&format!(",{}", name));
+    }
+    csv.push('\n');
+    for (i, bucket) in buckets.iter().enumerate() {
+        csv.push_str(&format!("{},{},{}", bucket.start, cell(Some(bucket)), bucket.count));
+        for extra in metrics.values() {
+            csv.push_str(&format!(",{}", cell(extra.get(i))));
+        }
+        csv.push('\n');
+    }
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/csv; charset=utf-8")
+        .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}-{}.csv\"", sensor, metric))
+        .body(Body::from(csv))
+        .map_err(|e| AppError::InternalError(e.to_string()))
+}
+
+/// The `/temps` curve of a sensor, next to the outdoor one, as a PNG image for e-mails, e-ink
+/// displays or chat messages.
+#[utoipa::path(get, path = "/api/v1/chart.png", params(ChartQuery), responses(
+    (status = 200, description = "Line chart of the range", content_type = "image/png"),
+    (status = 400, description = "Invalid range, interval or size"),
+    (status = 404, description = "Unknown sensor or no values in the range"),
+))]
+async fn chart_png(
+    State(state): State<AppState>,
+    Query(params): Query<ChartQuery>,
+) -> Result<Response, AppError> {
+    let width = params.width.unwrap_or(DEFAULT_CHART_WIDTH);
+    let height = params.height.unwrap_or(DEFAULT_CHART_HEIGHT);
+    if !(MIN_SIZE..=MAX_SIZE).contains(&width) || !(MIN_SIZE..=MAX_SIZE).contains(&height) {
+        return Err(AppError::BadRequest(format!("width and height must be between {} and {}", MIN_SIZE, MAX_SIZE)));
+    }
+    let sensor = params.sensor.unwrap_or(state.default_sensor);
+    let metric = params.metric.unwrap_or_else(|| TEMPERATURE.to_string());
+    let query = TempsQuery {
+        hours: params.hours,
+        from: params.from,
+        to: params.to,
+        sensor: None,
+        metric: None,
+        interval: params.interval,
+        metrics: None,
+    };
+
+    let (from, to, buckets, outdoor) = {
+        let storage = state.storage.read()?;
+        let TempsBuckets { from, to, interval, buckets, .. } = temps_buckets(&query, &storage, &sensor, &metric, SystemTime::now())?;
+        let outdoor = state.settings.read()?.outdoor_sensor.clone()
+            .filter(|outdoor| *outdoor != sensor)
+            .and_then(|outdoor| {
+                let buckets = storage.series(&outdoor)?.metric_buckets(&metric, from, to, interval, storage.carry_forward()).ok()?;
+                Some((outdoor, buckets))
+            });
+        (from, to, buckets, outdoor)
+    };
+    if buckets.iter().all(|bucket| bucket.value.is_none()) {
+        return Err(AppError::NotFound(format!("no {} of {} in the range", metric, sensor)));
+    }
+
+    let time_format = time_format(to.duration_since(from).unwrap_or_default());
+    let timezone = state.timezone;
+    let png = tokio::task::spawn_blocking(move || {
+        let mut lines = vec![Line { label: &sensor, buckets: &buckets }];
+        if let Some((outdoor, buckets)) = &outdoor {
+            lines.push(Line { label: outdoor, buckets });
+        }
+        let y_label = metric_label(&metric);
+        let title = if metric == TEMPERATURE { sensor.clone() } else { format!("{} {}", sensor, metric) };
+        let chart = Chart { title: &title, y_label: &y_label, lines, width, height };
+        chart.render_png(&|secs| local_time(timezone, secs, time_format))
+    }).await.context("chart rendering failed")??;
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "image/png")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from(png))
+        .map_err(|e| AppError::InternalError(e.to_string()))
+}
+
+/// Range of a `/temps` query: `to` defaults to now, or to `hours` after `from`, and `from` to
+/// `hours` (default 3) before `to`.
+pub fn temps_range(from: Option<&str>, to: Option<&str>, hours: Option<u64>, now: SystemTime) -> Result<(SystemTime, SystemTime), AppError> {
+    let span = |hours: u64| Duration::from_secs(hours.saturating_mul(3600));
+    let from = from.map(|value| parse_time("from", value)).transpose()?;
+    let to = to.map(|value| parse_time("to", value)).transpose()?;
+    let (from, to) = match (from, to, hours) {
+        (Some(_), Some(_), Some(_)) => return Err(AppError::BadRequest("give at most two of from, to and hours".to_string())),
+        (Some(from), Some(to), None) => (from, to),
+        (Some(from), None, Some(hours)) => (from, from.checked_add(span(hours))
+            .ok_or_else(|| AppError::BadRequest(format!("hours {} is out of range", hours)))?),
+        (Some(from), None, None) => (from, now),
+        (None, to, hours) => {
+            let to = to.unwrap_or(now);
+            (to.checked_sub(span(hours.unwrap_or(3))).unwrap_or(SystemTime::UNIX_EPOCH), to)
+        }
+    };
+    if from >= to {
+        return Err(AppError::BadRequest("from must be before to".to_string()));
+    }
+    if from > now {
+        return Err(AppError::BadRequest("from is in the future".to_string()));
+    }
+    Ok((from, to))
+}
+
+/// Unix seconds like `1758294793` or RFC 3339 like `2025-09-19T17:13:13+02:00`.
+fn parse_time(name: &str, value: &str) -> Result<SystemTime, AppError> {
+    if let Ok(secs) = value.parse::<u64>() {
+        return unix_time(name, secs);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(SystemTime::from)
+        .map_err(|_| AppError::BadRequest(format!("{} {:?} is neither Unix seconds nor an RFC 3339 time", name, value)))
+}
+
+/// Unix seconds of the `name` parameter as a time, a bad request past what `SystemTime` holds.
+pub fn unix_time(name: &str, secs: u64) -> Result<SystemTime, AppError> {
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+        .ok_or_else(|| AppError::BadRequest(format!("{} {} is out of range", name, secs)))
+}
+
+/// Validates a requested averaging interval against `MIN_INTERVAL_SECS`, `MAX_INTERVAL_SECS`
+/// and the number of buckets it splits `hours` into.
+fn query_interval(secs: u64, hours: u64) -> Result<Duration, AppError> {
+    if !(MIN_INTERVAL_SECS..=MAX_INTERVAL_SECS).contains(&secs) {
+        return Err(AppError::BadRequest(format!(
+            "interval must be between {} and {} seconds", MIN_INTERVAL_SECS, MAX_INTERVAL_SECS)));
+    }
+    if hours.saturating_mul(3600) / secs > MAX_BUCKETS {
+        return Err(AppError::BadRequest(format!(
+            "interval {}s splits {} hours into more than {} values", secs, hours, MAX_BUCKETS)));
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// Min, max, mean, standard deviation and percentiles of each metric over the last `hours`.
+#[utoipa::path(get, path = "/api/v1/stats", params(StatsQuery), responses(
+    (status = 200, description = "Statistics of each metric", body = StatsResponse),
+    (status = 404, description = "Unknown sensor"),
+))]
+async fn stats(
+    State(state): State<AppState>,
+    Query(params): Query<StatsQuery>,
+) -> Result<Json<StatsResponse>, AppError> {
+    let hours = params.hours.unwrap_or(24);
+    let sensor = params.sensor.unwrap_or(state.default_sensor);
+    let now = SystemTime::now();
+    // more hours than there are since 1970 cover the whole history
+    let from = now.checked_sub(Duration::from_secs(hours.saturating_mul(3600))).unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let storage = state.storage.read()?;
+    let series = storage.series(&sensor)
+        .ok_or_else(|| AppError::NotFound(format!("unknown sensor {}", sensor)))?;
+    let stats = series.stats(from, now)?;
+    Ok(Json(StatsResponse { sensor, hours, stats }))
+}
+
+/// Current temperature, its 24-hour range and mean and where it is heading, for the dashboard header.
+#[utoipa::path(get, path = "/api/v1/summary", params(SummaryQuery), responses(
+    (status = 200, description = "Summary of the sensor", body = SummaryResponse),
+    (status = 404, description = "Unknown sensor"),
+))]
+async fn summary(
+    State(state): State<AppState>,
+    Query(params): Query<SummaryQuery>,
+) -> Result<Json<SummaryResponse>, AppError> {
+    let sensor = params.sensor.unwrap_or(state.default_sensor);
+    let now = SystemTime::now();
+
+    let storage = state.storage.read()?;
+    let series = storage.series(&sensor)
+        .ok_or_else(|| AppError::NotFound(format!("unknown sensor {}", sensor)))?;
+    let last = series.latest_sample();
+    let day = series.stats(now - Duration::from_secs(24 * 3600), now).ok()
+        .and_then(|mut stats| stats.remove(TEMPERATURE));
+    let trend_per_hour = series.rate_of_change(TEMPERATURE, now - Duration::from_secs(3600), now, Duration::from_secs(3600)).ok()
+        .and_then(|rates| rates.first().and_then(|rate| rate.per_hour));
+    Ok(Json(SummaryResponse {
+        current: last.map(|sample| sample.temperature),
+        current_at: last.map(unix_secs),
+        min: day.as_ref().map(|stats| stats.min),
+        max: day.as_ref().map(|stats| stats.max),
+        mean: day.as_ref().map(|stats| stats.mean),
+        trend: trend_per_hour.map(Trend::from_rate),
+        trend_per_hour,
+        sensor,
+    }))
+}
+
+/// Pushes every newly stored sample to the client as a JSON text message.
+async fn live_updates(
+    State(state): State<AppState>,
+    Query(params): Query<LiveQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, AppError> {
+    let stored = state.storage.read()?.subscribe();
+    Ok(upgrade.on_upgrade(move |socket| push_samples(socket, stored, params.sensor, state.shutdown)))
+}
+
+async fn push_samples(mut socket: WebSocket, mut stored: broadcast::Receiver<StoredSample>, sensor: Option<String>, shutdown: watch::Receiver<bool>) {
+    loop {
+        tokio::select! {
+            _ = stopped(shutdown.clone()) => {
+                let _ = socket.send(Message::Close(None)).await;
+                break;
+            }
+            update = stored.recv() => {
+                let update = match update {
+                    Ok(update) => update,
+                    // a slow client skips what it missed
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if sensor.as_ref().is_some_and(|sensor| *sensor != update.series) {
+                    continue;
+                }
+                let Ok(text) = serde_json::to_string(&LiveSample::from(update)) else {
+                    continue;
+                };
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Streams newly stored samples as `sample` events and sensor alerts as `alert` events, with a
+/// heartbeat comment every `HEARTBEAT_SECS` so proxies keep the connection open.
+async fn events(
+    State(state): State<AppState>,
+    Query(params): Query<LiveQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, AppError> {
+    let stored = state.storage.read()?.subscribe();
+    let alerts = state.statuses.lock()?.subscribe();
+    let stream = futures_util::stream::unfold((stored, alerts, params.sensor, state.shutdown), |(mut stored, mut alerts, sensor, shutdown)| async move {
+        loop {
+            let event = tokio::select! {
+                _ = stopped(shutdown.clone()) => return None,
+                update = stored.recv() => match update {
+                    Ok(update) if sensor.as_ref().is_none_or(|sensor| *sensor == update.series) => {
+                        Event::default().event("sample").json_data(LiveSample::from(update))
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+                alert = alerts.recv() => match alert {
+                    Ok(alert) if sensor.as_ref().is_none_or(|sensor| sensor == alert.sensor()) => {
+                        Event::default().event("alert").json_data(alert)
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+            };
+            return Some((event, (stored, alerts, sensor, shutdown)));
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(HEARTBEAT_SECS)).text("heartbeat")))
+}
+
+/// Uptime, sensor polls, data freshness and storage size, 503 once a sensor's data is stale.
+#[utoipa::path(get, path = "/health", responses(
+    (status = 200, description = "All sensors have fresh data", body = HealthResponse),
+    (status = 503, description = "A sensor's data is stale", body = HealthResponse),
+))]
+async fn health(State(state): State<AppState>) -> Result<(StatusCode, Json<HealthResponse>), AppError> {
+    let now = SystemTime::now();
+    let stale_after = state.settings.read()?.stale_after;
+    let storage = state.storage.read()?;
+    let statuses = state.statuses.lock()?;
+    let sensors: Vec<SensorHealth> = statuses.iter()
+        .map(|(name, status)| {
+            let age = storage.series(name)
+                .and_then(|series| series.latest_sample())
+                .map(|sample| now.duration_since(sample.timestamp).unwrap_or_default());
+            SensorHealth {
+                name: name.to_string(),
+                last_poll_ok: status.last_success.is_some() && status.consecutive_failures == 0,
+                last_success: status.last_success
+                    .map(|time| time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()),
+                newest_sample_age_secs: age.map(|age| age.as_secs()),
+                stale: age.is_none_or(|age| age > stale_after),
+            }
+        })
+        .collect();
+    let stale = sensors.iter().any(|sensor| sensor.stale);
+    let response = HealthResponse {
+        status: if stale { "stale" } else { "ok" },
+        uptime_secs: state.started.elapsed().as_secs(),
+        samples: storage.series_names().filter_map(|name| storage.series(name)).map(|series| series.len()).sum(),
+        memory_bytes: storage.memory_usage(),
+        sensors,
+    };
+    let code = if stale { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+    Ok((code, Json(response)))
+}
+
+/// Health of every sensor, for alerting on stale data sources.
+#[utoipa::path(get, path = "/api/v1/sensors", responses(
+    (status = 200, description = "Status of each sensor", body = SensorsResponse),
+))]
+async fn sensors(State(state): State<AppState>) -> Result<Json<SensorsResponse>, AppError> {
+    let statuses = state.statuses.lock()?;
+    let sensors: Vec<SensorResponse> = statuses.iter()
+        .map(|(name, status)| SensorResponse {
+            name: name.to_string(),
+            status: SensorStatusResponse::from(status),
+        })
+        .collect();
+    Ok(Json(SensorsResponse { sensors }))
+}
+
+/// The `[[alerts]]` rules currently firing, one entry per rule and sensor.
+#[utoipa::path(get, path = "/api/v1/alerts", responses(
+    (status = 200, description = "Active alerts", body = AlertsResponse),
+))]
+async fn active_alerts(State(state): State<AppState>) -> Result<Json<AlertsResponse>, AppError> {
+    let alerts = state.alerts.lock()?.active().cloned().collect();
+    Ok(Json(AlertsResponse { alerts }))
+}
+
+/// Acknowledges an active alert, which stops the repeats of its rule's `renotify`; it is
+/// notified again once it resolves.
+#[utoipa::path(post, path = "/api/v1/alerts/{id}/ack", params(("id" = u64, Path, description = "`id` of the alert")), security(("admin_token" = [])), responses(
+    (status = 200, description = "`{\"status\": \"ok\", \"alert\": ...}`", body = Object),
+    (status = 401, description = "Missing or wrong admin token"),
+    (status = 404, description = "No active alert has the id"),
+))]
+async fn acknowledge_alert(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    check_admin_token(&state, bearer_token(&headers))?;
+    let mut alerts = state.alerts.lock()?;
+    let alert = alerts.acknowledge(id, SystemTime::now())
+        .ok_or_else(|| AppError::NotFound(format!("no active alert {}", id)))?;
+    log::info!("Acknowledged alert {}", alert.event.message());
+    Ok(Json(serde_json::json!({ "status": "ok", "alert": alert })))
+}
+
+/// The latest attempts to send alert events to the notification channels, admin only as a
+/// webhook URL or an ntfy topic is itself the secret.
+#[utoipa::path(get, path = "/api/v1/alerts/deliveries", security(("admin_token" = [])), responses(
+    (status = 200, description = "Latest deliveries", body = DeliveriesResponse),
+    (status = 401, description = "Missing or wrong admin token"),
+))]
+async fn alert_deliveries(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<DeliveriesResponse>, AppError> {
+    check_admin_token(&state, bearer_token(&headers))?;
+    let deliveries = state.deliveries.lock()?.iter().cloned().collect();
+    Ok(Json(DeliveriesResponse { deliveries }))
+}
+
+/// Raw samples of a series in a time range as CSV or JSON, streamed in chunks.
+#[utoipa::path(get, path = "/api/v1/export", params(ExportQuery), responses(
+    (status = 200, description = "Samples in the requested format", content(
+        (String = "text/csv"),
+        (Object = "application/json"),
+        (String = "text/plain"),
+    )),
+    (status = 400, description = "Invalid range"),
+    (status = 404, description = "Unknown sensor"),
+))]
+async fn export(
+    State(state): State<AppState>,
+    Query(params): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    let sensor = params.sensor.unwrap_or(state.default_sensor);
+    let from = unix_time("from", params.from.unwrap_or(0))?;
+    let to = params.to.map_or_else(|| Ok(SystemTime::now()), |to| unix_time("to", to))?;
+    if from > to {
+        return Err(AppError::BadRequest("from is after to".to_string()));
+    }
+
+    let samples = {
+        let storage = state.storage.read()?;
+        if storage.latest(&sensor).is_none() {
+            return Err(AppError::NotFound(format!("unknown sensor {}", sensor)));
+        }
+        storage.range(&sensor, from, to)?
+    };
+
+    let mut tags = state.influx.tags.clone();
+    tags.insert("sensor".to_string(), sensor.clone());
+    let chunks = Export::new(params.format, samples)
+        .with_series_key(&state.influx.measurement, &tags)
+        .into_chunks()
+        .map(Ok::<_, Infallible>);
+    Response::builder()
+        .header(header::CONTENT_TYPE, params.format.content_type())
+        .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.{}\"", sensor, params.format.extension()))
+        .body(Body::from_stream(futures_util::stream::iter(chunks)))
+        .map_err(|e| AppError::InternalError(e.to_string()))
+}
+
+fn unix_secs(sample: &Sample) -> u64 {
+    sample.timestamp
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Whether `given` is the `expected` secret, compared in constant time so the response time
+/// doesn't tell how much of a guess was right.
+pub fn secret_eq(expected: &str, given: &str) -> bool {
+    expected.as_bytes().ct_eq(given.as_bytes()).into()
+}
+
+fn check_push_token(state: &AppState, token: Option<&str>) -> Result<(), AppError> {
+    match (&state.settings.read()?.push_token, token) {
+        (Some(expected), Some(token)) if !expected.is_empty() && secret_eq(expected, token) => Ok(()),
+        _ => Err(AppError::Unauthorized("invalid push token".to_string())),
+    }
+}
+
+fn check_admin_token(state: &AppState, token: Option<&str>) -> Result<(), AppError> {
+    match (&state.settings.read()?.admin_token, token) {
+        (Some(expected), Some(token)) if !expected.is_empty() && secret_eq(expected, token) => Ok(()),
+        _ => Err(AppError::Unauthorized("invalid admin token".to_string())),
+    }
+}
+
+/// Logs every request with its status and how long the response took, without the query
+/// string, which may carry an `api_key`.
+async fn log_access(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    log::info!("{} {} {} {:.1}ms", method, path, response.status().as_u16(), started.elapsed().as_secs_f64() * 1000.0);
+    response
+}
+
+/// Adds the message and the causes of an `AppError` to its JSON body with `detailed_errors` on.
+async fn detail_errors(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    let Some(details) = response.extensions_mut().remove::<ErrorDetails>() else {
+        return response;
+    };
+    if !state.settings.read().is_ok_and(|settings| settings.detailed_errors) {
+        return response;
+    }
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(details.body(true).to_string()))
+}
+
+/// Refuses requests without one of the configured `api_keys` in the `X-API-Key` header or the
+/// `api_key` query parameter, except for the dashboard and API docs pages and the assets.
+async fn require_api_key(State(state): State<AppState>, request: Request, next: Next) -> Result<Response, AppError> {
+    let path = request.uri().path();
+    let exempt = path == "/" || path == "/api/docs" || path.starts_with("/assets/");
+    let key = match request.headers().get(API_KEY_HEADER) {
+        Some(value) => value.to_str().ok().map(str::to_string),
+        None => Query::<ApiKeyQuery>::try_from_uri(request.uri()).ok().and_then(|query| query.0.api_key),
+    };
+    let allowed = {
+        let settings = state.settings.read()?;
+        exempt || settings.api_keys.is_empty() || key.is_some_and(|key| settings.api_keys.iter()
+            .fold(false, |found, allowed| found | secret_eq(allowed, &key)))
+    };
+    if !allowed {
+        return Err(AppError::Unauthorized("invalid API key".to_string()));
+    }
+    Ok(next.run(request).await)
+}
+
+/// Asks the browser to log in with the `dashboard_auth` credentials before serving the dashboard
+/// page and its assets.
+async fn require_dashboard_login(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    let auth = match state.settings.read() {
+        Ok(settings) => settings.dashboard_auth.clone(),
+        Err(e) => return AppError::from(e).into_response(),
+    };
+    let Some(auth) = auth.filter(|_| path == "/" || path.starts_with("/assets/")) else {
+        return next.run(request).await;
+    };
+    let credentials = request.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    if let Some((username, password)) = credentials.as_deref().and_then(|credentials| credentials.split_once(':')) {
+        if username == auth.username {
+            let (password, hash) = (password.to_string(), auth.password_hash);
+            // bcrypt is slow on purpose, keep it off the runtime threads
+            let valid = tokio::task::spawn_blocking(move || bcrypt::verify(password, &hash).unwrap_or(false)).await;
+            if valid.unwrap_or(false) {
+                return next.run(request).await;
+            }
+        }
+    }
+    (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Basic realm=\"heater-monitor\", charset=\"UTF-8\"")]).into_response()
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Adds a note to the timeline, e.g. "changed boiler filter", returned by `/temps`.
+#[utoipa::path(post, path = "/api/v1/annotations", request_body = NewAnnotation, security(("admin_token" = [])), responses(
+    (status = 200, description = "`{\"status\": \"ok\", \"annotation\": ...}`", body = Object),
+    (status = 400, description = "Empty or too long text"),
+    (status = 401, description = "Missing or wrong admin token"),
+))]
+async fn add_annotation(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(annotation): Json<NewAnnotation>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    check_admin_token(&state, bearer_token(&headers))?;
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+    let annotation = Annotation {
+        timestamp: annotation.timestamp.unwrap_or(now),
+        text: annotation.text,
+        sensor: annotation.sensor,
+    };
+    state.annotations.lock()?.add(annotation.clone())?;
+    Ok(Json(serde_json::json!({ "status": "ok", "annotation": annotation })))
+}
+
+/// Raw samples of a series in pages, for syncing the full resolution incrementally.
+#[utoipa::path(get, path = "/api/v1/samples", params(SamplesQuery), responses(
+    (status = 200, description = "A page of samples, oldest first", body = SamplesResponse),
+    (status = 400, description = "Invalid range, limit or cursor"),
+    (status = 404, description = "Unknown sensor"),
+))]
+async fn samples(
+    State(state): State<AppState>,
+    Query(params): Query<SamplesQuery>,
+) -> Result<Json<SamplesResponse>, AppError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::BadRequest(format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+    let cursor = params.cursor.as_deref().map(str::parse::<Cursor>).transpose()?;
+    let from = unix_time("from", params.from.unwrap_or(0))?;
+    let to = params.to.map_or_else(|| Ok(SystemTime::now()), |to| unix_time("to", to))?;
+    if from > to {
+        return Err(AppError::BadRequest("from is after to".to_string()));
+    }
+    let sensor = params.sensor.unwrap_or(state.default_sensor);
+
+    let storage = state.storage.read()?;
+    let series = storage.series(&sensor)
+        .ok_or_else(|| AppError::NotFound(format!("unknown sensor {}", sensor)))?;
+    let page = series.page(from, to, cursor, limit);
+    let samples = page.samples.into_iter()
+        .map(|sample| RawSample {
+            timestamp: sample.timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+            temperature: sample.temperature,
+            metrics: sample.metrics,
+        })
+        .collect();
+    Ok(Json(SamplesResponse {
+        sensor,
+        samples,
+        next_cursor: page.next.map(|cursor| cursor.to_string()),
+        more: page.more,
+    }))
+}
+
+/// Deletes the samples of a sensor in a range from memory and the backlog, e.g. garbage
+/// recorded by a miswired sensor.
+#[utoipa::path(delete, path = "/api/v1/samples", params(DeleteQuery), security(("admin_token" = [])), responses(
+    (status = 200, description = "`{\"status\": \"ok\", \"sensor\": ..., \"removed\": <samples>}`", body = Object),
+    (status = 400, description = "Invalid range"),
+    (status = 401, description = "Missing or wrong admin token"),
+    (status = 404, description = "Unknown sensor"),
+))]
+async fn delete_samples(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<DeleteQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    check_admin_token(&state, bearer_token(&headers))?;
+    if params.from > params.to {
+        return Err(AppError::BadRequest("from is after to".to_string()));
+    }
+    let sensor = params.sensor.unwrap_or(state.default_sensor);
+    let from = unix_time("from", params.from)?;
+    // the backlog keeps whole seconds, `to` covers the whole second
+    let to = unix_time("to", params.to)?.checked_add(Duration::from_nanos(999_999_999))
+        .ok_or_else(|| AppError::BadRequest(format!("to {} is out of range", params.to)))?;
+
+    let (removed, backlog) = {
+        let mut storage = state.storage.write()?;
+        if storage.series(&sensor).is_none() {
+            return Err(AppError::NotFound(format!("unknown sensor {}", sensor)));
+        }
+        (storage.delete_range(&sensor, from, to)?, storage.backlog_path().map(str::to_string))
+    };
+    // rewriting the closed segments takes a while, readers and sensors don't wait for it
+    if let Some(path) = backlog {
+        let series = sensor.clone();
+        let removed = tokio::task::spawn_blocking(move || Backlog::remove_from_segments(&path, &series, from, to))
+            .await.context("backlog rewrite failed")??;
+        log::info!("Removed {} samples of {} from the closed backlog segments", removed, sensor);
+    }
+    Ok(Json(serde_json::json!({ "status": "ok", "sensor": sensor, "removed": removed })))
+}
+
+/// Writes and syncs the buffered backlog records.
+#[utoipa::path(post, path = "/api/v1/admin/flush", security(("admin_token" = [])), responses(
+    (status = 200, description = "The backlog is on the disk", body = FlushResponse),
+    (status = 401, description = "Missing or wrong admin token"),
+))]
+async fn flush(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<FlushResponse>, AppError> {
+    check_admin_token(&state, bearer_token(&headers))?;
+    let mut storage = state.storage.write()?;
+    storage.sync_backlog()?;
+    Ok(Json(FlushResponse { status: "ok", backlog_bytes: storage.backlog_size() }))
+}
+
+/// Drops samples from memory, e.g. after a memory spike; the backlog keeps them.
+#[utoipa::path(post, path = "/api/v1/admin/purge", params(PurgeQuery), security(("admin_token" = [])), responses(
+    (status = 200, description = "How many samples were dropped", body = PurgeResponse),
+    (status = 401, description = "Missing or wrong admin token"),
+    (status = 404, description = "Unknown sensor"),
+))]
+async fn purge(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<PurgeQuery>,
+) -> Result<Json<PurgeResponse>, AppError> {
+    check_admin_token(&state, bearer_token(&headers))?;
+    let mut storage = state.storage.write()?;
+    if let Some(sensor) = params.sensor.as_deref().filter(|sensor| storage.series(sensor).is_none()) {
+        return Err(AppError::NotFound(format!("unknown sensor {}", sensor)));
+    }
+    let purged = storage.purge(params.sensor.as_deref());
+    Ok(Json(PurgeResponse { status: "ok", purged }))
+}
+
+/// Re-reads the configuration file and applies the settings that can change without a restart.
+#[utoipa::path(post, path = "/api/v1/admin/reload", security(("admin_token" = [])), responses(
+    (status = 200, description = "The settings applied", body = ReloadResponse),
+    (status = 401, description = "Missing or wrong admin token"),
+    (status = 500, description = "The file cannot be read or is invalid, nothing was changed"),
+))]
+async fn reload(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<ReloadResponse>, AppError> {
+    check_admin_token(&state, bearer_token(&headers))?;
+    let config = Config::read(state.config_path.clone())
+        .map_err(|e| AppError::InternalError(format!("cannot reload {}: {:#}", state.config_path.display(), e)))?;
+    state.storage.write()?.reload(&config);
+    *state.settings.write()? = Settings::from(&config);
+    {
+        let mut running = state.running.write()?;
+        running.config.reload(&config);
+        running.reloaded_at = Some(SystemTime::now());
+    }
+    log::info!("Reloaded configuration from {}", state.config_path.display());
+    Ok(Json(ReloadResponse { status: "ok", reloaded: RELOADED_SETTINGS.to_vec() }))
+}
+
+/// The configuration in effect with its secrets redacted, to confirm what the daemon runs with.
+#[utoipa::path(get, path = "/api/v1/config", security(("admin_token" = [])), responses(
+    (status = 200, description = "The effective configuration", body = ConfigResponse),
+    (status = 401, description = "Missing or wrong admin token"),
+))]
+async fn running_config(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<ConfigResponse>, AppError> {
+    check_admin_token(&state, bearer_token(&headers))?;
+    let running = state.running.read()?;
+    let secs = |time: SystemTime| time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    Ok(Json(ConfigResponse {
+        path: state.config_path.display().to_string(),
+        loaded_at: secs(running.loaded_at),
+        reloaded_at: running.reloaded_at.map(secs),
+        config: running.config.clone(),
+    }))
+}
+
+/// Stores a reading of a `push` sensor, e.g. an ESP8266 posting its measurements.
+#[utoipa::path(post, path = "/api/v1/measurements", request_body = PushedMeasurement, security(("push_token" = [])), responses(
+    (status = 200, description = "`{\"status\": \"ok\", \"sensor\": ...}`", body = Object),
+    (status = 400, description = "Invalid value or timestamp"),
+    (status = 401, description = "Missing or wrong push token"),
+    (status = 404, description = "Unknown push sensor"),
+))]
+async fn push_measurement(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(measurement): Json<PushedMeasurement>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    check_push_token(&state, bearer_token(&headers))?;
+
+    let sensor = match measurement.sensor {
+        Some(sensor) => sensor,
+        None => state.push_sensors.first()
+            .cloned()
+            .ok_or_else(|| AppError::NotFound("no push sensor configured".to_string()))?,
+    };
+    if !state.push_sensors.contains(&sensor) {
+        return Err(AppError::NotFound(format!("unknown push sensor {}", sensor)));
+    }
+    if !measurement.temperature.is_finite() {
+        return Err(AppError::BadRequest("temperature must be a number".to_string()));
+    }
+    for (metric, value) in &measurement.metrics {
+        if !is_valid_metric_name(metric) || !value.is_finite() {
+            return Err(AppError::BadRequest(format!("invalid metric {}", metric)));
+        }
+    }
+
+    let now = SystemTime::now();
+    let timestamp = match measurement.timestamp {
+        Some(secs) => unix_time("timestamp", secs)?,
+        None => now,
+    };
+    if timestamp > now + Duration::from_secs(60) {
+        return Err(AppError::BadRequest("timestamp is in the future".to_string()));
+    }
+
+    let mut sample = Sample::new(timestamp, measurement.temperature);
+    sample.metrics = measurement.metrics;
+    if let Some(humidity) = measurement.humidity.filter(|humidity| humidity.is_finite()) {
+        sample.metrics.insert(HUMIDITY.to_string(), humidity);
+    }
+    store_pushed(&state, &sensor, sample)?;
+
+    Ok(Json(serde_json::json!({ "status": "ok", "sensor": sensor })))
+}
+
+/// Battery Shellys sleep and only wake up to report, the token comes in the query
+/// because their action URLs can't carry headers.
+async fn shelly_report(
+    State(state): State<AppState>,
+    Query(report): Query<ShellyReport>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    check_push_token(&state, report.token.as_deref())?;
+    let sensor = state.shelly_devices.get(&report.id)
+        .cloned()
+        .ok_or_else(|| AppError::NotFound(format!("unknown shelly device {}", report.id)))?;
+    let temperature = report.temp
+        .filter(|temperature| temperature.is_finite())
+        .ok_or_else(|| AppError::BadRequest("temp must be a number".to_string()))?;
+
+    let mut sample = Sample::new(SystemTime::now(), temperature);
+    if let Some(humidity) = report.hum.filter(|humidity| humidity.is_finite()) {
+        sample.metrics.insert(HUMIDITY.to_string(), humidity);
+    }
+    store_pushed(&state, &sensor, sample)?;
+
+    Ok(Json(serde_json::json!({ "status": "ok", "sensor": sensor })))
+}
+
+/// Whether a pushed `sample` follows the `latest` one of its sensor. A resend of the latest one,
+/// e.g. by a device retrying after a timeout, is accepted without storing it twice; an older
+/// one or different values at the same time are a bad request.
+pub fn is_new_push(latest: Option<&Sample>, sample: &Sample) -> Result<bool, AppError> {
+    match latest {
+        Some(latest) if latest.timestamp > sample.timestamp =>
+            Err(AppError::BadRequest("timestamp is older than the latest sample".to_string())),
+        Some(latest) if latest.timestamp == sample.timestamp => {
+            if latest.temperature == sample.temperature && latest.metrics == sample.metrics {
+                Ok(false)
+            } else {
+                Err(AppError::BadRequest("another sample has the same timestamp".to_string()))
+            }
+        }
+        _ => Ok(true),
+    }
+}
+
+fn store_pushed(state: &AppState, sensor: &str, sample: Sample) -> Result<(), AppError> {
+    let mut storage = state.storage.write()?;
+    let latest = storage.series(sensor).and_then(|series| series.get_last_sample());
+    if is_new_push(latest, &sample)? {
+        storage.add_sample(sensor, sample);
+    }
+    drop(storage);
+    state.statuses.lock()?.record_success(sensor);
+    Ok(())
+}
+
+/// ESPHome, Shelly and Tasmota devices found on the LAN by mDNS.
+#[utoipa::path(get, path = "/api/v1/discovery", responses(
+    (status = 200, description = "`{\"devices\": [...]}`", body = Object),
+    (status = 404, description = "Discovery is disabled"),
+))]
+async fn discovered_devices(State(state): State<AppState>) -> Result<Json<serde_json::Value>, AppError> {
+    if !state.discovery {
+        return Err(AppError::NotFound("discovery is disabled".to_string()));
+    }
+    let discovered = state.discovered.lock()?;
+    let devices: Vec<_> = discovered.devices().collect();
+    Ok(Json(serde_json::json!({ "devices": devices })))
+}
+
 async fn fallback() -> &'static str {
     "Not found"
 }
\ No newline at end of file
@@ -0,0 +1,31 @@
+use std::f64::consts::TAU;
+use std::time::SystemTime;
+use rand::Rng;
+use crate::app_error::AppError;
+use crate::config::SimulatorConfig;
+use crate::temp_sensor::Measurement;
+
+/// Generates a sine wave plus uniform noise, for running the daemon without hardware.
+pub struct SimulatedSensor {
+    config: SimulatorConfig,
+}
+
+impl SimulatedSensor {
+    pub fn new(config: &SimulatorConfig) -> Self {
+        Self { config: config.clone() }
+    }
+
+    pub async fn query(&self) -> Result<Measurement, AppError> {
+        // the phase follows the wall clock so the curve survives restarts
+        let secs = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+        let wave = (secs / self.config.period as f64 * TAU).sin();
+        let mut rng = rand::rng();
+        let noise = self.config.noise;
+
+        Ok(Measurement::new(
+            self.config.mean + self.config.amplitude * wave + rng.random_range(-noise..=noise),
+            // humidity drops while the temperature rises
+            self.config.humidity_mean - self.config.humidity_amplitude * wave + rng.random_range(-noise..=noise),
+        ))
+    }
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::time::{Duration, SystemTime};
+use crate::app_error::AppError;
+use crate::backlog::{binary_record, read_binary_records, Record};
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"HMSN";
+const SNAPSHOT_VERSION: u8 = 1;
+const HEADER_LEN: usize = SNAPSHOT_MAGIC.len() + 1 + 16;
+
+/// The samples in memory at shutdown, restored at startup instead of replaying the backlog.
+///
+/// Layout: `HMSN`, a version byte, u64 backlog size, u64 secs of the backlog's first sample
+/// (0 for none), followed by binary backlog records.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    /// Size of the backlog file when the snapshot was taken, a different size means samples
+    /// were appended or rotated away since and the snapshot is stale.
+    pub backlog_size: u64,
+    pub backlog_started: Option<SystemTime>,
+    pub records: Vec<Record>,
+}
+
+impl Snapshot {
+    pub fn write(&self, path: &str) -> Result<(), AppError> {
+        let mut data = Vec::with_capacity(HEADER_LEN + self.records.len() * 40);
+        data.extend_from_slice(SNAPSHOT_MAGIC);
+        data.push(SNAPSHOT_VERSION);
+        data.extend_from_slice(&self.backlog_size.to_le_bytes());
+        let started = match self.backlog_started {
+            Some(started) => started.duration_since(SystemTime::UNIX_EPOCH)?.as_secs(),
+            None => 0,
+        };
+        data.extend_from_slice(&started.to_le_bytes());
+        for (series, sample) in &self.records {
+            data.extend_from_slice(&binary_record(series, sample)?);
+        }
+
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// `None` when there is no snapshot at `path`.
+    pub fn read(path: &str) -> Result<Option<Self>, AppError> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if data.len() < HEADER_LEN || !data.starts_with(SNAPSHOT_MAGIC) || data[4] != SNAPSHOT_VERSION {
+            return Err(AppError::ParseError(format!("{} is not a snapshot", path)));
+        }
+        let backlog_size = u64::from_le_bytes(data[5..13].try_into().expect("8 bytes"));
+        let started = u64::from_le_bytes(data[13..21].try_into().expect("8 bytes"));
+        let (records, len) = read_binary_records(&data[HEADER_LEN..]);
+        if HEADER_LEN + len < data.len() {
+            return Err(AppError::ParseError(format!("corrupt record in snapshot {}", path)));
+        }
+        Ok(Some(Self {
+            backlog_size,
+            backlog_started: (started > 0).then(|| SystemTime::UNIX_EPOCH + Duration::from_secs(started)),
+            records,
+        }))
+    }
+}
@@ -0,0 +1,58 @@
+use std::str::FromStr;
+use snmp2::{AsyncSession, Oid, Value};
+use crate::app_error::AppError;
+use crate::temp_sensor::Measurement;
+
+/// Temperature probe read with SNMPv2c GET requests.
+pub struct SnmpSensor {
+    address: String,
+    community: String,
+    oid: Oid<'static>,
+    scale: f64,
+    humidity_oid: Option<Oid<'static>>,
+}
+
+impl SnmpSensor {
+    pub fn new(address: &str, community: &str, oid: &str, scale: f64, humidity_oid: Option<&str>) -> Result<Self, AppError> {
+        Ok(Self {
+            address: address.to_string(),
+            community: community.to_string(),
+            oid: parse_oid(oid)?,
+            scale,
+            humidity_oid: humidity_oid.map(parse_oid).transpose()?,
+        })
+    }
+
+    pub async fn query(&self) -> Result<Measurement, AppError> {
+        let mut session = AsyncSession::new_v2c(&self.address, self.community.as_bytes(), 0).await?;
+        let temperature = get_number(&mut session, &self.oid).await? * self.scale;
+        let humidity = match &self.humidity_oid {
+            Some(oid) => get_number(&mut session, oid).await?,
+            None => f64::NAN,
+        };
+        Ok(Measurement::new(temperature, humidity))
+    }
+}
+
+fn parse_oid(oid: &str) -> Result<Oid<'static>, AppError> {
+    Oid::from_str(oid).map_err(|e| AppError::ParseError(format!("invalid OID {}: {:?}", oid, e)))
+}
+
+async fn get_number(session: &mut AsyncSession, oid: &Oid<'_>) -> Result<f64, AppError> {
+    let mut pdu = session.get(oid)
+        .await
+        .map_err(|e| AppError::TemperatureSensorError(format!("SNMP request failed: {}", e)))?;
+    let (_, value) = pdu.varbinds.next()
+        .ok_or_else(|| AppError::TemperatureSensorError(format!("no value for OID {}", oid)))?;
+    match value {
+        Value::Integer(v) => Ok(v as f64),
+        Value::Unsigned32(v) | Value::Counter32(v) => Ok(v as f64),
+        Value::Counter64(v) => Ok(v as f64),
+        // some probes report the reading as a string, e.g. "21.5"
+        Value::OctetString(bytes) => std::str::from_utf8(bytes)
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .ok_or_else(|| AppError::TemperatureSensorError(format!("OID {} is not a number", oid))),
+        other => Err(AppError::TemperatureSensorError(format!("OID {} has unsupported value {:?}", oid, other))),
+    }
+}
@@ -0,0 +1,81 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Summary of a metric over a time range.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Stats {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Population standard deviation.
+    pub stddev: f64,
+    pub p5: f64,
+    pub p95: f64,
+}
+
+impl Stats {
+    /// `None` without any finite value.
+    pub fn from_values(mut values: Vec<f64>) -> Option<Self> {
+        values.retain(|value| value.is_finite());
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+
+        let count = values.len();
+        let mean = values.iter().sum::<f64>() / count as f64;
+        let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / count as f64;
+        Some(Self {
+            count,
+            min: values[0],
+            max: values[count - 1],
+            mean,
+            stddev: variance.sqrt(),
+            p5: percentile(&values, 5.0),
+            p95: percentile(&values, 95.0),
+        })
+    }
+}
+
+/// Linear interpolation between the closest ranks of the sorted `values`.
+fn percentile(values: &[f64], percent: f64) -> f64 {
+    let rank = percent / 100.0 * (values.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    values[lower] + (values[upper] - values[lower]) * (rank - lower as f64)
+}
+
+/// Least-squares slope of `y` over `x` of the `points`, `None` for fewer than two distinct `x`.
+pub fn slope(points: &[(f64, f64)]) -> Option<f64> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+/// Change per hour below which a metric counts as steady, e.g. 0.2 °C/h.
+pub const STEADY_PER_HOUR: f64 = 0.2;
+
+/// Direction a metric is heading in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Trend {
+    Rising,
+    Falling,
+    Steady,
+}
+
+impl Trend {
+    pub fn from_rate(per_hour: f64) -> Self {
+        if per_hour >= STEADY_PER_HOUR {
+            Trend::Rising
+        } else if per_hour <= -STEADY_PER_HOUR {
+            Trend::Falling
+        } else {
+            Trend::Steady
+        }
+    }
+}
@@ -1,156 +1,235 @@
 use std::time::{Duration, SystemTime};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
 use crate::app_error::AppError;
 use crate::config::Config;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use crate::backlog::{Backlog, Record};
+use crate::downsampling::{roll_up, Tier};
+use crate::snapshot::Snapshot;
+use crate::stats::{slope, Stats};
+use crate::derived::{derived_value, DERIVED_METRICS};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use log::{debug, error, info, warn};
 use serde::Serialize;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+/// Series used by the legacy single-sensor setup (`temp_sensor_url`).
+pub const DEFAULT_SERIES: &str = "default";
+
+/// Metric stored in `Sample::temperature`, the one `/temps` returns by default.
+pub const TEMPERATURE: &str = "temperature";
+/// Metric name of the relative humidity, when the sensor reports one.
+pub const HUMIDITY: &str = "humidity";
+
+/// Metric names end up in the backlog as `<metric>=<value>`, `temperature` is implicit.
+pub fn is_valid_metric_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != TEMPERATURE
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct Sample {
+    #[schema(value_type = Object)]
     pub timestamp: SystemTime,
     pub temperature: f64,
- //   #[allow(dead_code)]
- //   pub humidity: f64,
+    /// Further metrics of the reading by name, e.g. `humidity`, `pressure` or `co2`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, f64>,
+    /// Period averaged into a downsampled sample starting at `timestamp`, `None` for a reading.
+    #[serde(skip)]
+    pub span: Option<Duration>,
 }
 
 impl Sample {
-    fn serialize(&self) -> Result<String, AppError> {
-        Ok(format!("t1 {} {}",
-           self.timestamp.duration_since(SystemTime::UNIX_EPOCH)?.as_secs(),
-           self.temperature))
+    pub fn new(timestamp: SystemTime, temperature: f64) -> Self {
+        Self { timestamp, temperature, metrics: BTreeMap::new(), span: None }
     }
 
-    #[allow(dead_code)]
-    fn deserialize(line: &str) -> Result<Sample, AppError> {
-        let parts: Vec<&str> = line.trim().split_whitespace().collect();
-
-        if parts.len() != 3 || parts[0] != "t1" {
-            return Err(AppError::ParseError(format!("Invalid sample format: {}", line)));
-        }
-
-        let timestamp_secs: u64 = parts[1].parse()
-            .map_err(|_| AppError::ParseError(format!("Invalid timestamp: {}", parts[1])))?;
-
-        let temperature: f64 = parts[2].parse()
-            .map_err(|_| AppError::ParseError(format!("Invalid temperature: {}", parts[2])))?;
-
-        if temperature > 1000.0 || temperature < -1000.0 {
-            return Err(AppError::ParseError(format!("Invalid temperature range: {}", temperature)));
+    /// Value of `metric` in this reading, `temperature` is always present. A derived metric
+    /// like `dew_point` is computed unless the device reported it.
+    pub fn value(&self, metric: &str) -> Option<f64> {
+        if metric == TEMPERATURE {
+            Some(self.temperature)
+        } else {
+            self.metrics.get(metric).copied().or_else(|| derived_value(self, metric))
         }
+    }
 
-        let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp_secs);
-
-        Ok(Sample {
-            timestamp,
-            temperature,
-        })
+    /// Approximate bytes the sample takes in a series, for `max_memory_mb`.
+    pub fn memory_size(&self) -> usize {
+        size_of::<Sample>() + self.metrics.keys().map(|metric| METRIC_ENTRY_SIZE + metric.len()).sum::<usize>()
     }
 }
 
-#[derive(Debug)]
-pub struct Storage {
+/// A metric of `Sample::metrics`: the key, its value and a share of the B-tree node.
+const METRIC_ENTRY_SIZE: usize = size_of::<String>() + size_of::<f64>() + 16;
+
+/// Samples of a single sensor, oldest first.
+#[derive(Debug, Default)]
+pub struct Series {
     pub(crate) samples: VecDeque<Sample>,
-    file_store: Option<File>,
     last_sample_time: Option<SystemTime>,
-    config: Config,
     last: Option<Sample>,
+    /// Sum of `Sample::memory_size` of `samples`.
+    memory_usage: usize,
 }
 
+static EMPTY_SERIES: Series = Series {
+    samples: VecDeque::new(),
+    last_sample_time: None,
+    last: None,
+    memory_usage: 0,
+};
+
 #[derive(Debug)]
-pub enum StorageError {
-    InvalidTimeRange,
-    NoDataAvailable,
+pub struct Storage {
+    series: BTreeMap<String, Series>,
+    backlog: Option<Backlog>,
+    config: Config,
+    stored: broadcast::Sender<StoredSample>,
 }
 
-impl Storage {
-    fn read_samples_from_file(&mut self, file_path: &str) -> Result<(), AppError> {
-        let file = File::open(file_path)?;
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let mut line = line?;
-            if let Ok(sample) = Sample::deserialize(&line) {
-                self.push_raw_sample(sample);
-            } else {
-                line.truncate(100);
-                error!("Failed to parse sample from file: {}", &line);
-            }
-        }
+/// Samples a live subscriber may fall behind by before it misses some.
+const SUBSCRIBER_BACKLOG: usize = 256;
 
-        Ok(())
-    }
+/// A reading as it is stored, sent to the subscribers of `Storage::subscribe`.
+#[derive(Debug, Clone)]
+pub struct StoredSample {
+    pub series: String,
+    pub sample: Sample,
+}
 
-    pub fn new(config: &Config) -> Result<Self, AppError> {
-        let mut rv = Self {
-            samples: VecDeque::new(),
-            file_store: None,
-            last_sample_time: None,
-            config: config.clone(),
-            last: None,
-        };
+/// Empty buckets repeating the previous average by default, bridging a missed reading or two.
+pub const DEFAULT_CARRY_FORWARD: usize = 5;
+
+/// Values of a `/temps` range by default and at most, a 30-day range averages over 30 minutes.
+pub const DEFAULT_MAX_POINTS: usize = 1500;
+pub const MIN_POINTS: usize = 10;
+pub const MAX_POINTS: usize = 10_000;
+
+/// Round averaging intervals in seconds a long range steps up through, from a minute to a day.
+const RESOLUTIONS: &[u64] = &[60, 120, 300, 600, 900, 1800, 3600, 7200, 10800, 21600, 43200, 86400];
+
+/// One `interval` of `Series::metric_buckets`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct Bucket {
+    /// Unix seconds the bucket starts at, `interval` after the previous one.
+    pub start: u64,
+    /// Average of the readings, or the previous average carried forward. `None` in a gap.
+    pub value: Option<f64>,
+    /// Readings in the bucket, 0 when empty.
+    pub count: usize,
+    /// No readings for longer than the carry-forward window, e.g. a sensor outage.
+    pub gap: bool,
+}
 
-        if let Some(file_path) = &config.backlog {
-            if !rv.read_samples_from_file(file_path).is_ok() {
-                info!("Failed to read samples from file");
-            };
-        }
+/// One window of `Series::rate_of_change`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct Rate {
+    /// Unix seconds the window starts at.
+    pub start: u64,
+    /// Change per hour, e.g. °C/h, `None` without enough readings.
+    pub per_hour: Option<f64>,
+}
 
-        info!("Storage initialized by {}", rv.samples.len());
+/// Position after a sample returned by `Series::page`: its time and how many samples of that
+/// time were returned up to it. Written as `<unix nanos>_<count>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub timestamp: SystemTime,
+    pub count: usize,
+}
 
-        rv.file_store = if let Some(file_path) = &config.backlog {
-            Some(File::options()
-                .create(true)
-                .append(true)
-                .open(file_path)?)
-        } else {
-            None
-        };
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = self.timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos();
+        write!(f, "{}_{}", nanos, self.count)
+    }
+}
 
-        Ok(rv)
+impl FromStr for Cursor {
+    type Err = AppError;
+
+    fn from_str(cursor: &str) -> Result<Self, Self::Err> {
+        let invalid = || AppError::BadRequest(format!("invalid cursor {:?}", cursor));
+        let (nanos, count) = cursor.split_once('_').ok_or_else(invalid)?;
+        let nanos: u64 = nanos.parse().map_err(|_| invalid())?;
+        Ok(Cursor {
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos),
+            count: count.parse().map_err(|_| invalid())?,
+        })
     }
+}
+
+/// One page of `Series::page`.
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub samples: Vec<Sample>,
+    /// After the last sample of the page, or the requested cursor for an empty page.
+    pub next: Option<Cursor>,
+    /// More samples up to `to` follow the page.
+    pub more: bool,
+}
+
+/// One calendar day of `Series::daily`.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Day {
+    /// Local date, `YYYY-MM-DD`.
+    pub date: String,
+    pub count: usize,
+    pub min: f64,
+    /// Unix seconds of the minimum.
+    pub min_at: u64,
+    pub max: f64,
+    /// Unix seconds of the maximum.
+    pub max_at: u64,
+    pub mean: f64,
+}
 
+#[derive(Debug)]
+pub enum StorageError {
+    InvalidTimeRange,
+    NoDataAvailable,
+}
 
-    pub fn push_raw_sample(&mut self, sample: Sample) {
+impl Series {
+    /// Returns whether the sample was stored.
+    fn push_raw_sample(&mut self, sample: Sample, max_capacity: Option<usize>) -> bool {
         if let Some(last_sample_time) = self.last_sample_time {
             if last_sample_time > sample.timestamp {
                 error!("Sample timestamp is in the past");
-                return;
+                return false;
             }
         }
 
         self.last_sample_time = Some(sample.timestamp);
-        if let Some(capacity) = self.config.max_capacity {
+        if let Some(capacity) = max_capacity {
             if capacity == 0 {
                 // Don't store anything if capacity is zero
-                return;
+                return false;
             }
             if self.samples.len() >= capacity {
-                self.samples.pop_front();
+                self.pop_oldest();
             }
         }
+        self.memory_usage += sample.memory_size();
         self.samples.push_back(sample.clone());
         self.last = Some(sample);
+        true
     }
 
-    pub fn add_measurement(&mut self, temp: f64, _hum: f64) {
-        let sample = Sample {
-            timestamp: SystemTime::now(),
-            temperature: temp,
-         //   humidity: hum,
-        };
-
-        if let Some(file_store) = &mut self.file_store {
-            if let Ok(mut s) = sample.serialize() {
-                s.push('\n');
-                if !file_store.write(s.as_bytes()).is_ok() {
-                    info!("Failed to write sample to file");
-                };
-            }
-        }
+    fn pop_oldest(&mut self) -> Option<Sample> {
+        let sample = self.samples.pop_front()?;
+        self.memory_usage -= sample.memory_size();
+        Some(sample)
+    }
 
-        self.push_raw_sample(sample);
+    /// Approximate bytes of the samples in memory.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
     }
 
     pub fn get_samples_in_range(&self, from: SystemTime, to: SystemTime) -> Result<Vec<&Sample>, StorageError> {
@@ -170,7 +249,19 @@ impl Storage {
         Ok(samples)
     }
 
-    pub fn per_minute_avg_fill(&self, from: SystemTime, to: SystemTime) -> Result<Vec<Option<f64>>, StorageError> {
+    pub fn per_minute_avg_fill(&self, from: SystemTime, to: SystemTime, interval: Duration) -> Result<Vec<Option<f64>>, StorageError> {
+        self.metric_avg_fill(TEMPERATURE, from, to, interval)
+    }
+
+    /// Like `per_minute_avg_fill` for any metric, readings without the metric are skipped.
+    pub fn metric_avg_fill(&self, metric: &str, from: SystemTime, to: SystemTime, interval: Duration) -> Result<Vec<Option<f64>>, StorageError> {
+        let buckets = self.metric_buckets(metric, from, to, interval, DEFAULT_CARRY_FORWARD)?;
+        Ok(buckets.into_iter().map(|bucket| bucket.value).collect())
+    }
+
+    /// Averages of `metric` per `interval`, oldest first. Up to `carry_forward` empty buckets
+    /// repeat the previous average, after that they are gaps.
+    pub fn metric_buckets(&self, metric: &str, from: SystemTime, to: SystemTime, interval: Duration, carry_forward: usize) -> Result<Vec<Bucket>, StorageError> {
         if from > to {
             return Err(StorageError::InvalidTimeRange);
         }
@@ -196,10 +287,10 @@ impl Storage {
         let mut previous_average: Option<f64> = None;
         let mut averages = Vec::new();
         let mut no_samples_count = 0;
+        // a downsampled sample stands for its whole span
+        let mut covered_until = timestamp;
         let mut fin = false;
 
-        let interval = Duration::from_secs(self.config.averaging_interval as u64);
-
         let mut loops = 0;
 
         while !fin {
@@ -213,8 +304,13 @@ impl Storage {
             match it.peek() {
                 Some(curr) => {
                     if curr.timestamp < timestamp + interval {
-                        sum += curr.temperature;
-                        count += 1;
+                        if let Some(value) = curr.value(metric) {
+                            sum += value;
+                            count += 1;
+                            if let Some(span) = curr.span {
+                                covered_until = covered_until.max(curr.timestamp + span);
+                            }
+                        }
                         it.next();
                         continue;
                     }
@@ -232,13 +328,19 @@ impl Storage {
                 no_samples_count += 1;
             }
 
-            timestamp = timestamp + interval;
+            let start = timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+            timestamp += interval;
 
-            if no_samples_count > 5 {
+            if no_samples_count > carry_forward && timestamp > covered_until {
                 previous_average = None;
             }
 
-            averages.push(previous_average);
+            averages.push(Bucket {
+                start,
+                value: previous_average,
+                count,
+                gap: previous_average.is_none(),
+            });
             if averages.len() > 100000 {
                 error!("Too many entries in averages, something is wrong");
                 break;
@@ -254,11 +356,95 @@ impl Storage {
         self.last.as_ref()
     }
 
-    #[allow(dead_code)]
+    /// Statistics of every metric of the samples between `from` and `to`, by metric name.
+    pub fn stats(&self, from: SystemTime, to: SystemTime) -> Result<BTreeMap<String, Stats>, StorageError> {
+        let samples = self.get_samples_in_range(from, to)?;
+        let mut values: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+        for sample in samples {
+            values.entry(TEMPERATURE).or_default().push(sample.temperature);
+            for (metric, value) in &sample.metrics {
+                values.entry(metric).or_default().push(*value);
+            }
+            for derived in DERIVED_METRICS.iter().filter(|derived| !sample.metrics.contains_key(derived.name)) {
+                if let Some(value) = (derived.compute)(sample) {
+                    values.entry(derived.name).or_default().push(value);
+                }
+            }
+        }
+        Ok(values.into_iter()
+            .filter_map(|(metric, values)| Some((metric.to_string(), Stats::from_values(values)?)))
+            .collect())
+    }
+
+    /// Change of `metric` per hour in consecutive `window`s from `from` to `to`, the slope of a
+    /// linear fit of the window's readings. `None` for windows with fewer than two readings.
+    pub fn rate_of_change(&self, metric: &str, from: SystemTime, to: SystemTime, window: Duration) -> Result<Vec<Rate>, StorageError> {
+        let samples = self.get_samples_in_range(from, to)?;
+        let mut rates = Vec::new();
+        let mut samples = samples.into_iter().peekable();
+        let mut start = from;
+        while start < to && !window.is_zero() {
+            let end = start + window;
+            let mut points = Vec::new();
+            while let Some(sample) = samples.next_if(|sample| sample.timestamp < end) {
+                if let Some(value) = sample.value(metric) {
+                    let hours = sample.timestamp.duration_since(start).unwrap_or_default().as_secs_f64() / 3600.0;
+                    points.push((hours, value));
+                }
+            }
+            rates.push(Rate {
+                start: start.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs(),
+                per_hour: slope(&points),
+            });
+            start = end;
+        }
+        Ok(rates)
+    }
+
+    /// Minimum, maximum and mean of `metric` per calendar day of `tz` between `from` and `to`,
+    /// so a day spans 23 or 25 hours across a DST change. Days without readings are left out.
+    pub fn daily<Z: TimeZone>(&self, metric: &str, from: SystemTime, to: SystemTime, tz: &Z) -> Result<Vec<Day>, StorageError> {
+        let samples = self.get_samples_in_range(from, to)?;
+        let mut days: Vec<(NaiveDate, Day, f64)> = Vec::new();
+        for sample in samples {
+            let Some(value) = sample.value(metric).filter(|value| value.is_finite()) else {
+                continue;
+            };
+            let date = DateTime::<Utc>::from(sample.timestamp).with_timezone(tz).date_naive();
+            let at = sample.timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+            match days.last_mut() {
+                Some((last, day, sum)) if *last == date => {
+                    day.count += 1;
+                    *sum += value;
+                    if value < day.min {
+                        day.min = value;
+                        day.min_at = at;
+                    }
+                    if value > day.max {
+                        day.max = value;
+                        day.max_at = at;
+                    }
+                }
+                _ => days.push((date, Day {
+                    date: date.format("%Y-%m-%d").to_string(),
+                    count: 1,
+                    min: value,
+                    min_at: at,
+                    max: value,
+                    max_at: at,
+                    mean: 0.0,
+                }, value)),
+            }
+        }
+        Ok(days.into_iter()
+            .map(|(_, day, sum)| Day { mean: sum / day.count as f64, ..day })
+            .collect())
+    }
+
     pub fn read_sample(&self, from: SystemTime, duration: Duration) -> Result<Sample, StorageError> {
         let to = from + duration;
         let samples = self.get_samples_in_range(from, to)?;
-        
+
         if let Some(sample) = samples.first() {
             Ok((*sample).clone())
         } else {
@@ -266,12 +452,10 @@ impl Storage {
         }
     }
 
-    #[allow(dead_code)]
     pub fn len(&self) -> usize {
         self.samples.len()
     }
 
-    #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.samples.is_empty()
     }
@@ -284,10 +468,500 @@ impl Storage {
         self.samples.front()
     }
 
+    /// Up to `limit` samples up to `to`, oldest first, from `from` or following `after`.
+    pub fn page(&self, from: SystemTime, to: SystemTime, after: Option<Cursor>, limit: usize) -> Page {
+        // a cursor taken before samples were purged or deleted may point past the end
+        let start = match after {
+            Some(cursor) => self.samples.partition_point(|sample| sample.timestamp < cursor.timestamp).saturating_add(cursor.count),
+            None => self.samples.partition_point(|sample| sample.timestamp < from),
+        }.min(self.samples.len());
+        let end = self.samples.partition_point(|sample| sample.timestamp <= to).max(start);
+        let stop = end.min(start.saturating_add(limit));
+        let samples: Vec<Sample> = self.samples.range(start.min(end)..stop).cloned().collect();
+        let next = match samples.last() {
+            Some(last) => Some(Cursor {
+                timestamp: last.timestamp,
+                count: stop - self.samples.partition_point(|sample| sample.timestamp < last.timestamp),
+            }),
+            None => after,
+        };
+        Page { samples, next, more: stop < end }
+    }
+
+    /// Rolls the samples up according to `tiers`, returns by how many samples the series shrank.
+    pub fn downsample(&mut self, tiers: &[Tier]) -> usize {
+        let Some(newest) = tiers.iter().map(|tier| tier.before).max() else {
+            return 0;
+        };
+        let end = self.samples.partition_point(|sample| sample.timestamp < newest);
+        let old: Vec<Sample> = self.samples.drain(..end).collect();
+        self.memory_usage -= old.iter().map(Sample::memory_size).sum::<usize>();
+        let rolled = roll_up(old, tiers);
+        let shrunk = end - rolled.len();
+        for sample in rolled.into_iter().rev() {
+            self.memory_usage += sample.memory_size();
+            self.samples.push_front(sample);
+        }
+        shrunk
+    }
+
+    /// Drops the samples taken between `from` and `to`, including a removed last sample.
+    pub fn remove_range(&mut self, from: SystemTime, to: SystemTime) -> usize {
+        let start = self.samples.partition_point(|sample| sample.timestamp < from);
+        let end = self.samples.partition_point(|sample| sample.timestamp <= to);
+        if start >= end {
+            return 0;
+        }
+        for sample in self.samples.drain(start..end) {
+            self.memory_usage -= sample.memory_size();
+        }
+        if self.last.as_ref().is_some_and(|last| last.timestamp >= from && last.timestamp <= to) {
+            self.last = self.samples.back().cloned();
+        }
+        end - start
+    }
+
+    /// Drops the samples taken before `cutoff`, `get_last_sample` keeps returning the last one.
+    pub fn evict_before(&mut self, cutoff: SystemTime) -> usize {
+        let keep_from = self.samples.partition_point(|sample| sample.timestamp < cutoff);
+        for sample in self.samples.drain(..keep_from) {
+            self.memory_usage -= sample.memory_size();
+        }
+        keep_from
+    }
+
+    /// Drops every sample, the newest one is still known to `get_last_sample`. Returns how many
+    /// were dropped.
+    pub fn clear(&mut self) -> usize {
+        self.memory_usage = 0;
+        self.samples.drain(..).count()
+    }
+}
+
+impl Storage {
+    pub fn new(config: &Config) -> Result<Self, AppError> {
+        let mut rv = Self {
+            series: BTreeMap::new(),
+            backlog: None,
+            config: config.clone(),
+            stored: broadcast::channel(SUBSCRIBER_BACKLOG).0,
+        };
+
+        let cutoff = config.retention().map(|retention| SystemTime::now() - retention);
+        let restored = match &config.snapshot {
+            Some(path) => rv.restore_snapshot(path, cutoff),
+            None => false,
+        };
+        if let (false, Some(file_path)) = (restored, &config.backlog) {
+            rv.replay_backlog(file_path, cutoff)?;
+        }
+
+        if let Some(downsampling) = &config.downsampling {
+            let tiers = downsampling.tiers(SystemTime::now());
+            for series in rv.series.values_mut() {
+                series.downsample(&tiers);
+            }
+        }
+
+        for (name, series) in &rv.series {
+            info!("Storage initialized by {} samples of {} (~{} KB)", series.len(), name, series.memory_usage() / 1024);
+        }
+
+        Ok(rv)
+    }
+
+
+    fn replay_backlog(&mut self, file_path: &str, cutoff: Option<SystemTime>) -> Result<(), AppError> {
+        if let Some(cutoff) = cutoff {
+            Backlog::prune_segments(file_path, cutoff)?;
+        }
+        if self.config.compress_backlog {
+            Backlog::compress_closed_segments(file_path)?;
+        }
+        let (backlog, mut samples) = Backlog::open(file_path, self.config.backlog_format)?;
+        // older segments are only read while a series still has room for their samples
+        for segment in Backlog::closed_segments(file_path)? {
+            if is_filled(&samples, self.config.max_capacity, self.config.memory_budget()) {
+                break;
+            }
+            samples.extend(Backlog::load_segment(&segment)?);
+        }
+        if let Some(cutoff) = cutoff {
+            samples.retain(|(_, sample)| sample.timestamp >= cutoff);
+        }
+        let duplicates = dedup_records(&mut samples);
+        if duplicates > 0 {
+            info!("Dropped {} duplicate samples of the backlog", duplicates);
+        }
+        for (series, sample) in samples {
+            self.push_raw_sample(&series, sample);
+        }
+        self.set_backlog(backlog);
+        Ok(())
+    }
+
+    fn set_backlog(&mut self, backlog: Backlog) {
+        self.backlog = Some(backlog
+            .with_rotation(self.config.backlog_rotation())
+            .with_flushing(self.config.backlog_flushing()));
+    }
+
+    /// Loads the snapshot at `path` unless it is missing, unreadable or older than the backlog.
+    fn restore_snapshot(&mut self, path: &str, cutoff: Option<SystemTime>) -> bool {
+        let snapshot = match Snapshot::read(path) {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => return false,
+            Err(e) => {
+                warn!("Ignoring snapshot: {}", e);
+                return false;
+            }
+        };
+        // a snapshot is used once, after a crash the backlog has to be replayed
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!("Ignoring snapshot {} that cannot be removed: {}", path, e);
+            return false;
+        }
+        if let Some(file_path) = &self.config.backlog {
+            match Backlog::resume(file_path, snapshot.backlog_size, snapshot.backlog_started) {
+                Ok(Some(backlog)) => self.set_backlog(backlog),
+                Ok(None) => {
+                    info!("Backlog changed since the snapshot was taken, replaying it");
+                    return false;
+                }
+                Err(e) => {
+                    warn!("Ignoring snapshot: {}", e);
+                    return false;
+                }
+            }
+        }
+        for (series, sample) in snapshot.records {
+            if cutoff.is_none_or(|cutoff| sample.timestamp >= cutoff) {
+                self.push_raw_sample(&series, sample);
+            }
+        }
+        info!("Restored snapshot {}", path);
+        true
+    }
+
+    /// Writes the samples in memory to `snapshot`, after syncing the backlog, on shutdown.
+    pub fn write_snapshot(&mut self) -> Result<(), AppError> {
+        self.sync_backlog()?;
+        let Some(path) = &self.config.snapshot else {
+            return Ok(());
+        };
+        let snapshot = Snapshot {
+            backlog_size: self.backlog.as_ref().map_or(0, Backlog::size),
+            backlog_started: self.backlog.as_ref().and_then(Backlog::started),
+            records: self.series.iter()
+                .flat_map(|(name, series)| series.samples.iter().map(|sample| (name.clone(), sample.clone())))
+                .collect(),
+        };
+        snapshot.write(path)?;
+        info!("Wrote snapshot of {} samples to {}", snapshot.records.len(), path);
+        Ok(())
+    }
+
+    pub fn push_raw_sample(&mut self, series: &str, sample: Sample) -> bool {
+        let max_capacity = self.config.max_capacity;
+        let stored = self.series
+            .entry(series.to_string())
+            .or_default()
+            .push_raw_sample(sample, max_capacity);
+        self.enforce_memory_budget();
+        stored
+    }
+
+    /// Receives every sample stored by `add_sample` from now on, e.g. for pushing live updates.
+    pub fn subscribe(&self) -> broadcast::Receiver<StoredSample> {
+        self.stored.subscribe()
+    }
+
+    /// Approximate bytes of the samples of every series in memory.
+    pub fn memory_usage(&self) -> usize {
+        self.series.values().map(Series::memory_usage).sum()
+    }
+
+    /// Evicts the oldest samples across all series while `max_memory_mb` is exceeded.
+    fn enforce_memory_budget(&mut self) {
+        let Some(budget) = self.config.memory_budget() else {
+            return;
+        };
+        let mut usage = self.memory_usage();
+        while usage > budget {
+            let oldest = self.series.values_mut()
+                .filter(|series| !series.samples.is_empty())
+                .min_by_key(|series| series.samples.front().map(|sample| sample.timestamp));
+            let Some(sample) = oldest.and_then(Series::pop_oldest) else {
+                break;
+            };
+            usage -= sample.memory_size();
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn add_measurement(&mut self, temp: f64, hum: f64) {
+        self.add_sensor_measurement(DEFAULT_SERIES, temp, hum);
+    }
+
+    pub fn add_sensor_measurement(&mut self, series: &str, temp: f64, hum: f64) {
+        self.add_sensor_measurement_at(series, SystemTime::now(), temp, hum);
+    }
+
+    /// Like `add_sensor_measurement` but with the time the measurement was taken,
+    /// samples older than the newest one of the series are dropped.
+    pub fn add_sensor_measurement_at(&mut self, series: &str, timestamp: SystemTime, temp: f64, hum: f64) {
+        let mut sample = Sample::new(timestamp, temp);
+        // sensors without a humidity probe report NaN
+        if hum.is_finite() {
+            sample.metrics.insert(HUMIDITY.to_string(), hum);
+        }
+        self.add_sample(series, sample);
+    }
+
+    /// Stores a reading with any metrics to the series and the backlog.
+    pub fn add_sample(&mut self, series: &str, sample: Sample) {
+        if let Some(backlog) = &mut self.backlog {
+            if backlog.append(series, &sample).is_err() {
+                info!("Failed to write sample to file");
+            }
+        }
+
+        if self.push_raw_sample(series, sample.clone()) {
+            // nobody listening is not an error
+            let _ = self.stored.send(StoredSample { series: series.to_string(), sample });
+        }
+    }
+
+    /// Stores the samples, oldest first, newer than the newest one of the series and within
+    /// `retention_days`, e.g. copied from a peer instance; returns how many were stored.
+    pub fn backfill(&mut self, series: &str, samples: Vec<Sample>) -> usize {
+        let latest = self.series(series).and_then(Series::latest_sample).map(|sample| sample.timestamp);
+        let cutoff = self.config.retention().map(|retention| SystemTime::now() - retention);
+        let mut added = 0;
+        for sample in samples {
+            if latest.is_some_and(|latest| sample.timestamp <= latest)
+                || cutoff.is_some_and(|cutoff| sample.timestamp < cutoff) {
+                continue;
+            }
+            self.add_sample(series, sample);
+            added += 1;
+        }
+        added
+    }
+
+    /// Deletes the samples of `series` taken between `from` and `to` from memory and the open
+    /// backlog, returns how many were removed from memory. Its closed segments are left to
+    /// `Backlog::remove_from_segments`, which doesn't need the storage.
+    pub fn delete_range(&mut self, series: &str, from: SystemTime, to: SystemTime) -> Result<usize, AppError> {
+        let removed = self.series.get_mut(series).map_or(0, |series| series.remove_range(from, to));
+        if let Some(backlog) = &mut self.backlog {
+            let removed = backlog.remove_range(series, from, to)?;
+            info!("Removed {} samples of {} from the backlog", removed, series);
+        }
+        Ok(removed)
+    }
+
+    /// Drops the samples of `series`, or of every series, from memory, e.g. to recover from a
+    /// memory spike; the backlog keeps them. Returns how many were dropped.
+    pub fn purge(&mut self, series: Option<&str>) -> usize {
+        let purged = self.series.iter_mut()
+            .filter(|(name, _)| series.is_none_or(|series| series == name.as_str()))
+            .map(|(_, series)| series.clear())
+            .sum();
+        info!("Purged {} samples from memory", purged);
+        purged
+    }
+
+    /// Takes the capacity, retention, downsampling, averaging and `max_points` settings of a
+    /// re-read `config` and trims the series to a lowered capacity; the backlog and snapshot
+    /// settings keep the values the storage was opened with.
+    pub fn reload(&mut self, config: &Config) {
+        self.config.max_capacity = config.max_capacity;
+        self.config.max_memory_mb = config.max_memory_mb;
+        self.config.retention_days = config.retention_days;
+        self.config.downsampling = config.downsampling.clone();
+        self.config.averaging_interval = config.averaging_interval;
+        self.config.carry_forward_buckets = config.carry_forward_buckets;
+        self.config.max_points = config.max_points;
+        if let Some(capacity) = self.config.max_capacity {
+            for series in self.series.values_mut() {
+                while series.len() > capacity && series.pop_oldest().is_some() {}
+            }
+        }
+        self.enforce_memory_budget();
+    }
+
+    /// Evicts samples older than `retention_days` before `now` from every series and
+    /// deletes closed backlog segments holding only such samples, returns the evicted count.
+    pub fn apply_retention(&mut self, now: SystemTime) -> Result<usize, AppError> {
+        let Some(cutoff) = self.config.retention().map(|retention| now - retention) else {
+            return Ok(0);
+        };
+        let evicted = self.series.values_mut().map(|series| series.evict_before(cutoff)).sum();
+        if let Some(path) = &self.config.backlog {
+            Backlog::prune_segments(path, cutoff)?;
+        }
+        Ok(evicted)
+    }
+
+    /// Rolls up old samples in memory and in the closed backlog segments per `[downsampling]`,
+    /// returns by how many samples the series in memory shrank.
+    pub fn downsample(&mut self, now: SystemTime) -> Result<usize, AppError> {
+        let Some(tiers) = self.config.downsampling.as_ref().map(|downsampling| downsampling.tiers(now)) else {
+            return Ok(0);
+        };
+        let shrunk = self.series.values_mut().map(|series| series.downsample(&tiers)).sum();
+        if let Some(path) = &self.config.backlog {
+            Backlog::downsample_segments(path, &tiers)?;
+        }
+        Ok(shrunk)
+    }
+
+    /// Path of the open backlog.
+    pub fn backlog_path(&self) -> Option<&str> {
+        self.backlog.as_ref().and(self.config.backlog.as_deref())
+    }
+
+    /// Writes the buffered backlog records, syncing them per `backlog_fsync_interval`.
+    pub fn flush_backlog(&mut self) -> Result<(), AppError> {
+        match &mut self.backlog {
+            Some(backlog) => backlog.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes and syncs the buffered backlog records, on shutdown.
+    pub fn sync_backlog(&mut self) -> Result<(), AppError> {
+        match &mut self.backlog {
+            Some(backlog) => backlog.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Bytes of the current backlog file, `None` without a backlog.
+    pub fn backlog_size(&self) -> Option<u64> {
+        self.backlog.as_ref().map(Backlog::size)
+    }
+
+    /// Returns the series stored under `name`, if any sample was ever recorded for it.
+    pub fn series(&self, name: &str) -> Option<&Series> {
+        self.series.get(name)
+    }
+
+    #[allow(dead_code)]
+    pub fn series_names(&self) -> impl Iterator<Item = &str> {
+        self.series.keys().map(|name| name.as_str())
+    }
+
+    pub fn averaging_interval(&self) -> Duration {
+        Duration::from_secs(self.config.averaging_interval as u64)
+    }
+
+    pub fn carry_forward(&self) -> usize {
+        self.config.carry_forward_buckets
+    }
+
+    /// Averaging interval of a `/temps` range without an explicit one: `averaging_interval`,
+    /// or the shortest of `RESOLUTIONS` splitting `span` into at most `max_points` values,
+    /// whole days beyond them.
+    pub fn resolution(&self, span: Duration) -> Duration {
+        let base = self.averaging_interval().as_secs().max(1);
+        let max_points = self.config.max_points.max(1) as u64;
+        let needed = span.as_secs().div_ceil(max_points);
+        if needed <= base {
+            return Duration::from_secs(base);
+        }
+        let secs = RESOLUTIONS.iter()
+            .copied()
+            .find(|&secs| secs >= needed)
+            .unwrap_or_else(|| needed.div_ceil(86400) * 86400);
+        Duration::from_secs(secs)
+    }
+
+    fn default_series(&self) -> &Series {
+        self.series(DEFAULT_SERIES).unwrap_or(&EMPTY_SERIES)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_samples_in_range(&self, from: SystemTime, to: SystemTime) -> Result<Vec<&Sample>, StorageError> {
+        self.default_series().get_samples_in_range(from, to)
+    }
+
+    #[allow(dead_code)]
+    pub fn per_minute_avg_fill(&self, from: SystemTime, to: SystemTime) -> Result<Vec<Option<f64>>, StorageError> {
+        self.default_series().per_minute_avg_fill(from, to, self.averaging_interval())
+    }
+
+    #[allow(dead_code)]
+    pub fn stats(&self, from: SystemTime, to: SystemTime) -> Result<BTreeMap<String, Stats>, StorageError> {
+        self.default_series().stats(from, to)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_last_sample(&self) -> Option<&Sample> {
+        self.default_series().get_last_sample()
+    }
+
+    #[allow(dead_code)]
+    pub fn read_sample(&self, from: SystemTime, duration: Duration) -> Result<Sample, StorageError> {
+        self.default_series().read_sample(from, duration)
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.default_series().len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.default_series().is_empty()
+    }
+
+    #[allow(dead_code)]
+    pub fn latest_sample(&self) -> Option<&Sample> {
+        self.default_series().latest_sample()
+    }
+
+    #[allow(dead_code)]
+    pub fn oldest_sample(&self) -> Option<&Sample> {
+        self.default_series().oldest_sample()
+    }
+
     // Helper method for testing - only available when testing
     #[cfg(any(test, feature = "test-helpers"))]
     #[allow(dead_code)]
     pub fn add_sample_direct(&mut self, sample: Sample) {
-        self.samples.push_back(sample);
+        self.series
+            .entry(DEFAULT_SERIES.to_string())
+            .or_default()
+            .samples
+            .push_back(sample);
+    }
+}
+
+/// Sorts `records` by timestamp and keeps only the first loaded record of a series per
+/// timestamp, e.g. of segments overlapping after an import or a crash mid-compression.
+/// Returns how many were dropped.
+fn dedup_records(records: &mut Vec<Record>) -> usize {
+    let len = records.len();
+    // stable, so the live backlog and newer segments, loaded first, win
+    records.sort_by(|(a_series, a), (b_series, b)| a.timestamp.cmp(&b.timestamp).then_with(|| a_series.cmp(b_series)));
+    records.dedup_by(|(series, sample), (kept_series, kept)| series == kept_series && sample.timestamp == kept.timestamp);
+    len - records.len()
+}
+
+/// Whether every series of `samples` holds at least `max_capacity` samples or all of them
+/// together take at least `memory_budget` bytes.
+fn is_filled(samples: &[Record], max_capacity: Option<usize>, memory_budget: Option<usize>) -> bool {
+    if memory_budget.is_some_and(|budget| samples.iter().map(|(_, sample)| sample.memory_size()).sum::<usize>() >= budget) {
+        return true;
+    }
+    let Some(max_capacity) = max_capacity else {
+        return false;
+    };
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (series, _) in samples {
+        *counts.entry(series).or_default() += 1;
     }
+    !counts.is_empty() && counts.values().all(|&count| count >= max_capacity)
 }
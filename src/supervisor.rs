@@ -0,0 +1,39 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use log::{error, warn};
+use crate::sensor_status::SensorStatuses;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Runs the task created by `start` for sensor `name` and creates it anew whenever it
+/// panics, is cancelled or returns, waiting twice as long after each quick restart.
+pub async fn supervise<F, Fut>(name: String, statuses: Arc<Mutex<SensorStatuses>>, start: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut restarts: u32 = 0;
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        let started = Instant::now();
+        let reason = match tokio::spawn(start()).await {
+            Ok(()) => "stopped".to_string(),
+            Err(e) if e.is_panic() => "panicked".to_string(),
+            Err(e) => format!("failed: {}", e),
+        };
+        // a task that ran for a while failed on its own, not because of the last restart
+        if started.elapsed() > MAX_BACKOFF {
+            backoff = Duration::from_secs(1);
+        }
+        restarts += 1;
+        error!("{}: sampling task {}, restart #{} in {:?}", name, reason, restarts, backoff);
+        if let Ok(mut statuses) = statuses.lock() {
+            statuses.record_failure(&name, &format!("sampling task {}", reason));
+        } else {
+            warn!("{}: failed to lock sensor statuses", name);
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,64 @@
+use serde_json::{Map, Value};
+use crate::app_error::AppError;
+use crate::temp_sensor::Measurement;
+
+/// Tasmota device, read from the `Status 10` (sensors) command of its HTTP API.
+pub struct TasmotaSensor {
+    url: String,
+    sensor: Option<String>,
+    client: reqwest::Client,
+}
+
+impl TasmotaSensor {
+    /// `url` is the device root, `sensor` picks a section of `StatusSNS`
+    /// like `DS18B20-2` when more probes are attached.
+    pub fn new(url: &str, sensor: Option<&str>, client: reqwest::Client) -> Self {
+        Self {
+            url: format!("{}/cm?cmnd=Status%2010", url.trim_end_matches('/')),
+            sensor: sensor.map(str::to_string),
+            client,
+        }
+    }
+
+    pub async fn query(&self) -> Result<Measurement, AppError> {
+        let body = self.client.get(&self.url).send().await?.bytes().await?;
+        parse_status(&serde_json::from_slice(&body)?, self.sensor.as_deref())
+    }
+}
+
+/// Reads the configured (or the first) `StatusSNS` section reporting a temperature,
+/// e.g. `AM2301`, `DS18B20` or `BME280`.
+fn parse_status(status: &Value, sensor: Option<&str>) -> Result<Measurement, AppError> {
+    let sns = status.get("StatusSNS")
+        .and_then(Value::as_object)
+        .ok_or_else(|| AppError::TemperatureSensorError("no StatusSNS in Tasmota status".to_string()))?;
+    let section = match sensor {
+        Some(name) => sns.get(name)
+            .and_then(Value::as_object)
+            .ok_or_else(|| AppError::TemperatureSensorError(format!("no {} in Tasmota status", name)))?,
+        None => sns.values()
+            .filter_map(Value::as_object)
+            .find(|section| section.contains_key("Temperature"))
+            .ok_or_else(|| AppError::TemperatureSensorError("no temperature sensor in Tasmota status".to_string()))?,
+    };
+    let value = |key: &str| section.get(key).and_then(Value::as_f64);
+
+    let temperature = value("Temperature")
+        .ok_or_else(|| AppError::TemperatureSensorError("no Temperature in Tasmota sensor".to_string()))?;
+    let mut measurement = Measurement::new(to_celsius(sns, temperature), value("Humidity").unwrap_or(f64::NAN));
+    if let Some(pressure) = value("Pressure") {
+        measurement.metrics.insert("pressure".to_string(), pressure);
+    }
+    if let Some(dew_point) = value("DewPoint") {
+        measurement.metrics.insert("dew_point".to_string(), to_celsius(sns, dew_point));
+    }
+    Ok(measurement)
+}
+
+/// Tasmota reports in Fahrenheit with `SetOption8 1`.
+fn to_celsius(sns: &Map<String, Value>, temperature: f64) -> f64 {
+    match sns.get("TempUnit").and_then(Value::as_str) {
+        Some("F") => (temperature - 32.0) * 5.0 / 9.0,
+        _ => temperature,
+    }
+}
@@ -0,0 +1,326 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use anyhow::{anyhow, bail, Context};
+use chrono_tz::Tz;
+use log::{error, info, warn};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+use crate::alerts::{next_event, retry, unix_secs, AlertEvent, Alerts, Delivery, DeliveryLog};
+use crate::app_error::AppError;
+use crate::chart::{local_time, metric_label, time_format, Chart, Line};
+use crate::config::TelegramConfig;
+use crate::storage::{Storage, HUMIDITY, TEMPERATURE};
+
+/// Seconds the Bot API gets per request.
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+/// Seconds `getUpdates` waits for a message before returning empty.
+const POLL_TIMEOUT_SECS: u64 = 50;
+/// Seconds before polling again after a failed `getUpdates`.
+const POLL_RETRY_SECS: u64 = 10;
+/// Further attempts at an alert message, 5 seconds apart and doubling.
+const RETRIES: u32 = 3;
+const RETRY_BACKOFF_SECS: u64 = 5;
+/// Range of `/chart` by default and at most.
+const DEFAULT_CHART_HOURS: u64 = 24;
+const MAX_CHART_HOURS: u64 = 30 * 24;
+const CHART_WIDTH: u32 = 800;
+const CHART_HEIGHT: u32 = 400;
+
+const HELP: &str = "/temp [sensor] - the latest readings\n\
+    /chart [sensor] [24h|7d] - chart of the temperature\n\
+    /alerts - the active alerts";
+
+/// A command sent to the bot.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// The latest reading of one sensor or all of them.
+    Temp { sensor: Option<String> },
+    Chart { sensor: Option<String>, hours: u64 },
+    Alerts,
+    Help,
+}
+
+/// Reads `/temp office`, `/chart 7d` or `/chart@HeaterBot office 12h`, `None` for other text.
+pub fn parse_command(text: &str) -> Option<Command> {
+    let mut words = text.split_whitespace();
+    let command = words.next()?.strip_prefix('/')?;
+    let command = command.split('@').next().unwrap_or(command);
+    let args: Vec<&str> = words.collect();
+    match command {
+        "temp" => Some(Command::Temp { sensor: args.first().map(|sensor| sensor.to_string()) }),
+        "chart" => {
+            let mut sensor = None;
+            let mut hours = DEFAULT_CHART_HOURS;
+            for arg in args {
+                match parse_hours(arg) {
+                    Some(value) => hours = value.clamp(1, MAX_CHART_HOURS),
+                    None => sensor = Some(arg.to_string()),
+                }
+            }
+            Some(Command::Chart { sensor, hours })
+        }
+        "alerts" => Some(Command::Alerts),
+        "start" | "help" => Some(Command::Help),
+        _ => None,
+    }
+}
+
+/// `12h`, `7d` or `12` (hours).
+fn parse_hours(arg: &str) -> Option<u64> {
+    if let Some(days) = arg.strip_suffix('d') {
+        // too many days for a u64 of hours are clamped like any other too long range
+        return days.parse::<u64>().ok().map(|days| days.saturating_mul(24));
+    }
+    arg.strip_suffix('h').unwrap_or(arg).parse().ok()
+}
+
+/// Client of the Telegram Bot API for the configured bot.
+pub struct Telegram {
+    client: reqwest::Client,
+    config: TelegramConfig,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+impl Telegram {
+    pub fn new(config: &TelegramConfig, client: reqwest::Client) -> Self {
+        Self { client, config: config.clone() }
+    }
+
+    /// The chats, for the delivery log.
+    pub fn recipients(&self) -> String {
+        self.config.chat_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Calls a Bot API method, the result of the response. Errors leave the URL, which holds
+    /// the token, out.
+    async fn call(&self, method: &str, request: impl FnOnce(reqwest::RequestBuilder) -> reqwest::RequestBuilder) -> Result<Value, anyhow::Error> {
+        let url = format!("{}/bot{}/{}", self.config.api_url.trim_end_matches('/'), self.config.bot_token, method);
+        let request = request(self.client.post(url).timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS)));
+        let response = request.send().await.map_err(|e| e.without_url())?;
+        let body = response.text().await.map_err(|e| e.without_url())?;
+        let body: Value = serde_json::from_str(&body).with_context(|| format!("{} returned no JSON", method))?;
+        if body["ok"] != true {
+            bail!("{} failed: {}", method, body["description"].as_str().unwrap_or("no description"));
+        }
+        Ok(body["result"].clone())
+    }
+
+    pub async fn send_message(&self, chat_id: i64, text: &str) -> Result<(), anyhow::Error> {
+        let body = json!({ "chat_id": chat_id, "text": text });
+        self.call("sendMessage", |request| request.header(CONTENT_TYPE, "application/json").body(body.to_string())).await?;
+        Ok(())
+    }
+
+    pub async fn send_photo(&self, chat_id: i64, png: Vec<u8>, caption: &str) -> Result<(), anyhow::Error> {
+        let form = Form::new()
+            .text("chat_id", chat_id.to_string())
+            .text("caption", caption.to_string())
+            .part("photo", Part::bytes(png).file_name("chart.png").mime_str("image/png")?);
+        self.call("sendPhoto", |request| request.multipart(form)).await?;
+        Ok(())
+    }
+
+    async fn updates(&self, offset: i64) -> Result<Vec<Update>, anyhow::Error> {
+        let body = json!({ "offset": offset, "timeout": POLL_TIMEOUT_SECS, "allowed_updates": ["message"] });
+        let result = self.call("getUpdates", |request| request
+            .timeout(Duration::from_secs(POLL_TIMEOUT_SECS + REQUEST_TIMEOUT_SECS))
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string()))
+            .await?;
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+/// Sends the alert events its routing allows at the time in `timezone` to the configured chats.
+pub async fn run_telegram_alerts(bot: Arc<Telegram>, mut events: broadcast::Receiver<AlertEvent>, log: Arc<Mutex<DeliveryLog>>, timezone: Option<Tz>) {
+    while let Some(event) = next_event(&mut events, "telegram", &bot.config.routing, timezone).await {
+        let text = event.message();
+        // a retry goes only to the chats that didn't get the message yet
+        let pending = Mutex::new(bot.config.chat_ids.clone());
+        let (attempts, result) = retry(RETRIES, Duration::from_secs(RETRY_BACKOFF_SECS), || async {
+            let chat_ids = pending.lock().map(|mut pending| std::mem::take(&mut *pending)).unwrap_or_default();
+            let mut error = None;
+            let mut failed = Vec::new();
+            for chat_id in chat_ids {
+                if let Err(e) = bot.send_message(chat_id, &text).await {
+                    error = Some(format!("chat {}: {:#}", chat_id, e));
+                    failed.push(chat_id);
+                }
+            }
+            if let Ok(mut pending) = pending.lock() {
+                *pending = failed;
+            }
+            error.map_or(Ok(()), Err)
+        }).await;
+        let error = result.err();
+        match &error {
+            None => info!("Sent alert {} of {} to telegram", event.rule, event.sensor),
+            Some(e) => error!("failed to send alert {} of {} to telegram after {} attempts: {}", event.rule, event.sensor, attempts, e),
+        }
+        if let Ok(mut log) = log.lock() {
+            log.record(Delivery {
+                channel: "telegram".to_string(),
+                target: bot.recipients(),
+                rule: event.rule,
+                sensor: event.sensor,
+                state: event.state,
+                sent_at: unix_secs(SystemTime::now()),
+                attempts,
+                status: None,
+                error,
+            });
+        }
+    }
+}
+
+/// What the commands read, shared with the server.
+pub struct Sources {
+    pub storage: Arc<RwLock<Storage>>,
+    pub alerts: Arc<Mutex<Alerts>>,
+    /// Sensor drawn next to the requested one by `/chart`.
+    pub outdoor_sensor: Option<String>,
+    /// Sensor of `/chart` without a name.
+    pub default_sensor: String,
+    pub timezone: Option<Tz>,
+}
+
+/// Polls the bot for commands and answers those from the configured chats.
+pub async fn run_telegram_commands(bot: Arc<Telegram>, sources: Sources) {
+    let mut offset = 0;
+    loop {
+        let updates = match bot.updates(offset).await {
+            Ok(updates) => updates,
+            Err(e) => {
+                warn!("failed to get telegram updates: {:#}", e);
+                tokio::time::sleep(Duration::from_secs(POLL_RETRY_SECS)).await;
+                continue;
+            }
+        };
+        for update in updates {
+            offset = offset.max(update.update_id + 1);
+            let Some(Message { chat, text: Some(text) }) = update.message else {
+                continue;
+            };
+            if !bot.config.chat_ids.contains(&chat.id) {
+                warn!("ignoring telegram message from chat {}, which is not in chat_ids", chat.id);
+                continue;
+            }
+            let Some(command) = parse_command(&text) else {
+                continue;
+            };
+            if let Err(e) = answer(&bot, chat.id, command, &sources).await {
+                warn!("failed to answer telegram command {:?}: {:#}", text, e);
+                let _ = bot.send_message(chat.id, &format!("{:#}", e)).await;
+            }
+        }
+    }
+}
+
+async fn answer(bot: &Telegram, chat_id: i64, command: Command, sources: &Sources) -> Result<(), anyhow::Error> {
+    match command {
+        Command::Help => bot.send_message(chat_id, HELP).await,
+        Command::Alerts => {
+            let text = {
+                let alerts = sources.alerts.lock().map_err(|_| anyhow!("failed to lock alerts"))?;
+                let lines: Vec<String> = alerts.active()
+                    .map(|alert| match alert.acknowledged_at {
+                        Some(_) => format!("{} (acknowledged)", alert.event.message()),
+                        None => alert.event.message(),
+                    })
+                    .collect();
+                if lines.is_empty() { "No active alerts.".to_string() } else { lines.join("\n") }
+            };
+            bot.send_message(chat_id, &text).await
+        }
+        Command::Temp { sensor } => {
+            let text = {
+                let storage = sources.storage.read().map_err(|_| anyhow!("failed to lock storage"))?;
+                latest_readings(&storage, sensor.as_deref(), &|secs| local_time(sources.timezone, secs, "%H:%M"))?
+            };
+            bot.send_message(chat_id, &text).await
+        }
+        Command::Chart { sensor, hours } => {
+            let sensor = sensor.unwrap_or_else(|| sources.default_sensor.clone());
+            let png = chart(sources, sensor.clone(), hours).await?;
+            bot.send_photo(chat_id, png, &format!("{}, last {} hours", sensor, hours)).await
+        }
+    }
+}
+
+/// The newest reading of `sensor`, or of every sensor, one line each, e.g.
+/// `office: 21.3 °C, 45 % at 17:13`.
+pub fn latest_readings(storage: &Storage, sensor: Option<&str>, time_label: &dyn Fn(u64) -> String) -> Result<String, anyhow::Error> {
+    let names: Vec<&str> = match sensor {
+        Some(sensor) if storage.series(sensor).is_some() => vec![sensor],
+        Some(sensor) => bail!("unknown sensor {}, try one of: {}", sensor, storage.series_names().collect::<Vec<_>>().join(", ")),
+        None => storage.series_names().collect(),
+    };
+    let lines: Vec<String> = names.into_iter()
+        .map(|name| match storage.series(name).and_then(|series| series.latest_sample()) {
+            Some(sample) => {
+                let humidity = sample.value(HUMIDITY)
+                    .filter(|humidity| humidity.is_finite())
+                    .map(|humidity| format!(", {:.0} %", humidity))
+                    .unwrap_or_default();
+                format!("{}: {:.1} °C{} at {}", name, sample.temperature, humidity, time_label(unix_secs(sample.timestamp)))
+            }
+            None => format!("{}: no readings", name),
+        })
+        .collect();
+    if lines.is_empty() {
+        bail!("no sensors yet");
+    }
+    Ok(lines.join("\n"))
+}
+
+/// PNG of the temperature of `sensor` over the last `hours`, next to the outdoor one.
+async fn chart(sources: &Sources, sensor: String, hours: u64) -> Result<Vec<u8>, anyhow::Error> {
+    let to = SystemTime::now();
+    let span = Duration::from_secs(hours * 3600);
+    let from = to - span;
+    let (buckets, outdoor) = {
+        let storage = sources.storage.read().map_err(|_| anyhow!("failed to lock storage"))?;
+        let interval = storage.resolution(span);
+        let series = storage.series(&sensor).ok_or_else(|| anyhow!("unknown sensor {}", sensor))?;
+        let buckets = series.metric_buckets(TEMPERATURE, from, to, interval, storage.carry_forward()).map_err(AppError::from)?;
+        if buckets.iter().all(|bucket| bucket.value.is_none()) {
+            bail!("no temperature of {} in the last {} hours", sensor, hours);
+        }
+        let outdoor = sources.outdoor_sensor.clone()
+            .filter(|outdoor| *outdoor != sensor)
+            .and_then(|outdoor| {
+                let buckets = storage.series(&outdoor)?.metric_buckets(TEMPERATURE, from, to, interval, storage.carry_forward()).ok()?;
+                Some((outdoor, buckets))
+            });
+        (buckets, outdoor)
+    };
+    let timezone = sources.timezone;
+    tokio::task::spawn_blocking(move || {
+        let mut lines = vec![Line { label: &sensor, buckets: &buckets }];
+        if let Some((outdoor, buckets)) = &outdoor {
+            lines.push(Line { label: outdoor, buckets });
+        }
+        let y_label = metric_label(TEMPERATURE);
+        let chart = Chart { title: &sensor, y_label: &y_label, lines, width: CHART_WIDTH, height: CHART_HEIGHT };
+        chart.render_png(&|secs| local_time(timezone, secs, time_format(span)))
+    }).await.context("chart rendering failed")?
+}
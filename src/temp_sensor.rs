@@ -1,11 +1,46 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::time::{Duration, SystemTime};
+use log::debug;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde_json::Value;
 use crate::app_error::AppError;
+use crate::config::HttpAuth;
+use crate::dht22::Dht22Sensor;
+use crate::exec::ExecSensor;
+use crate::modbus::ModbusSensor;
+use crate::snmp::SnmpSensor;
+use crate::prometheus::PrometheusSensor;
+use crate::simulator::SimulatedSensor;
+use crate::tasmota::TasmotaSensor;
+use crate::storage::{Sample, HUMIDITY, TEMPERATURE};
 
+#[derive(Clone)]
 pub struct Measurement {
     pub humidity: f64,
     pub temperature: f64,
+    /// Further metrics by name, e.g. `pressure` or `co2`.
+    pub metrics: BTreeMap<String, f64>,
+}
+
+impl Measurement {
+    pub fn new(temperature: f64, humidity: f64) -> Self {
+        Self { humidity, temperature, metrics: BTreeMap::new() }
+    }
+
+    /// The stored form, humidity becomes a metric unless it is NaN.
+    pub fn into_sample(self, timestamp: SystemTime) -> Sample {
+        let mut sample = Sample::new(timestamp, self.temperature);
+        sample.metrics = self.metrics;
+        if self.humidity.is_finite() {
+            sample.metrics.insert(HUMIDITY.to_string(), self.humidity);
+        }
+        sample
+    }
 }
 
 impl Display for Measurement {
@@ -14,30 +49,200 @@ impl Display for Measurement {
     }
 }
 
+/// Client kept by a sensor for all its polls, so the connection to the sensor is reused.
+pub fn http_client(user_agent: &str, auth: &HttpAuth) -> Result<reqwest::Client, AppError> {
+    let mut headers = HeaderMap::new();
+    if let Some(username) = &auth.username {
+        let credentials = format!("{}:{}", username, auth.password.as_deref().unwrap_or_default());
+        headers.insert(AUTHORIZATION, sensitive(&format!("Basic {}", STANDARD.encode(credentials)))?);
+    }
+    for (name, value) in &auth.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| AppError::InternalError(format!("invalid header {}: {}", name, e)))?;
+        headers.insert(name, sensitive(value)?);
+    }
+    Ok(reqwest::Client::builder()
+        .user_agent(user_agent)
+        .default_headers(headers)
+        .tcp_keepalive(Duration::from_secs(60))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .build()?)
+}
+
+/// Header value kept out of debug output, they usually carry credentials.
+fn sensitive(value: &str) -> Result<HeaderValue, AppError> {
+    let mut value = HeaderValue::from_str(value)
+        .map_err(|e| AppError::InternalError(format!("invalid header value: {}", e)))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
 pub struct TempSensor {
     url: String,
+    re: Regex,
+    client: reqwest::Client,
 }
 
 
 impl TempSensor {
-    pub fn new(url: &String) -> Self {
+    pub fn new(url: &str, regex: &str, client: reqwest::Client) -> Result<Self, AppError> {
+        Ok(Self {
+            url: url.to_string(),
+            re: Regex::new(regex)?,
+            client,
+        })
+    }
+
+    pub async fn query(&self) -> Result<Measurement, AppError> {
+        let text = self.client.get(&self.url).send().await?.text().await?;
+        parse_regex(&self.re, &text)
+    }
+
+}
+
+/// Reads a measurement from the named groups of `re`: `temperature` is required,
+/// `humidity` is NaN when missing and any other group is stored as a metric.
+pub fn parse_regex(re: &Regex, text: &str) -> Result<Measurement, AppError> {
+    let caps = re.captures(text)
+        .ok_or_else(|| AppError::TemperatureSensorError("failed to parse measurement".to_string()))?;
+    let value = |group: &str| -> Result<Option<f64>, AppError> {
+        caps.name(group)
+            .map(|value| value.as_str().parse()
+                .map_err(|e| AppError::TemperatureSensorError(format!("Failed to parse {}: {}", group, e))))
+            .transpose()
+    };
+    let temperature = value(TEMPERATURE)?
+        .ok_or_else(|| AppError::TemperatureSensorError("no temperature matched".to_string()))?;
+    let mut measurement = Measurement::new(temperature, value(HUMIDITY)?.unwrap_or(f64::NAN));
+    for group in re.capture_names().flatten().filter(|group| ![TEMPERATURE, HUMIDITY].contains(group)) {
+        if let Some(metric) = value(group)? {
+            measurement.metrics.insert(group.to_string(), metric);
+        }
+    }
+    Ok(measurement)
+}
+
+/// Fetches a JSON document and picks the values at the configured JSON pointers.
+pub struct JsonSensor {
+    url: String,
+    temperature_path: String,
+    humidity_path: String,
+    metrics: BTreeMap<String, String>,
+    client: reqwest::Client,
+}
+
+impl JsonSensor {
+    pub fn new(
+        url: &str,
+        temperature_path: &str,
+        humidity_path: &str,
+        metrics: &BTreeMap<String, String>,
+        client: reqwest::Client) -> Self {
         Self {
-            url: url.clone(),
+            url: url.to_string(),
+            temperature_path: temperature_path.to_string(),
+            humidity_path: humidity_path.to_string(),
+            metrics: metrics.clone(),
+            client,
         }
     }
 
+    /// Current conditions at the location from the open-meteo.com forecast API.
+    pub fn open_meteo(latitude: f64, longitude: f64, client: reqwest::Client) -> Self {
+        let url = format!(
+            "https://api.open-meteo.com/v1/forecast?latitude={}&longitude={}&current=temperature_2m,relative_humidity_2m",
+            latitude, longitude);
+        Self::new(&url, "/current/temperature_2m", "/current/relative_humidity_2m", &BTreeMap::new(), client)
+    }
+
     pub async fn query(&self) -> Result<Measurement, AppError> {
-        let text = reqwest::get(&self.url).await?.text().await?;
-        let re = Regex::new(r"teplota:\s*<b>\s*(\d+\.\d+)\s*%\s*(\d+\.\d+)\s*&deg;C")?;
-        if let Some(caps) = re.captures(&text) {
-            return Ok(Measurement {
-                humidity: caps[1].parse()
-                    .map_err(|e| AppError::TemperatureSensorError(format!("Failed to parse humidity: {}", e)))?,
-                temperature: caps[2].parse()
-                    .map_err(|e| AppError::TemperatureSensorError(format!("Failed to parse temperature: {}", e)))?,
-            });
+        let body = self.client.get(&self.url).send().await?.bytes().await?;
+        parse_json(&body, &self.temperature_path, &self.humidity_path, &self.metrics)
+    }
+}
+
+/// Reads a measurement from a JSON document, humidity is NaN when missing
+/// and `metrics` (name to JSON pointer) missing in the document are left out.
+pub fn parse_json(
+    payload: &[u8],
+    temperature_path: &str,
+    humidity_path: &str,
+    metrics: &BTreeMap<String, String>) -> Result<Measurement, AppError> {
+    let value: Value = serde_json::from_slice(payload)?;
+    let temperature = value.pointer(temperature_path)
+        .and_then(Value::as_f64)
+        .ok_or_else(|| AppError::TemperatureSensorError(
+            format!("no temperature at {} in payload", temperature_path)))?;
+    // not every sensor reports humidity
+    let humidity = value.pointer(humidity_path)
+        .and_then(Value::as_f64)
+        .unwrap_or(f64::NAN);
+    let mut measurement = Measurement::new(temperature, humidity);
+    for (metric, path) in metrics {
+        if let Some(metric_value) = value.pointer(path).and_then(Value::as_f64) {
+            measurement.metrics.insert(metric.clone(), metric_value);
         }
-        Err(AppError::TemperatureSensorError("failed to parse measurement".to_string()))
     }
+    Ok(measurement)
+}
 
+/// A sensor polled every `sampling_interval` by its own task.
+pub enum PolledSensor {
+    Http(TempSensor),
+    Json(JsonSensor),
+    Dht22(Dht22Sensor),
+    Modbus(ModbusSensor),
+    Snmp(SnmpSensor),
+    Prometheus(PrometheusSensor),
+    Simulator(SimulatedSensor),
+    Exec(ExecSensor),
+    Tasmota(TasmotaSensor),
+}
+
+impl PolledSensor {
+    pub async fn query(&self) -> Result<Measurement, AppError> {
+        match self {
+            PolledSensor::Http(sensor) => sensor.query().await,
+            PolledSensor::Json(sensor) => sensor.query().await,
+            PolledSensor::Dht22(sensor) => sensor.query().await,
+            PolledSensor::Modbus(sensor) => sensor.query().await,
+            PolledSensor::Snmp(sensor) => sensor.query().await,
+            PolledSensor::Prometheus(sensor) => sensor.query().await,
+            PolledSensor::Simulator(sensor) => sensor.query().await,
+            PolledSensor::Exec(sensor) => sensor.query().await,
+            PolledSensor::Tasmota(sensor) => sensor.query().await,
+        }
+    }
+}
+
+/// How a polled sensor is queried: each attempt is cut off after `timeout`,
+/// failed attempts are retried with an exponentially growing delay.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    pub timeout: Duration,
+    pub retries: u32,
+    pub backoff: Duration,
+}
+
+impl PolledSensor {
+    pub async fn query_with_retry(&self, policy: &RetryPolicy) -> Result<Measurement, AppError> {
+        let mut backoff = policy.backoff;
+        let mut attempt = 0;
+        loop {
+            let result = tokio::time::timeout(policy.timeout, self.query())
+                .await
+                .unwrap_or_else(|_| Err(AppError::TemperatureSensorError(
+                    format!("query timed out after {}s", policy.timeout.as_secs()))));
+            match result {
+                Ok(measurement) => return Ok(measurement),
+                Err(e) if attempt < policy.retries => {
+                    debug!("query attempt {} failed: {}, retrying in {:?}", attempt + 1, e, backoff);
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
 }
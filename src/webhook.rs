@@ -0,0 +1,97 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use chrono_tz::Tz;
+use log::{info, warn};
+use reqwest::header::CONTENT_TYPE;
+use serde_json::Value;
+use tokio::sync::broadcast;
+use crate::alerts::{next_event, retry, unix_secs, AlertEvent, Delivery, DeliveryLog};
+use crate::config::{redact_url, WebhookConfig};
+
+/// Posts the alert events its routing allows at the time in `timezone` to the webhook, one at a
+/// time so the receiver sees them in order.
+pub async fn run_webhook(webhook: WebhookConfig, client: reqwest::Client, mut events: broadcast::Receiver<AlertEvent>, log: Arc<Mutex<DeliveryLog>>, timezone: Option<Tz>) {
+    let channel = format!("webhook {}", redact_url(&webhook.url));
+    while let Some(event) = next_event(&mut events, &channel, &webhook.routing, timezone).await {
+        let delivery = deliver(&webhook, &client, &event).await;
+        match &delivery.error {
+            None => info!("Sent alert {} of {} to webhook {}", event.rule, event.sensor, delivery.target),
+            Some(error) => warn!("failed to send alert {} of {} to webhook {} after {} attempts: {}",
+                event.rule, event.sensor, delivery.target, delivery.attempts, error),
+        }
+        if let Ok(mut log) = log.lock() {
+            log.record(delivery);
+        }
+    }
+}
+
+/// Posts the payload of `event`, retrying a failed request or a non-2xx response up to
+/// `retries` times.
+pub async fn deliver(webhook: &WebhookConfig, client: &reqwest::Client, event: &AlertEvent) -> Delivery {
+    let body = payload(webhook.payload.as_ref(), event);
+    let backoff = Duration::from_secs(webhook.retry_backoff);
+    let (attempts, result) = retry(webhook.retries, backoff, || post_json(client, &webhook.url, &body, webhook.timeout)).await;
+    let (status, error) = match result {
+        Ok(status) => (Some(status), None),
+        Err((status, error)) => (status, Some(error)),
+    };
+    Delivery {
+        channel: "webhook".to_string(),
+        target: redact_url(&webhook.url),
+        rule: event.rule.clone(),
+        sensor: event.sensor.clone(),
+        state: event.state,
+        sent_at: unix_secs(SystemTime::now()),
+        attempts,
+        status,
+        error,
+    }
+}
+
+/// Posts `body` to `url`, returning the status of a 2xx response, or the status, if there was a
+/// response, and what went wrong.
+pub async fn post_json(client: &reqwest::Client, url: &str, body: &Value, timeout_secs: u64) -> Result<u16, (Option<u16>, String)> {
+    let result = client.post(url)
+        .timeout(Duration::from_secs(timeout_secs))
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await;
+    match result {
+        Ok(response) if response.status().is_success() => Ok(response.status().as_u16()),
+        Ok(response) => Err((Some(response.status().as_u16()), format!("status {}", response.status()))),
+        Err(e) => Err((None, format!("{:#}", anyhow::Error::from(e.without_url())))),
+    }
+}
+
+/// Body posted for `event`: the `template` with the placeholders in its strings replaced, or
+/// the event itself without one.
+pub fn payload(template: Option<&Value>, event: &AlertEvent) -> Value {
+    match template {
+        Some(template) => fill(template, event),
+        None => serde_json::to_value(event).unwrap_or_default(),
+    }
+}
+
+fn fill(template: &Value, event: &AlertEvent) -> Value {
+    match template {
+        Value::String(text) => Value::String(fill_text(text, event)),
+        Value::Array(items) => Value::Array(items.iter().map(|item| fill(item, event)).collect()),
+        Value::Object(fields) => Value::Object(fields.iter().map(|(name, value)| (name.clone(), fill(value, event))).collect()),
+        other => other.clone(),
+    }
+}
+
+/// `text` with `{rule}`, `{sensor}`, `{metric}`, `{state}`, `{value}`, `{since}`, `{timestamp}`,
+/// `{priority}` and `{message}` replaced by those of `event`.
+pub fn fill_text(text: &str, event: &AlertEvent) -> String {
+    text.replace("{rule}", &event.rule)
+        .replace("{sensor}", &event.sensor)
+        .replace("{metric}", &event.metric)
+        .replace("{state}", event.state.as_str())
+        .replace("{value}", &event.value.to_string())
+        .replace("{since}", &event.since.to_string())
+        .replace("{timestamp}", &event.timestamp.to_string())
+        .replace("{priority}", event.priority.as_str())
+        .replace("{message}", &event.message())
+}
@@ -0,0 +1,53 @@
+use std::time::Duration;
+use heat_monitor::alert_command::{environment, run_command};
+use heat_monitor::alerts::{AlertEvent, AlertState, Priority};
+
+fn frost() -> AlertEvent {
+    AlertEvent {
+        id: 1,
+        rule: "frost".to_string(),
+        sensor: "office".to_string(),
+        metric: "temperature".to_string(),
+        state: AlertState::Active,
+        value: 14.5,
+        since: 1758294000,
+        timestamp: 1758294600,
+        priority: Priority::High,
+    }
+}
+
+#[test]
+fn test_environment() {
+    let environment = environment(&frost());
+    let get = |name: &str| environment.iter().find(|(key, _)| *key == name).map(|(_, value)| value.as_str());
+    assert_eq!(get("HEATER_ALERT_RULE"), Some("frost"));
+    assert_eq!(get("HEATER_ALERT_STATE"), Some("active"));
+    assert_eq!(get("HEATER_ALERT_VALUE"), Some("14.5"));
+    assert_eq!(get("HEATER_ALERT_PRIORITY"), Some("high"));
+    assert_eq!(get("HEATER_ALERT_MESSAGE"), Some("frost is active for office: temperature 14.5"));
+}
+
+#[tokio::test]
+async fn test_run_command() {
+    let command = ["sh", "-c", "test \"$HEATER_ALERT_SENSOR $1\" = \"office active\"", "sh", "{state}"].map(String::from);
+    let delivery = run_command(&command, &frost(), Duration::from_secs(10)).await;
+    assert_eq!(delivery.channel, "command");
+    assert_eq!(delivery.target, "sh");
+    assert_eq!(delivery.status, Some(0));
+    assert_eq!(delivery.error, None);
+}
+
+#[tokio::test]
+async fn test_run_command_failure() {
+    let command = ["sh", "-c", "echo relay offline >&2; exit 3"].map(String::from);
+    let delivery = run_command(&command, &frost(), Duration::from_secs(10)).await;
+    assert_eq!(delivery.status, Some(3));
+    assert!(delivery.error.unwrap().contains("relay offline"));
+
+    let delivery = run_command(&["sleep".to_string(), "5".to_string()], &frost(), Duration::from_millis(100)).await;
+    assert_eq!(delivery.status, None);
+    assert!(delivery.error.unwrap().starts_with("killed after"));
+
+    let delivery = run_command(&["/nonexistent/siren".to_string()], &frost(), Duration::from_secs(10)).await;
+    assert!(delivery.error.is_some());
+}
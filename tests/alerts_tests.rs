@@ -0,0 +1,384 @@
+use std::time::{Duration, SystemTime};
+use heat_monitor::alerts::{retry, AlertRule, AlertState, Alerts, Delivery, DeliveryLog, Priority, Routing, StaleAlert};
+use heat_monitor::storage::Sample;
+
+fn frost(duration: u64) -> AlertRule {
+    AlertRule {
+        name: "frost".to_string(),
+        sensor: None,
+        metric: "temperature".to_string(),
+        below: Some(15.0),
+        above: None,
+        drop: None,
+        rise: None,
+        projected_below: None,
+        horizon: 10800,
+        falling_buckets: None,
+        bucket: 900,
+        heating_hours: None,
+        window: 1800,
+        duration,
+        priority: Priority::Normal,
+        hysteresis: 0.0,
+        cooldown: 0,
+        renotify: 0,
+        command: None,
+    }
+}
+
+fn at(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn sample(secs: u64, temperature: f64) -> Sample {
+    Sample::new(SystemTime::UNIX_EPOCH + Duration::from_secs(secs), temperature)
+}
+
+#[test]
+fn test_alert_fires_after_duration_and_resolves() {
+    let mut alerts = Alerts::new(vec![frost(600)]);
+
+    assert!(alerts.evaluate("office", &sample(1000, 14.0)).is_empty());
+    assert!(alerts.evaluate("office", &sample(1300, 13.5)).is_empty());
+    let events = alerts.evaluate("office", &sample(1600, 13.0));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].state, AlertState::Active);
+    assert_eq!(events[0].since, 1000);
+    assert_eq!(events[0].value, 13.0);
+    assert_eq!(alerts.active().count(), 1);
+
+    // still below, nothing new
+    assert!(alerts.evaluate("office", &sample(1900, 12.0)).is_empty());
+
+    let events = alerts.evaluate("office", &sample(2200, 16.0));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].state, AlertState::Resolved);
+    assert_eq!(events[0].since, 1000);
+    assert_eq!(events[0].timestamp, 2200);
+    assert_eq!(alerts.active().count(), 0);
+}
+
+#[test]
+fn test_short_crossing_does_not_fire() {
+    let mut alerts = Alerts::new(vec![frost(600)]);
+
+    assert!(alerts.evaluate("office", &sample(1000, 14.0)).is_empty());
+    assert!(alerts.evaluate("office", &sample(1300, 16.0)).is_empty());
+    assert!(alerts.evaluate("office", &sample(1600, 14.0)).is_empty());
+    assert!(alerts.evaluate("office", &sample(2000, 14.0)).is_empty());
+    assert_eq!(alerts.evaluate("office", &sample(2200, 14.0)).len(), 1);
+}
+
+#[test]
+fn test_rules_are_kept_per_sensor() {
+    let mut rule = frost(0);
+    rule.sensor = Some("garage".to_string());
+    let mut alerts = Alerts::new(vec![frost(0), rule]);
+
+    assert_eq!(alerts.evaluate("office", &sample(1000, 14.0)).len(), 1);
+    let events = alerts.evaluate("garage", &sample(1000, 14.0));
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().all(|event| event.sensor == "garage"));
+    assert_eq!(alerts.active().count(), 3);
+}
+
+#[test]
+fn test_sample_without_the_metric_is_ignored() {
+    let mut alerts = Alerts::new(vec![AlertRule {
+        name: "damp".to_string(),
+        sensor: None,
+        metric: "humidity".to_string(),
+        below: None,
+        above: Some(70.0),
+        drop: None,
+        rise: None,
+        projected_below: None,
+        horizon: 10800,
+        falling_buckets: None,
+        bucket: 900,
+        heating_hours: None,
+        window: 1800,
+        duration: 0,
+        priority: Priority::Normal,
+        hysteresis: 0.0,
+        cooldown: 0,
+        renotify: 0,
+        command: None,
+    }]);
+
+    assert!(alerts.evaluate("office", &sample(1000, 21.0)).is_empty());
+    let mut damp = sample(1060, 21.0);
+    damp.metrics.insert("humidity".to_string(), 75.0);
+    assert_eq!(alerts.evaluate("office", &damp)[0].state, AlertState::Active);
+    assert!(alerts.evaluate("office", &sample(1120, 21.0)).is_empty());
+    assert_eq!(alerts.active().count(), 1);
+}
+
+#[test]
+fn test_hysteresis_clears_past_the_threshold() {
+    let mut rule = frost(0);
+    rule.hysteresis = 0.5;
+    let mut alerts = Alerts::new(vec![rule]);
+
+    assert_eq!(alerts.evaluate("office", &sample(1000, 14.9)).len(), 1);
+    // bouncing around 15.0 keeps it active
+    assert!(alerts.evaluate("office", &sample(1060, 15.1)).is_empty());
+    assert!(alerts.evaluate("office", &sample(1120, 14.9)).is_empty());
+    assert!(alerts.evaluate("office", &sample(1180, 15.4)).is_empty());
+    let events = alerts.evaluate("office", &sample(1240, 15.5));
+    assert_eq!(events[0].state, AlertState::Resolved);
+    // fires again below 15.0 only
+    assert!(alerts.evaluate("office", &sample(1300, 15.2)).is_empty());
+    assert_eq!(alerts.evaluate("office", &sample(1360, 14.8)).len(), 1);
+}
+
+#[test]
+fn test_cooldown_holds_back_notifications() {
+    let mut rule = frost(0);
+    rule.cooldown = 600;
+    let mut alerts = Alerts::new(vec![rule]);
+    let mut notified = alerts.subscribe();
+
+    alerts.evaluate("office", &sample(1000, 14.0));
+    assert_eq!(notified.try_recv().unwrap().state, AlertState::Active);
+
+    // resolving and firing again within the cooldown tells nobody
+    assert_eq!(alerts.evaluate("office", &sample(1060, 16.0)).len(), 1);
+    assert_eq!(alerts.evaluate("office", &sample(1120, 14.0)).len(), 1);
+    assert_eq!(alerts.evaluate("office", &sample(1180, 16.0)).len(), 1);
+    assert!(notified.try_recv().is_err());
+    assert_eq!(alerts.active().count(), 0);
+
+    // the held back resolution is sent once the cooldown passes
+    assert!(alerts.evaluate("office", &sample(1500, 16.0)).is_empty());
+    assert!(notified.try_recv().is_err());
+    alerts.evaluate("office", &sample(1600, 16.0));
+    let event = notified.try_recv().unwrap();
+    assert_eq!(event.state, AlertState::Resolved);
+    assert_eq!(event.timestamp, 1180);
+    assert!(notified.try_recv().is_err());
+}
+
+#[test]
+fn test_drop_within_the_window() {
+    let mut rule = frost(0);
+    rule.name = "heater failure".to_string();
+    rule.below = None;
+    rule.drop = Some(2.0);
+    let mut alerts = Alerts::new(vec![rule]);
+
+    assert!(alerts.evaluate("office", &sample(1000, 21.0)).is_empty());
+    assert!(alerts.evaluate("office", &sample(1600, 20.0)).is_empty());
+    let events = alerts.evaluate("office", &sample(2200, 18.9));
+    assert_eq!(events[0].state, AlertState::Active);
+    assert_eq!(events[0].value, 18.9);
+    // 21.0 leaves the window, 20.0 - 18.9 is within 2 degrees
+    let events = alerts.evaluate("office", &sample(2900, 18.9));
+    assert_eq!(events[0].state, AlertState::Resolved);
+}
+
+#[test]
+fn test_slow_drop_does_not_fire() {
+    let mut rule = frost(0);
+    rule.below = None;
+    rule.drop = Some(2.0);
+    rule.window = 600;
+    let mut alerts = Alerts::new(vec![rule]);
+
+    for (i, temperature) in [21.0, 20.0, 19.0, 18.0, 17.0].into_iter().enumerate() {
+        assert!(alerts.evaluate("office", &sample(1000 + i as u64 * 600, temperature)).is_empty());
+    }
+}
+
+#[test]
+fn test_projected_below() {
+    let mut rule = frost(0);
+    rule.name = "freeze forecast".to_string();
+    rule.below = None;
+    rule.projected_below = Some(0.0);
+    rule.window = 3600;
+    let mut alerts = Alerts::new(vec![rule]);
+
+    // falling 2 °C an hour, but too little of the window to trust the trend yet
+    assert!(alerts.evaluate("garage", &sample(0, 6.0)).is_empty());
+    assert!(alerts.evaluate("garage", &sample(900, 5.5)).is_empty());
+    // 5.0 °C now, -1.0 °C in 3 hours
+    let events = alerts.evaluate("garage", &sample(1800, 5.0));
+    assert_eq!(events[0].state, AlertState::Active);
+    assert_eq!(events[0].value, 5.0);
+
+    let resolved = (1..=9).find_map(|i| alerts.evaluate("garage", &sample(1800 + i * 600, 5.0)).pop());
+    assert_eq!(resolved.unwrap().state, AlertState::Resolved);
+}
+
+#[test]
+fn test_slow_fall_is_not_projected_below() {
+    let mut rule = frost(0);
+    rule.below = None;
+    rule.projected_below = Some(0.0);
+    rule.window = 3600;
+    let mut alerts = Alerts::new(vec![rule]);
+
+    // -0.5 °C an hour from 5.0 °C stays above 0 °C for the next 3 hours
+    for i in 0..=12 {
+        assert!(alerts.evaluate("garage", &sample(i * 600, 5.0 - i as f64 * 0.5 / 6.0)).is_empty());
+    }
+}
+
+fn heater_failure() -> AlertRule {
+    let mut rule = frost(0);
+    rule.name = "heater failure".to_string();
+    rule.below = None;
+    rule.falling_buckets = Some(3);
+    rule.bucket = 600;
+    rule
+}
+
+#[test]
+fn test_falling_buckets() {
+    let mut alerts = Alerts::new(vec![heater_failure()]).with_timezone(Some(chrono_tz::UTC));
+
+    // two samples per 10 minute bucket, the means 20.0, 19.5, 19.0, 18.5
+    let mut events = Vec::new();
+    for i in 0..8 {
+        events.extend(alerts.evaluate("office", &sample(i * 300, 20.0 - (i / 2) as f64 * 0.5)));
+    }
+    assert!(events.is_empty());
+    // the 18.5 bucket complete, the third in a row that fell
+    let events = alerts.evaluate("office", &sample(2400, 18.5));
+    assert_eq!(events[0].state, AlertState::Active);
+    assert_eq!(events[0].rule, "heater failure");
+
+    // a mean above the one before resolves it
+    assert!(alerts.evaluate("office", &sample(2700, 19.5)).is_empty());
+    let events = alerts.evaluate("office", &sample(3000, 19.5));
+    assert_eq!(events[0].state, AlertState::Resolved);
+}
+
+#[test]
+fn test_falling_buckets_only_in_heating_hours() {
+    let mut rule = heater_failure();
+    rule.heating_hours = Some("06:00-22:00".to_string());
+    let mut alerts = Alerts::new(vec![rule]).with_timezone(Some(chrono_tz::UTC));
+
+    // falling every bucket from 21:30 UTC on, but the heating stops at 22:00
+    let start = 21 * 3600 + 1800;
+    for i in 0..12 {
+        assert!(alerts.evaluate("office", &sample(start + i * 600, 20.0 - i as f64 * 0.5)).is_empty());
+    }
+}
+#[test]
+fn test_stale_alert() {
+    let minute = Duration::from_secs(60);
+    let mut alerts = Alerts::new(Vec::new());
+    assert!(alerts.check_stale("garage", at(1000), minute, at(5000)).is_none());
+
+    let mut alerts = Alerts::new(Vec::new()).with_stale_alert(Some(StaleAlert { intervals: 5, priority: Priority::High }));
+    assert!(alerts.check_stale("garage", at(1000), minute, at(1300)).is_none());
+    let event = alerts.check_stale("garage", at(1000), minute, at(1301)).unwrap();
+    assert_eq!(event.state, AlertState::Active);
+    assert_eq!(event.rule, "stale");
+    assert_eq!(event.value, 301.0);
+    assert_eq!(event.since, 1000);
+    assert_eq!(event.priority, Priority::High);
+    assert_eq!(alerts.active().count(), 1);
+    assert!(alerts.check_stale("garage", at(1000), minute, at(1400)).is_none());
+
+    let event = alerts.check_stale("garage", at(1390), minute, at(1400)).unwrap();
+    assert_eq!(event.state, AlertState::Resolved);
+    assert_eq!(alerts.active().count(), 0);
+}
+
+#[test]
+fn test_acknowledge_stops_renotifying() {
+    let mut rule = frost(0);
+    rule.renotify = 600;
+    let mut alerts = Alerts::new(vec![rule, frost(0)]);
+    let mut notified = alerts.subscribe();
+
+    let events = alerts.evaluate("office", &sample(1000, 14.0));
+    assert_ne!(events[0].id, events[1].id);
+    let id = events[0].id;
+    assert_eq!(notified.try_recv().unwrap().id, id);
+    notified.try_recv().unwrap();
+
+    alerts.evaluate("office", &sample(1300, 13.0));
+    assert!(notified.try_recv().is_err());
+    alerts.evaluate("office", &sample(1600, 12.0));
+    let repeat = notified.try_recv().unwrap();
+    assert_eq!((repeat.id, repeat.state, repeat.value, repeat.timestamp), (id, AlertState::Active, 12.0, 1600));
+
+    assert!(alerts.acknowledge(99, at(1700)).is_none());
+    assert_eq!(alerts.acknowledge(id, at(1700)).unwrap().acknowledged_at, Some(1700));
+    // a second acknowledgment keeps the first time
+    assert_eq!(alerts.acknowledge(id, at(1800)).unwrap().acknowledged_at, Some(1700));
+    alerts.evaluate("office", &sample(2200, 12.0));
+    assert!(notified.try_recv().is_err());
+
+    // the resolution is still sent, and the next activation is a new alert
+    alerts.evaluate("office", &sample(2300, 16.0));
+    let resolved = notified.try_recv().unwrap();
+    assert_eq!((resolved.id, resolved.state), (id, AlertState::Resolved));
+    notified.try_recv().unwrap();
+    let events = alerts.evaluate("office", &sample(2400, 14.0));
+    assert!(events[0].id > id);
+    assert!(alerts.active().all(|alert| alert.acknowledged_at.is_none()));
+}
+
+#[test]
+fn test_routing() {
+    let time = |hour, minute| chrono::NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+    assert!(Routing::default().allows(Priority::Low, time(3, 0)));
+
+    let night = Routing { quiet_hours: Some("22:00-07:00".to_string()), ..Routing::default() };
+    assert!(night.allows(Priority::High, time(21, 59)));
+    assert!(!night.allows(Priority::High, time(22, 0)));
+    assert!(!night.allows(Priority::High, time(6, 59)));
+    assert!(night.allows(Priority::Urgent, time(3, 0)));
+    assert!(night.allows(Priority::Low, time(7, 0)));
+
+    let lunch = Routing {
+        min_priority: Priority::Normal,
+        quiet_hours: Some("12:00-13:00".to_string()),
+        quiet_min_priority: Priority::High,
+    };
+    assert!(!lunch.allows(Priority::Low, time(9, 0)));
+    assert!(!lunch.allows(Priority::Normal, time(12, 30)));
+    assert!(lunch.allows(Priority::High, time(12, 30)));
+    assert!(lunch.allows(Priority::Normal, time(13, 0)));
+}
+
+#[test]
+fn test_delivery_log_keeps_the_newest() {
+    let mut log = DeliveryLog::default();
+    for i in 0..150 {
+        log.record(Delivery {
+            channel: "webhook".to_string(),
+            target: "http://localhost/hook".to_string(),
+            rule: "frost".to_string(),
+            sensor: "office".to_string(),
+            state: AlertState::Active,
+            sent_at: i,
+            attempts: 1,
+            status: Some(200),
+            error: None,
+        });
+    }
+    assert_eq!(log.iter().count(), 100);
+    assert_eq!(log.iter().next().unwrap().sent_at, 149);
+    assert_eq!(log.iter().last().unwrap().sent_at, 50);
+}
+
+#[tokio::test]
+async fn test_retry() {
+    let mut calls = 0;
+    let (attempts, result) = retry(3, Duration::ZERO, || { calls += 1; let call = calls; async move { if call < 2 { Err(call) } else { Ok(call) } } }).await;
+    assert_eq!((attempts, result), (2, Ok(2)));
+
+    let (attempts, result) = retry(3, Duration::ZERO, || async { Err::<(), _>("down") }).await;
+    assert_eq!((attempts, result), (4, Err("down")));
+
+    // the doubling backoff saturates instead of overflowing
+    let (attempts, result) = retry(0, Duration::MAX, || async { Err::<(), _>("down") }).await;
+    assert_eq!((attempts, result), (1, Err("down")));
+}
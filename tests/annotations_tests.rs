@@ -0,0 +1,45 @@
+use heat_monitor::annotations::{Annotation, Annotations};
+
+fn note(timestamp: u64, text: &str, sensor: Option<&str>) -> Annotation {
+    Annotation { timestamp, text: text.to_string(), sensor: sensor.map(str::to_string) }
+}
+
+#[test]
+fn test_annotations_in_range_by_sensor() {
+    let mut annotations = Annotations::open(None).unwrap();
+    annotations.add(note(300, "window left open", Some("office"))).unwrap();
+    annotations.add(note(100, "changed boiler filter", None)).unwrap();
+    annotations.add(note(200, "hall door open", Some("hall"))).unwrap();
+
+    let office: Vec<String> = annotations.in_range("office", 0, 1000).into_iter().map(|note| note.text).collect();
+    assert_eq!(office, ["changed boiler filter", "window left open"]);
+    assert_eq!(annotations.in_range("hall", 150, 250).len(), 1);
+    assert!(annotations.in_range("hall", 301, 1000).is_empty());
+}
+
+#[test]
+fn test_annotations_are_persisted() {
+    let path = std::env::temp_dir().join(format!("heat_monitor_{}.annotations", uuid::Uuid::new_v4()));
+    let path = path.to_string_lossy().to_string();
+    {
+        let mut annotations = Annotations::open(Some(&path)).unwrap();
+        annotations.add(note(200, "window left open", Some("office"))).unwrap();
+        annotations.add(note(100, "changed boiler filter", None)).unwrap();
+    }
+
+    let annotations = Annotations::open(Some(&path)).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(annotations.in_range("office", 0, 1000), [
+        note(100, "changed boiler filter", None),
+        note(200, "window left open", Some("office")),
+    ]);
+}
+
+#[test]
+fn test_empty_or_long_text_is_rejected() {
+    let mut annotations = Annotations::open(None).unwrap();
+    assert!(annotations.add(note(100, " ", None)).is_err());
+    assert!(annotations.add(note(100, &"x".repeat(501), None)).is_err());
+    assert!(annotations.in_range("office", 0, 1000).is_empty());
+}
@@ -0,0 +1,32 @@
+use anyhow::Context;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use heat_monitor::app_error::{AppError, ErrorDetails};
+use heat_monitor::storage::StorageError;
+
+#[test]
+fn test_storage_errors_are_client_errors() {
+    assert_eq!(AppError::from(StorageError::NoDataAvailable).into_response().status(), StatusCode::NOT_FOUND);
+    assert_eq!(AppError::from(StorageError::InvalidTimeRange).into_response().status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn test_error_details_keep_the_chain() {
+    let error: AppError = Err::<(), _>(std::io::Error::other("disk full"))
+        .context("cannot write backlog")
+        .unwrap_err()
+        .into();
+    let response = error.into_response();
+    let details = response.extensions().get::<ErrorDetails>().unwrap();
+
+    assert_eq!(details.code, 3);
+    assert_eq!(details.message, "cannot write backlog");
+    assert_eq!(details.chain, ["disk full"]);
+
+    let body = details.body(false);
+    assert_eq!(body["status"], "error");
+    assert!(body.get("message").is_none());
+    let body = details.body(true);
+    assert_eq!(body["message"], "cannot write backlog");
+    assert_eq!(body["chain"][0], "disk full");
+}
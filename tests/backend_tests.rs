@@ -0,0 +1,46 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use heat_monitor::backend::SharedBackend;
+use heat_monitor::config::Config;
+use heat_monitor::storage::{Sample, Storage};
+
+fn backend() -> SharedBackend {
+    let config = Config::parse(r#"
+temp_sensor_url = "http://192.168.6.75/"
+sampling_interval = 45
+averaging_interval = 120
+port = 3000
+listen_address = "0.0.0.0"
+log_path = "/tmp/heat_monitor.log"
+pid_file = "/tmp/heater-monitor.pid"
+"#).unwrap();
+    Arc::new(RwLock::new(Storage::new(&config).unwrap()))
+}
+
+#[test]
+fn test_storage_as_backend() {
+    let backend = backend();
+    let base = SystemTime::now() - Duration::from_secs(600);
+    {
+        let mut backend = backend.write().unwrap();
+        for i in 0..3 {
+            backend.append("office", Sample::new(base + Duration::from_secs(i * 60), 20.0 + i as f64)).unwrap();
+        }
+    }
+
+    let backend = backend.read().unwrap();
+    let samples = backend.range("office", base + Duration::from_secs(60), SystemTime::now()).unwrap();
+    assert_eq!(samples.iter().map(|sample| sample.temperature).collect::<Vec<_>>(), [21.0, 22.0]);
+    assert_eq!(backend.latest("office").unwrap().temperature, 22.0);
+}
+
+#[test]
+fn test_backend_unknown_series_and_range() {
+    let backend = backend();
+    let backend = backend.read().unwrap();
+    let now = SystemTime::now();
+
+    assert!(backend.latest("attic").is_none());
+    assert!(backend.range("attic", now - Duration::from_secs(60), now).unwrap().is_empty());
+    backend.range("default", now, now - Duration::from_secs(60)).unwrap_err();
+}
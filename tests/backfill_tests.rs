@@ -0,0 +1,27 @@
+use std::time::{Duration, SystemTime};
+use heat_monitor::backfill::parse_export;
+use heat_monitor::export::{Export, ExportFormat};
+use heat_monitor::storage::Sample;
+
+#[test]
+fn test_exported_json_is_parsed() {
+    let mut humid = Sample::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_060), 21.0);
+    humid.metrics.insert("humidity".to_string(), 40.0);
+    let plain = Sample::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000), 20.5);
+    let json: String = Export::new(ExportFormat::Json, vec![plain, humid]).into_chunks().collect();
+
+    let samples = parse_export(&serde_json::from_str(&json).unwrap()).unwrap();
+
+    assert_eq!(samples.len(), 2);
+    assert_eq!(samples[0].temperature, 20.5);
+    assert!(samples[0].metrics.is_empty());
+    assert_eq!(samples[1].timestamp, SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_060));
+    assert_eq!(samples[1].value("humidity"), Some(40.0));
+}
+
+#[test]
+fn test_invalid_export_is_rejected() {
+    assert!(parse_export(&serde_json::json!({"status": "error"})).is_err());
+    assert!(parse_export(&serde_json::json!([{"timestamp": 1_700_000_000}])).is_err());
+    assert!(parse_export(&serde_json::json!([{"timestamp": u64::MAX, "temperature": 21.0}])).is_err());
+}
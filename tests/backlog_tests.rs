@@ -0,0 +1,205 @@
+use std::io::Write;
+use std::time::{Duration, SystemTime};
+use heat_monitor::backlog::{Backlog, BacklogFormat, Flushing, Rotation};
+use heat_monitor::storage::Sample;
+
+fn temp_path() -> String {
+    std::env::temp_dir()
+        .join(format!("heat_monitor_{}.backlog", uuid::Uuid::new_v4()))
+        .to_string_lossy()
+        .to_string()
+}
+
+fn sample(secs: u64, temperature: f64) -> Sample {
+    Sample::new(SystemTime::UNIX_EPOCH + Duration::from_secs(secs), temperature)
+}
+
+#[test]
+fn test_binary_roundtrip() {
+    let path = temp_path();
+    {
+        let (mut backlog, samples) = Backlog::open(&path, BacklogFormat::Binary).unwrap();
+        assert!(samples.is_empty());
+        let mut with_metrics = sample(1_700_000_060, 21.5);
+        with_metrics.metrics.insert("humidity".to_string(), 45.0);
+        backlog.append("default", &sample(1_700_000_000, 21.0)).unwrap();
+        backlog.append("boiler_room", &with_metrics).unwrap();
+    }
+
+    let (_, samples) = Backlog::open(&path, BacklogFormat::Text).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(samples.len(), 2);
+    assert_eq!(samples[0].0, "default");
+    assert_eq!(samples[0].1.temperature, 21.0);
+    assert_eq!(samples[1].0, "boiler_room");
+    assert_eq!(samples[1].1.value("humidity"), Some(45.0));
+}
+
+#[test]
+fn test_binary_torn_record_is_cut_off() {
+    let path = temp_path();
+    {
+        let (mut backlog, _) = Backlog::open(&path, BacklogFormat::Binary).unwrap();
+        backlog.append("default", &sample(1_700_000_000, 21.0)).unwrap();
+    }
+    // power lost in the middle of the next record
+    std::fs::OpenOptions::new().append(true).open(&path).unwrap()
+        .write_all(&[20, 0, 0, 0, 1, 2, 3]).unwrap();
+
+    {
+        let (mut backlog, samples) = Backlog::open(&path, BacklogFormat::Binary).unwrap();
+        assert_eq!(samples.len(), 1);
+        backlog.append("default", &sample(1_700_000_060, 22.0)).unwrap();
+    }
+
+    let (_, samples) = Backlog::open(&path, BacklogFormat::Binary).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(samples.len(), 2);
+    assert_eq!(samples[1].1.temperature, 22.0);
+}
+
+#[test]
+fn test_existing_text_backlog_stays_text() {
+    let path = temp_path();
+    std::fs::write(&path, "t1 1700000000 20.5\n").unwrap();
+    {
+        let (mut backlog, samples) = Backlog::open(&path, BacklogFormat::Binary).unwrap();
+        assert_eq!(samples.len(), 1);
+        backlog.append("default", &sample(1_700_000_060, 21.0)).unwrap();
+    }
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(content, "t1 1700000000 20.5\nt1 1700000060 21\n");
+}
+
+#[test]
+fn test_rotation_by_size() {
+    let path = temp_path();
+    let rotation = Rotation { max_size: Some(30), ..Rotation::default() };
+    {
+        let (backlog, _) = Backlog::open(&path, BacklogFormat::Text).unwrap();
+        let mut backlog = backlog.with_rotation(rotation);
+        for i in 0..3 {
+            backlog.append("default", &sample(1_700_000_000 + i * 60, 20.0)).unwrap();
+        }
+    }
+
+    let segments = Backlog::closed_segments(&path).unwrap();
+    let (_, current) = Backlog::open(&path, BacklogFormat::Text).unwrap();
+    let closed = Backlog::load_segment(&segments[0]).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&segments[0]).unwrap();
+
+    // two 19 byte lines fill the first segment
+    assert_eq!(segments.len(), 1);
+    assert_eq!(closed.len(), 2);
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0].1.timestamp, SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_120));
+}
+
+#[test]
+fn test_rotation_by_age_compresses_segment() {
+    let path = temp_path();
+    let rotation = Rotation { max_age: Some(Duration::from_secs(3600)), compress: true, ..Rotation::default() };
+    {
+        let (backlog, _) = Backlog::open(&path, BacklogFormat::Binary).unwrap();
+        let mut backlog = backlog.with_rotation(rotation);
+        backlog.append("default", &sample(1_700_000_000, 20.0)).unwrap();
+        backlog.append("default", &sample(1_700_001_000, 21.0)).unwrap();
+        backlog.append("default", &sample(1_700_003_600, 22.0)).unwrap();
+    }
+
+    let segments = Backlog::closed_segments(&path).unwrap();
+    let (_, current) = Backlog::open(&path, BacklogFormat::Text).unwrap();
+    let closed = Backlog::load_segment(&segments[0]).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&segments[0]).unwrap();
+
+    assert_eq!(segments.len(), 1);
+    assert!(segments[0].to_string_lossy().ends_with(".zst"));
+    assert_eq!(closed.len(), 2);
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0].1.temperature, 22.0);
+}
+
+#[test]
+fn test_downsampled_samples_keep_their_span() {
+    for format in [BacklogFormat::Text, BacklogFormat::Binary] {
+        let path = temp_path();
+        let mut rollup = sample(1_700_000_000, 21.25);
+        rollup.metrics.insert("humidity".to_string(), 41.5);
+        rollup.span = Some(Duration::from_secs(300));
+        {
+            let (mut backlog, _) = Backlog::open(&path, format).unwrap();
+            backlog.append("office", &rollup).unwrap();
+            backlog.append("office", &sample(1_700_000_300, 22.0)).unwrap();
+        }
+
+        let (_, samples) = Backlog::open(&path, format).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(samples[0].1.span, Some(Duration::from_secs(300)));
+        assert_eq!(samples[0].1.temperature, 21.25);
+        assert_eq!(samples[0].1.value("humidity"), Some(41.5));
+        assert_eq!(samples[1].1.span, None);
+    }
+}
+
+#[test]
+fn test_buffered_records_are_written_on_flush() {
+    let path = temp_path();
+    let flushing = Flushing { flush_interval: Some(Duration::from_secs(3600)), ..Flushing::default() };
+    let (backlog, _) = Backlog::open(&path, BacklogFormat::Text).unwrap();
+    let mut backlog = backlog.with_flushing(flushing);
+    backlog.append("default", &sample(1_700_000_000, 20.0)).unwrap();
+    backlog.append("default", &sample(1_700_000_060, 21.0)).unwrap();
+    let buffered = std::fs::read_to_string(&path).unwrap();
+
+    backlog.sync().unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(buffered, "");
+    assert_eq!(written, "t1 1700000000 20\nt1 1700000060 21\n");
+}
+
+#[test]
+fn test_delete_while_rotating_with_compression() {
+    for _ in 0..20 {
+        let path = temp_path();
+        for i in 0..4 {
+            let records = [i * 60, i * 60 + 30].map(|secs| ("default".to_string(), sample(1_700_000_000 + secs, 20.0 + i as f64)));
+            let segment = Backlog::write_segment(&format!("{}{}", path, i), BacklogFormat::Text, &records).unwrap();
+            std::fs::rename(&segment, format!("{}.2023111{}-000000", path, i)).unwrap();
+        }
+        let rotation = Rotation { max_size: Some(1), compress: true, ..Rotation::default() };
+        let (backlog, _) = Backlog::open(&path, BacklogFormat::Text).unwrap();
+        let mut backlog = backlog.with_rotation(rotation);
+
+        let deleting = {
+            let path = path.clone();
+            std::thread::spawn(move || Backlog::remove_from_segments(&path, "default",
+                SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000), SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_299)))
+        };
+        Backlog::compress_closed_segments(&path).unwrap();
+        for secs in [1_700_001_000, 1_700_001_060, 1_700_001_120] {
+            backlog.append("default", &sample(secs, 25.0)).unwrap();
+        }
+        deleting.join().unwrap().unwrap();
+
+        let segments = Backlog::closed_segments(&path).unwrap();
+        let names: Vec<String> = segments.iter().map(|segment| segment.to_string_lossy().to_string()).collect();
+        let kept: Vec<f64> = segments.iter()
+            .flat_map(|segment| Backlog::load_segment(segment).unwrap())
+            .map(|(_, sample)| sample.temperature)
+            .collect();
+        for segment in segments.iter().map(|segment| segment.as_path()).chain([std::path::Path::new(&path)]) {
+            std::fs::remove_file(segment).unwrap();
+        }
+
+        assert!(names.iter().all(|name| !names.contains(&format!("{}.zst", name))), "{:?}", names);
+        assert!(kept.iter().all(|temperature| *temperature == 25.0), "{:?}", kept);
+    }
+}
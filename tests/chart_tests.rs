@@ -0,0 +1,35 @@
+use heat_monitor::chart::{Chart, Line};
+use heat_monitor::storage::Bucket;
+
+fn bucket(start: u64, value: Option<f64>) -> Bucket {
+    Bucket { start, value, count: value.map_or(0, |_| 1), gap: value.is_none() }
+}
+
+#[test]
+fn test_render_png() {
+    let buckets = [bucket(1758294000, Some(21.3)), bucket(1758294060, None), bucket(1758294120, Some(21.8)), bucket(1758294180, Some(22.0))];
+    let chart = Chart {
+        title: "office",
+        y_label: "temperature (°C)",
+        lines: vec![Line { label: "office", buckets: &buckets }],
+        width: 320,
+        height: 200,
+    };
+    let png = chart.render_png(&|secs| secs.to_string()).unwrap();
+
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    // the IHDR chunk leads with the width and height
+    assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 320);
+    assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 200);
+}
+
+#[test]
+fn test_render_png_needs_values_and_size() {
+    let gaps = [bucket(1758294000, None)];
+    let chart = Chart { title: "office", y_label: "temperature", lines: vec![Line { label: "office", buckets: &gaps }], width: 320, height: 200 };
+    assert!(chart.render_png(&|secs| secs.to_string()).is_err());
+
+    let buckets = [bucket(1758294000, Some(21.3))];
+    let chart = Chart { title: "office", y_label: "temperature", lines: vec![Line { label: "office", buckets: &buckets }], width: 10, height: 200 };
+    assert!(chart.render_png(&|secs| secs.to_string()).is_err());
+}
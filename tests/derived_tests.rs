@@ -0,0 +1,24 @@
+use std::time::SystemTime;
+use heat_monitor::derived::{dew_point, DEW_POINT};
+use heat_monitor::storage::Sample;
+
+#[test]
+fn test_dew_point() {
+    assert!((dew_point(20.0, 50.0).unwrap() - 9.26).abs() < 0.01);
+    assert!((dew_point(25.0, 100.0).unwrap() - 25.0).abs() < 1e-9);
+    assert_eq!(dew_point(20.0, 0.0), None);
+    assert_eq!(dew_point(20.0, f64::NAN), None);
+}
+
+#[test]
+fn test_dew_point_of_sample() {
+    let mut sample = Sample::new(SystemTime::now(), 20.0);
+    assert_eq!(sample.value(DEW_POINT), None);
+
+    sample.metrics.insert("humidity".to_string(), 50.0);
+    assert!((sample.value(DEW_POINT).unwrap() - 9.26).abs() < 0.01);
+
+    // a dew point reported by the device wins
+    sample.metrics.insert(DEW_POINT.to_string(), 9.0);
+    assert_eq!(sample.value(DEW_POINT), Some(9.0));
+}
@@ -0,0 +1,21 @@
+use heat_monitor::discovery::{classify, DeviceKind, DiscoveredDevice};
+
+#[test]
+fn test_classify_services() {
+    assert_eq!(classify("_esphomelib._tcp.local.", "living-room"), Some(DeviceKind::Esphome));
+    assert_eq!(classify("_shelly._tcp.local.", "shellyplusht-08b61fcb7f3c"), Some(DeviceKind::Shelly));
+    assert_eq!(classify("_http._tcp.local.", "shellyht-AABBCC"), Some(DeviceKind::Shelly));
+    assert_eq!(classify("_http._tcp.local.", "tasmota-1A2B3C-1234"), Some(DeviceKind::Tasmota));
+    assert_eq!(classify("_http._tcp.local.", "printer"), None);
+}
+
+#[test]
+fn test_suggested_config() {
+    let device = DiscoveredDevice::new("tasmota-1A2B3C", DeviceKind::Tasmota,
+        "tasmota-1A2B3C.local.", Some("192.168.6.80".to_string()), 80);
+    assert!(device.suggested_config.contains("type = \"tasmota\""));
+    assert!(device.suggested_config.contains("url = \"http://192.168.6.80\""));
+
+    let device = DiscoveredDevice::new("shellyht-AABBCC", DeviceKind::Shelly, "shellyht-AABBCC.local.", None, 80);
+    assert!(device.suggested_config.contains("device = \"shellyht-AABBCC\""));
+}
@@ -0,0 +1,70 @@
+use std::time::{Duration, SystemTime};
+use heat_monitor::downsampling::{roll_up, roll_up_records, Tier};
+use heat_monitor::storage::Sample;
+
+fn at(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn sample(secs: u64, temperature: f64) -> Sample {
+    Sample::new(at(secs), temperature)
+}
+
+const BASE: u64 = 1_700_000_000 - 1_700_000_000 % 3600;
+
+#[test]
+fn test_tiers_roll_up_by_age() {
+    let tiers = [
+        Tier::new(at(BASE + 7200), Duration::from_secs(300)),
+        Tier::new(at(BASE + 3600), Duration::from_secs(3600)),
+    ];
+    let mut with_humidity = sample(BASE + 60, 22.0);
+    with_humidity.metrics.insert("humidity".to_string(), 40.0);
+    let samples = vec![
+        sample(BASE, 20.0),
+        with_humidity,
+        sample(BASE + 3600, 21.0),
+        sample(BASE + 3660, 23.0),
+        sample(BASE + 3900, 25.0),
+        sample(BASE + 7200, 30.0),
+    ];
+
+    let rolled = roll_up(samples, &tiers);
+
+    assert_eq!(rolled.len(), 4);
+    assert_eq!(rolled[0].timestamp, at(BASE));
+    assert_eq!(rolled[0].span, Some(Duration::from_secs(3600)));
+    assert_eq!(rolled[0].temperature, 21.0);
+    assert_eq!(rolled[0].value("humidity"), Some(40.0));
+    assert_eq!(rolled[1].timestamp, at(BASE + 3600));
+    assert_eq!(rolled[1].span, Some(Duration::from_secs(300)));
+    assert_eq!(rolled[1].temperature, 22.0);
+    assert_eq!(rolled[2].temperature, 25.0);
+    assert_eq!(rolled[3].span, None);
+}
+
+#[test]
+fn test_rollups_are_not_rolled_again() {
+    let tiers = [Tier::new(at(BASE + 3600), Duration::from_secs(300))];
+    let samples = roll_up(vec![sample(BASE, 20.0), sample(BASE + 60, 21.0)], &tiers);
+    let again = roll_up(samples.clone(), &tiers);
+
+    assert_eq!(again.len(), 1);
+    assert_eq!(again[0].temperature, samples[0].temperature);
+}
+
+#[test]
+fn test_records_are_rolled_up_per_series() {
+    let tiers = [Tier::new(at(BASE + 3600), Duration::from_secs(300))];
+    let records = vec![
+        ("default".to_string(), sample(BASE, 20.0)),
+        ("office".to_string(), sample(BASE + 30, 10.0)),
+        ("default".to_string(), sample(BASE + 60, 22.0)),
+    ];
+
+    let rolled = roll_up_records(records, &tiers);
+
+    assert_eq!(rolled.len(), 2);
+    assert!(rolled.iter().any(|(series, sample)| series == "default" && sample.temperature == 21.0));
+    assert!(rolled.iter().any(|(series, sample)| series == "office" && sample.temperature == 10.0));
+}
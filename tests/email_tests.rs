@@ -0,0 +1,81 @@
+use std::time::{Duration, SystemTime};
+use chrono::NaiveTime;
+use heat_monitor::alerts::{AlertEvent, AlertState, Priority};
+use heat_monitor::config::Config;
+use heat_monitor::email::{alert_mail, summary_mail, until_next};
+use heat_monitor::storage::{Sample, Storage};
+
+fn at(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn frost() -> AlertEvent {
+    AlertEvent {
+        id: 1,
+        rule: "frost".to_string(),
+        sensor: "office".to_string(),
+        metric: "temperature".to_string(),
+        state: AlertState::Active,
+        value: 14.5,
+        since: 1758294000,
+        timestamp: 1758294600,
+        priority: Priority::Normal,
+    }
+}
+
+#[test]
+fn test_until_next() {
+    let seven = NaiveTime::from_hms_opt(7, 0, 0).unwrap();
+    // 2025-09-19 06:00 UTC
+    let now = at(1758261600);
+    assert_eq!(until_next(&chrono::Utc, now, seven), Duration::from_secs(3600));
+    // 08:00 CEST, the next 07:00 is tomorrow
+    assert_eq!(until_next(&chrono_tz::Europe::Prague, now, seven), Duration::from_secs(23 * 3600));
+    // exactly at the time, the next one is a day later
+    assert_eq!(until_next(&chrono::Utc, at(1758265200), seven), Duration::from_secs(24 * 3600));
+}
+
+#[test]
+fn test_until_next_skipped_by_dst() {
+    // 2025-03-30 00:00 CET, clocks jump from 02:00 to 03:00
+    let midnight = at(1743289200);
+    let half_past_two = NaiveTime::from_hms_opt(2, 30, 0).unwrap();
+    assert_eq!(until_next(&chrono_tz::Europe::Prague, midnight, half_past_two), Duration::from_secs(2 * 3600 + 1800));
+}
+
+#[test]
+fn test_alert_mail() {
+    let (subject, body) = alert_mail(&frost(), &|secs| format!("@{}", secs));
+    assert_eq!(subject, "heater-monitor: frost is active for office: temperature 14.5");
+    assert!(body.contains("Alert frost is active for sensor office."));
+    assert!(body.contains("since: @1758294000"));
+    assert!(body.contains("time: @1758294600"));
+}
+
+#[test]
+fn test_summary_mail() {
+    let config = Config::parse(r#"
+sampling_interval = 45
+averaging_interval = 120
+port = 3000
+listen_address = "0.0.0.0"
+log_path = "/tmp/heat_monitor.log"
+pid_file = "/tmp/heater-monitor.pid"
+temp_sensor_url = "http://192.168.6.75/"
+"#).unwrap();
+    let mut storage = Storage::new(&config).unwrap();
+    let now = at(1758294600);
+    storage.add_sample("office", Sample::new(at(1758294000), 20.0));
+    storage.add_sample("office", Sample::new(at(1758294300), 22.0));
+    storage.add_sample("garage", Sample::new(at(1758000000), 8.0));
+
+    let (subject, body) = summary_mail(&storage, &[], now);
+    assert_eq!(subject, "heater-monitor daily summary");
+    assert!(body.contains("office: 22.0 °C now, 20.0 to 22.0 °C over the last 24 hours, mean 21.0 °C"), "{}", body);
+    assert!(body.contains("garage: no samples in the last 24 hours"));
+    assert!(body.contains("No active alerts."));
+
+    let (subject, body) = summary_mail(&storage, &[frost()], now);
+    assert_eq!(subject, "heater-monitor daily summary, 1 active alerts");
+    assert!(body.contains("- frost is active for office: temperature 14.5"));
+}
@@ -0,0 +1,66 @@
+use std::time::{Duration, SystemTime};
+use heat_monitor::export::{Export, ExportFormat};
+use heat_monitor::import::parse_csv;
+use heat_monitor::storage::Sample;
+
+fn samples() -> Vec<Sample> {
+    let mut humid = Sample::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000), 20.5);
+    humid.metrics.insert("humidity".to_string(), 40.0);
+    let mut co2 = Sample::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_060), 21.0);
+    co2.metrics.insert("co2".to_string(), 812.0);
+    vec![humid, co2]
+}
+
+fn export(format: ExportFormat, samples: Vec<Sample>) -> String {
+    Export::new(format, samples).into_chunks().collect()
+}
+
+#[test]
+fn test_csv_has_a_column_per_metric() {
+    assert_eq!(
+        export(ExportFormat::Csv, samples()),
+        "timestamp,temperature,co2,humidity\n1700000000,20.5,,40\n1700000060,21,812,\n"
+    );
+}
+
+#[test]
+fn test_csv_can_be_imported() {
+    let imported = parse_csv(&export(ExportFormat::Csv, samples())).unwrap();
+
+    assert_eq!(imported.len(), 2);
+    assert_eq!(imported[0].value("humidity"), Some(40.0));
+    assert_eq!(imported[1].temperature, 21.0);
+}
+
+#[test]
+fn test_json_spans_chunks() {
+    let many: Vec<Sample> = (0..2500)
+        .map(|i| Sample::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + i * 60), 20.0))
+        .collect();
+    let json: serde_json::Value = serde_json::from_str(&export(ExportFormat::Json, many)).unwrap();
+
+    let array = json.as_array().unwrap();
+    assert_eq!(array.len(), 2500);
+    assert_eq!(array[1]["timestamp"], 1_700_000_060);
+
+    let json: serde_json::Value = serde_json::from_str(&export(ExportFormat::Json, samples())).unwrap();
+    assert_eq!(json[1]["co2"], 812.0);
+    assert_eq!(export(ExportFormat::Json, Vec::new()), "[]");
+}
+
+#[test]
+fn test_influx_line_protocol() {
+    let tags = [("location".to_string(), "my home".to_string()), ("sensor".to_string(), "office".to_string())]
+        .into_iter()
+        .collect();
+    let lines = Export::new(ExportFormat::Influx, samples())
+        .with_series_key("climate,indoor", &tags)
+        .into_chunks()
+        .collect::<String>();
+
+    assert_eq!(
+        lines,
+        "climate\\,indoor,location=my\\ home,sensor=office temperature=20.5,humidity=40 1700000000000000000\n\
+         climate\\,indoor,location=my\\ home,sensor=office temperature=21,co2=812 1700000060000000000\n"
+    );
+}
@@ -0,0 +1,42 @@
+use std::time::{Duration, SystemTime};
+use heat_monitor::import::parse_csv;
+
+#[test]
+fn test_unix_timestamps_with_humidity() {
+    let samples = parse_csv("timestamp,temperature,humidity\n1700000060,21.5,\n1700000000,20.5,40\n").unwrap();
+
+    assert_eq!(samples.len(), 2);
+    assert_eq!(samples[0].timestamp, SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    assert_eq!(samples[0].value("humidity"), Some(40.0));
+    assert_eq!(samples[1].temperature, 21.5);
+    assert!(samples[1].metrics.is_empty());
+}
+
+#[test]
+fn test_semicolons_quotes_and_extra_columns() {
+    let csv = "\"Date\";\"Battery\";\"Temp\"\n\"2023-11-14T22:13:20Z\";\"98\";\"19.25\"\n";
+    let samples = parse_csv(csv).unwrap();
+
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].timestamp, SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    assert_eq!(samples[0].temperature, 19.25);
+}
+
+#[test]
+fn test_invalid_rows_are_reported() {
+    assert!(parse_csv("").is_err());
+    assert!(parse_csv("time,humidity\n1700000000,40\n").is_err());
+
+    let error = parse_csv("time,temp\n1700000000,20\nyesterday,21\n").unwrap_err();
+    assert!(error.to_string().contains("line 3"), "{}", error);
+}
+
+#[test]
+fn test_out_of_range_and_non_finite_values_are_reported() {
+    let error = parse_csv("time,temp\n18446744073709551615,20\n").unwrap_err();
+    assert!(error.to_string().contains("line 2: invalid timestamp"), "{}", error);
+
+    for row in ["1700000000,NaN,40", "1700000000,inf,40", "1700000000,20,NaN", "1700000000,20,-inf"] {
+        assert!(parse_csv(&format!("time,temp,humidity\n{}\n", row)).is_err(), "{}", row);
+    }
+}
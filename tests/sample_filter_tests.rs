@@ -0,0 +1,46 @@
+use heat_monitor::sample_filter::{median, FilterConfig, SampleFilter};
+
+#[test]
+fn test_nan_is_always_rejected() {
+    let mut filter = SampleFilter::new(FilterConfig::default());
+    assert!(filter.check(f64::NAN).is_err());
+    assert!(filter.check(21.0).is_ok());
+    assert_eq!(filter.rejected(), 1);
+}
+
+#[test]
+fn test_out_of_range_is_rejected() {
+    let mut filter = SampleFilter::new(FilterConfig { min: Some(-40.0), max: Some(85.0), max_jump: None });
+    assert!(filter.check(655.35).is_err());
+    assert!(filter.check(-50.0).is_err());
+    assert!(filter.check(85.0).is_ok());
+    assert_eq!(filter.rejected(), 2);
+}
+
+#[test]
+fn test_spike_is_rejected() {
+    let mut filter = SampleFilter::new(FilterConfig { min: None, max: None, max_jump: Some(10.0) });
+    assert!(filter.check(21.0).is_ok());
+    assert!(filter.check(45.0).is_err());
+    assert!(filter.check(22.0).is_ok());
+    assert_eq!(filter.rejected(), 1);
+}
+
+#[test]
+fn test_persistent_step_is_accepted_eventually() {
+    let mut filter = SampleFilter::new(FilterConfig { min: None, max: None, max_jump: Some(10.0) });
+    assert!(filter.check(21.0).is_ok());
+    for _ in 0..5 {
+        assert!(filter.check(40.0).is_err());
+    }
+    assert!(filter.check(40.0).is_ok());
+    assert!(filter.check(41.0).is_ok());
+}
+
+#[test]
+fn test_median() {
+    assert_eq!(median(&[]), None);
+    assert_eq!(median(&[f64::NAN]), None);
+    assert_eq!(median(&[21.0, 35.0, 20.5]), Some(21.0));
+    assert_eq!(median(&[21.0, 22.0, f64::NAN, 20.0, 23.0]), Some(21.5));
+}
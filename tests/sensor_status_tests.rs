@@ -0,0 +1,53 @@
+use heat_monitor::sensor_status::{SensorAlert, SensorStatuses};
+
+#[test]
+fn test_new_sensors_have_no_history() {
+    let statuses = SensorStatuses::new(["default", "boiler_room"]);
+    let status = statuses.get("boiler_room").unwrap();
+
+    assert!(status.last_success.is_none());
+    assert_eq!(status.consecutive_failures, 0);
+    assert!(status.last_error.is_none());
+    assert_eq!(statuses.iter().count(), 2);
+}
+
+#[test]
+fn test_failures_are_counted_until_success() {
+    let mut statuses = SensorStatuses::new(["default"]);
+    statuses.record_failure("default", "timed out");
+    statuses.record_failure("default", "connection refused");
+
+    let status = statuses.get("default").unwrap();
+    assert_eq!(status.consecutive_failures, 2);
+    assert_eq!(status.last_error.as_deref(), Some("connection refused"));
+
+    statuses.record_success("default");
+    let status = statuses.get("default").unwrap();
+    assert_eq!(status.consecutive_failures, 0);
+    assert_eq!(status.total_failures, 2);
+    assert!(status.last_success.is_some());
+    assert_eq!(status.last_error.as_deref(), Some("connection refused"));
+}
+
+#[test]
+fn test_battery_level() {
+    let mut statuses = SensorStatuses::new(["bedroom"]);
+    assert!(statuses.get("bedroom").unwrap().battery.is_none());
+
+    statuses.record_battery("bedroom", 87.0);
+    assert_eq!(statuses.get("bedroom").unwrap().battery, Some(87.0));
+}
+
+#[test]
+fn test_alerts_on_failing_and_recovery() {
+    let mut statuses = SensorStatuses::new(["default"]);
+    let mut alerts = statuses.subscribe();
+    statuses.record_success("default");
+    statuses.record_failure("default", "timed out");
+    statuses.record_failure("default", "timed out");
+    statuses.record_success("default");
+
+    assert_eq!(alerts.try_recv().unwrap(), SensorAlert::Failing { sensor: "default".to_string(), error: "timed out".to_string() });
+    assert_eq!(alerts.try_recv().unwrap(), SensorAlert::Recovered { sensor: "default".to_string() });
+    assert!(alerts.try_recv().is_err());
+}
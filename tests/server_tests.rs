@@ -0,0 +1,49 @@
+use std::time::{Duration, SystemTime};
+use heat_monitor::app_error::AppError;
+use heat_monitor::server::{is_new_push, secret_eq, temps_range, unix_time};
+use heat_monitor::storage::Sample;
+
+fn at(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[test]
+fn test_unix_time() {
+    assert_eq!(unix_time("from", 1758294000).unwrap(), at(1758294000));
+    assert!(matches!(unix_time("from", u64::MAX), Err(AppError::BadRequest(_))));
+}
+
+#[test]
+fn test_temps_range() {
+    let now = at(1758294000);
+    assert_eq!(temps_range(None, None, None, now).unwrap(), (at(1758294000 - 3 * 3600), now));
+    assert_eq!(temps_range(Some("1758200000"), None, Some(2), now).unwrap(), (at(1758200000), at(1758207200)));
+    assert_eq!(temps_range(None, Some("1758290000"), Some(u64::MAX), now).unwrap(), (SystemTime::UNIX_EPOCH, at(1758290000)));
+}
+
+#[test]
+fn test_temps_range_out_of_range() {
+    let now = at(1758294000);
+    assert!(matches!(temps_range(Some("18446744073709551615"), None, None, now), Err(AppError::BadRequest(_))));
+    assert!(matches!(temps_range(None, Some("18446744073709551615"), None, now), Err(AppError::BadRequest(_))));
+    assert!(matches!(temps_range(Some("0"), None, Some(u64::MAX), now), Err(AppError::BadRequest(_))));
+}
+
+#[test]
+fn test_secret_eq() {
+    assert!(secret_eq("s3cret", "s3cret"));
+    assert!(!secret_eq("s3cret", "s3creT"));
+    assert!(!secret_eq("s3cret", "s3cret "));
+    assert!(!secret_eq("s3cret", ""));
+}
+
+#[test]
+fn test_is_new_push() {
+    let latest = Sample::new(at(1758294000), 21.5);
+    assert!(is_new_push(None, &latest).unwrap());
+    assert!(is_new_push(Some(&latest), &Sample::new(at(1758294060), 21.0)).unwrap());
+    // a device retrying after a timeout
+    assert!(!is_new_push(Some(&latest), &Sample::new(at(1758294000), 21.5)).unwrap());
+    assert!(matches!(is_new_push(Some(&latest), &Sample::new(at(1758294000), 22.0)), Err(AppError::BadRequest(_))));
+    assert!(matches!(is_new_push(Some(&latest), &Sample::new(at(1758293940), 21.0)), Err(AppError::BadRequest(_))));
+}
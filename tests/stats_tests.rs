@@ -0,0 +1,56 @@
+use heat_monitor::stats::{slope, Stats, Trend};
+
+#[test]
+fn test_stats_of_values() {
+    let stats = Stats::from_values(vec![4.0, 2.0, 8.0, 6.0]).unwrap();
+
+    assert_eq!(stats.count, 4);
+    assert_eq!(stats.min, 2.0);
+    assert_eq!(stats.max, 8.0);
+    assert_eq!(stats.mean, 5.0);
+    assert!((stats.stddev - 5.0f64.sqrt()).abs() < 1e-9);
+}
+
+#[test]
+fn test_percentiles_interpolate() {
+    let values: Vec<f64> = (0..=100).map(f64::from).collect();
+    let stats = Stats::from_values(values).unwrap();
+    assert_eq!(stats.p5, 5.0);
+    assert_eq!(stats.p95, 95.0);
+
+    let stats = Stats::from_values(vec![10.0, 20.0]).unwrap();
+    assert!((stats.p5 - 10.5).abs() < 1e-9);
+    assert!((stats.p95 - 19.5).abs() < 1e-9);
+}
+
+#[test]
+fn test_single_value() {
+    let stats = Stats::from_values(vec![21.5]).unwrap();
+    assert_eq!(stats.stddev, 0.0);
+    assert_eq!(stats.p5, 21.5);
+    assert_eq!(stats.p95, 21.5);
+}
+
+#[test]
+fn test_non_finite_values_are_skipped() {
+    assert_eq!(Stats::from_values(vec![]), None);
+    assert_eq!(Stats::from_values(vec![f64::NAN]), None);
+    assert_eq!(Stats::from_values(vec![f64::NAN, 3.0, f64::INFINITY]).unwrap().count, 1);
+}
+
+#[test]
+fn test_slope() {
+    assert_eq!(slope(&[(0.0, 20.0), (0.5, 21.0), (1.0, 22.0)]), Some(2.0));
+    assert!((slope(&[(0.0, 20.0), (1.0, 19.0), (2.0, 18.5)]).unwrap() + 0.75).abs() < 1e-9);
+    assert_eq!(slope(&[(1.0, 20.0)]), None);
+    assert_eq!(slope(&[(1.0, 20.0), (1.0, 21.0)]), None);
+    assert_eq!(slope(&[]), None);
+}
+
+#[test]
+fn test_trend_from_rate() {
+    assert_eq!(Trend::from_rate(1.5), Trend::Rising);
+    assert_eq!(Trend::from_rate(-0.5), Trend::Falling);
+    assert_eq!(Trend::from_rate(0.1), Trend::Steady);
+    assert_eq!(Trend::from_rate(-0.1), Trend::Steady);
+}
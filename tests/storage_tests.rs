@@ -1,4 +1,5 @@
-use heat_monitor::storage::{Storage, StorageError};
+use heat_monitor::storage::{is_valid_metric_name, Bucket, Cursor, Sample, Storage, StorageError};
+use heat_monitor::backlog::{Backlog, BacklogFormat};
 use std::time::{Duration, SystemTime};
 use heat_monitor::config::Config;
 
@@ -6,13 +7,47 @@ use heat_monitor::config::Config;
 fn default_config() -> Config {
     Config {
         max_capacity: Some(10000000),
+        max_memory_mb: None,
         port: 3000,
         sampling_interval: 35,
         averaging_interval: 120,
-        listen_address: "0.0.0.0".to_string(),
+        carry_forward_buckets: 5,
+        max_points: 1500,
+        stale_after: 600,
+        listen_address: vec!["0.0.0.0".to_string()],
         log_path: "test.log".to_string(),
         backlog: None,
-        temp_sensor_url: "http://localhost:3000/temperature".to_string(),
+        backlog_format: BacklogFormat::Text,
+        compress_backlog: false,
+        backlog_max_size_mb: None,
+        backlog_max_age_hours: None,
+        snapshot: None,
+        backlog_flush_interval: None,
+        backlog_fsync_interval: None,
+        retention_days: None,
+        downsampling: None,
+        temp_sensor_url: Some("http://localhost:3000/temperature".to_string()),
+        temp_sensor_regex: None,
+        sensors: vec![],
+        pid_file: "test.pid".to_string(),
+        mqtt: None,
+        push_token: None,
+        admin_token: None,
+        annotations: None,
+        outdoor_sensor: None,
+        http_user_agent: None,
+        discovery: false,
+        influx: Default::default(),
+        alerts: Vec::new(),
+        stale_alert: None,
+        webhooks: Vec::new(),
+        email: None,
+        telegram: None,
+        ntfy: None,
+        pushover: None,
+        server: Default::default(),
+        backfill_from: None,
+        timezone: None,
     }
 }
 
@@ -289,3 +324,642 @@ fn test_capacity_with_range_queries() {
     let samples = storage.get_samples_in_range(minute_ago, now).unwrap();
     assert_eq!(samples.len(), 5); // All remaining samples should be within range
 }
+
+#[test]
+fn test_sensor_series_are_separate() {
+    let mut storage = create_test_storage();
+
+    storage.add_sensor_measurement("living_room", 21.0, 40.0);
+    storage.add_sensor_measurement("boiler_room", 35.0, 20.0);
+    storage.add_sensor_measurement("living_room", 21.5, 41.0);
+
+    let living_room = storage.series("living_room").unwrap();
+    let boiler_room = storage.series("boiler_room").unwrap();
+
+    assert_eq!(living_room.len(), 2);
+    assert_eq!(living_room.latest_sample().unwrap().temperature, 21.5);
+    assert_eq!(boiler_room.len(), 1);
+    assert_eq!(boiler_room.latest_sample().unwrap().temperature, 35.0);
+
+    // The default series is untouched by named sensors
+    assert!(storage.is_empty());
+    assert!(storage.series("attic").is_none());
+}
+
+#[test]
+fn test_backlog_restores_sensor_series() {
+    let path = std::env::temp_dir().join(format!("heat_monitor_{}.backlog", uuid::Uuid::new_v4()));
+    let mut config = default_config();
+    config.backlog = Some(path.to_string_lossy().to_string());
+
+    {
+        let mut storage = Storage::new(&config).unwrap();
+        storage.add_measurement(20.0, 40.0);
+        storage.add_sensor_measurement("boiler_room", 35.0, 20.0);
+    }
+
+    let storage = Storage::new(&config).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(storage.len(), 1);
+    assert_eq!(storage.latest_sample().unwrap().temperature, 20.0);
+    assert_eq!(storage.series("boiler_room").unwrap().latest_sample().unwrap().temperature, 35.0);
+}
+
+#[test]
+fn test_backlog_replay_drops_duplicates() {
+    let path = std::env::temp_dir().join(format!("heat_monitor_{}.backlog", uuid::Uuid::new_v4()));
+    let segment = format!("{}.20231114-000000", path.display());
+    let mut config = default_config();
+    config.backlog = Some(path.to_string_lossy().to_string());
+
+    // the segment overlaps the live backlog, which also repeats a record
+    std::fs::write(&segment, "t1 1699999800 20\nt1 1699999860 21\n").unwrap();
+    std::fs::write(&path, "t1 1699999860 22\ns1 office 1699999860 18\nt1 1699999920 23\nt1 1699999920 23\n").unwrap();
+
+    let storage = Storage::new(&config).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&segment).unwrap();
+
+    let temperatures: Vec<f64> = storage.get_samples_in_range(SystemTime::UNIX_EPOCH, SystemTime::now()).unwrap()
+        .iter().map(|sample| sample.temperature).collect();
+    assert_eq!(temperatures, [20.0, 22.0, 23.0]);
+    assert_eq!(storage.series("office").unwrap().len(), 1);
+}
+
+#[test]
+fn test_delete_range_rewrites_backlog() {
+    let path = std::env::temp_dir().join(format!("heat_monitor_{}.backlog", uuid::Uuid::new_v4()));
+    let segment = format!("{}.20231114-000000", path.display());
+    let mut config = default_config();
+    config.backlog = Some(path.to_string_lossy().to_string());
+
+    std::fs::write(&segment, "t1 1699999800 20\nt1 1699999860 99\ns1 office 1699999860 18\n").unwrap();
+    std::fs::write(&path, "t1 1699999920 99\nt1 1699999980 23\n").unwrap();
+    let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+
+    let mut storage = Storage::new(&config).unwrap();
+    assert_eq!(storage.delete_range("default", at(1_699_999_860), at(1_699_999_920)).unwrap(), 2);
+    let backlog = storage.backlog_path().unwrap().to_string();
+    assert_eq!(Backlog::remove_from_segments(&backlog, "default", at(1_699_999_860), at(1_699_999_920)).unwrap(), 1);
+    storage.add_measurement(24.0, f64::NAN);
+    let temperatures = |storage: &Storage| -> Vec<f64> {
+        storage.get_samples_in_range(SystemTime::UNIX_EPOCH, SystemTime::now()).unwrap()
+            .iter().map(|sample| sample.temperature).collect()
+    };
+    assert_eq!(temperatures(&storage), [20.0, 23.0, 24.0]);
+
+    drop(storage);
+    let storage = Storage::new(&config).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&segment).unwrap();
+
+    assert_eq!(temperatures(&storage), [20.0, 23.0, 24.0]);
+    assert_eq!(storage.series("office").unwrap().len(), 1);
+}
+
+#[test]
+fn test_delete_range_drops_last_sample() {
+    let mut storage = create_test_storage();
+    let base = SystemTime::now() - Duration::from_secs(600);
+    storage.add_sensor_measurement_at("office", base, 21.0, f64::NAN);
+    storage.add_sensor_measurement_at("office", base + Duration::from_secs(60), 85.0, f64::NAN);
+
+    assert_eq!(storage.delete_range("office", base + Duration::from_secs(30), SystemTime::now()).unwrap(), 1);
+    let series = storage.series("office").unwrap();
+    assert_eq!(series.get_last_sample().unwrap().temperature, 21.0);
+    assert_eq!(series.memory_usage(), Sample::new(base, 21.0).memory_size());
+}
+
+#[test]
+fn test_measurement_with_timestamp() {
+    let mut storage = create_test_storage();
+    let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+    storage.add_sensor_measurement_at("esp", base, 20.0, 40.0);
+    storage.add_sensor_measurement_at("esp", base + Duration::from_secs(60), 21.0, 40.0);
+    // older than the newest sample, dropped
+    storage.add_sensor_measurement_at("esp", base + Duration::from_secs(30), 25.0, 40.0);
+
+    let series = storage.series("esp").unwrap();
+    assert_eq!(series.len(), 2);
+    assert_eq!(series.oldest_sample().unwrap().timestamp, base);
+    assert_eq!(series.latest_sample().unwrap().temperature, 21.0);
+}
+
+#[test]
+fn test_metrics_are_stored_and_averaged() {
+    let mut storage = create_test_storage();
+    let base = SystemTime::now() - Duration::from_secs(600);
+
+    let mut sample = Sample::new(base, 21.0);
+    sample.metrics.insert("co2".to_string(), 800.0);
+    storage.add_sample("office", sample);
+    // no co2 in this reading, skipped by the co2 average
+    storage.add_sensor_measurement_at("office", base + Duration::from_secs(30), 22.0, 40.0);
+
+    let series = storage.series("office").unwrap();
+    let latest = series.latest_sample().unwrap();
+    assert_eq!(latest.value("temperature"), Some(22.0));
+    assert_eq!(latest.value("humidity"), Some(40.0));
+    assert_eq!(latest.value("co2"), None);
+
+    let co2 = series.metric_avg_fill("co2", base, SystemTime::now(), Duration::from_secs(120)).unwrap();
+    assert_eq!(co2[0], Some(800.0));
+    let temperature = series.per_minute_avg_fill(base, SystemTime::now(), Duration::from_secs(120)).unwrap();
+    assert_eq!(temperature[0], Some(21.5));
+}
+
+#[test]
+fn test_stats_per_metric() {
+    let mut storage = create_test_storage();
+    let base = SystemTime::now() - Duration::from_secs(600);
+
+    let mut sample = Sample::new(base, 20.0);
+    sample.metrics.insert("co2".to_string(), 800.0);
+    storage.add_sample("office", sample);
+    storage.add_sensor_measurement_at("office", base + Duration::from_secs(30), 22.0, 40.0);
+
+    let stats = storage.series("office").unwrap().stats(base, SystemTime::now()).unwrap();
+    assert_eq!(stats.keys().collect::<Vec<_>>(), ["co2", "dew_point", "humidity", "temperature"]);
+    assert_eq!(stats["temperature"].count, 2);
+    assert_eq!(stats["temperature"].mean, 21.0);
+    assert_eq!(stats["temperature"].stddev, 1.0);
+    assert_eq!(stats["co2"].count, 1);
+    assert_eq!(stats["humidity"].max, 40.0);
+    assert_eq!(stats["dew_point"].count, 1);
+
+    let empty = storage.series("office").unwrap().stats(base - Duration::from_secs(60), base - Duration::from_secs(1));
+    assert!(matches!(empty, Err(StorageError::NoDataAvailable)));
+}
+
+#[test]
+fn test_buckets_mark_gaps_after_carry_forward() {
+    let mut storage = create_test_storage();
+    let base = SystemTime::now() - Duration::from_secs(3600);
+    storage.add_sensor_measurement_at("office", base, 20.0, f64::NAN);
+    storage.add_sensor_measurement_at("office", base + Duration::from_secs(10 * 60), 22.0, f64::NAN);
+
+    let series = storage.series("office").unwrap();
+    let minute = Duration::from_secs(60);
+    let carried = series.metric_buckets("temperature", base, SystemTime::now(), minute, 5).unwrap();
+    let start = |minutes: u64| (base + minute * minutes as u32).duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+    assert_eq!(carried[0], Bucket { start: start(0), value: Some(20.0), count: 1, gap: false });
+    assert_eq!(carried[5], Bucket { start: start(5), value: Some(20.0), count: 0, gap: false });
+    assert_eq!(carried[6], Bucket { start: start(6), value: None, count: 0, gap: true });
+    assert_eq!(carried[10], Bucket { start: start(10), value: Some(22.0), count: 1, gap: false });
+
+    let strict = series.metric_buckets("temperature", base, SystemTime::now(), minute, 0).unwrap();
+    assert!(strict[1..10].iter().all(|bucket| bucket.gap && bucket.value.is_none()));
+    assert!(!strict[10].gap);
+}
+
+#[test]
+fn test_backfill_adds_only_newer_samples() {
+    let mut storage = create_test_storage();
+    let base = SystemTime::now() - Duration::from_secs(3600);
+    storage.add_sensor_measurement_at("office", base + Duration::from_secs(600), 21.0, f64::NAN);
+
+    let peer: Vec<Sample> = (0..4)
+        .map(|i| Sample::new(base + Duration::from_secs(i * 300), 20.0 + i as f64))
+        .collect();
+    assert_eq!(storage.backfill("office", peer.clone()), 1);
+    assert_eq!(storage.backfill("hall", peer), 4);
+
+    let office = storage.series("office").unwrap();
+    assert_eq!(office.len(), 2);
+    assert_eq!(office.latest_sample().unwrap().temperature, 23.0);
+    assert_eq!(storage.series("hall").unwrap().len(), 4);
+}
+
+#[test]
+fn test_memory_budget_evicts_oldest_across_series() {
+    let mut config = default_config();
+    config.max_memory_mb = Some(1);
+    let mut storage = Storage::new(&config).unwrap();
+    let base = SystemTime::now() - Duration::from_secs(86400);
+    let size = Sample::new(base, 20.0).memory_size();
+    let fitting = 1024 * 1024 / size;
+
+    // the hall readings are older, so they are evicted first
+    for i in 0..fitting as u64 / 2 {
+        storage.add_sensor_measurement_at("hall", base + Duration::from_secs(i), 20.0, f64::NAN);
+    }
+    for i in 0..fitting as u64 {
+        storage.add_sensor_measurement_at("office", base + Duration::from_secs(40000 + i), 21.0, f64::NAN);
+    }
+
+    assert!(storage.memory_usage() <= 1024 * 1024);
+    assert_eq!(storage.series("office").unwrap().len(), fitting);
+    assert!(storage.series("hall").unwrap().is_empty());
+}
+
+#[test]
+fn test_memory_usage_follows_samples() {
+    let mut storage = create_test_storage();
+    let base = SystemTime::now() - Duration::from_secs(600);
+    let mut sample = Sample::new(base, 21.0);
+    sample.metrics.insert("co2".to_string(), 800.0);
+    let with_metric = sample.memory_size();
+    storage.add_sample("office", sample);
+    storage.add_sensor_measurement_at("office", base + Duration::from_secs(60), 22.0, f64::NAN);
+
+    let plain = Sample::new(base, 22.0).memory_size();
+    assert!(with_metric > plain);
+    assert_eq!(storage.memory_usage(), with_metric + plain);
+
+
+    let mut storage = create_test_storage_with_capacity(1);
+    storage.add_sensor_measurement_at("office", base, 21.0, 40.0);
+    storage.add_sensor_measurement_at("office", base + Duration::from_secs(60), 22.0, f64::NAN);
+    assert_eq!(storage.memory_usage(), plain);
+}
+
+#[test]
+fn test_rate_of_change_per_window() {
+    let mut storage = create_test_storage();
+    let from = SystemTime::now() - Duration::from_secs(3 * 3600);
+    // heating by 6 °C/h in the first hour, cooling by 1 °C/h in the second, nothing in the third
+    for minute in 0..60 {
+        storage.add_sensor_measurement_at("office", from + Duration::from_secs(minute * 60), 18.0 + minute as f64 / 10.0, f64::NAN);
+    }
+    for minute in 60..120 {
+        storage.add_sensor_measurement_at("office", from + Duration::from_secs(minute * 60), 24.0 - (minute - 60) as f64 / 60.0, f64::NAN);
+    }
+
+    let rates = storage.series("office").unwrap()
+        .rate_of_change("temperature", from, from + Duration::from_secs(3 * 3600), Duration::from_secs(3600)).unwrap();
+    assert_eq!(rates.len(), 3);
+    assert!((rates[0].per_hour.unwrap() - 6.0).abs() < 1e-9);
+    assert!((rates[1].per_hour.unwrap() + 1.0).abs() < 1e-9);
+    assert_eq!(rates[2].per_hour, None);
+    assert_eq!(rates[1].start - rates[0].start, 3600);
+}
+
+#[test]
+fn test_backlog_restores_metrics() {
+    let path = std::env::temp_dir().join(format!("heat_monitor_{}.backlog", uuid::Uuid::new_v4()));
+    let mut config = default_config();
+    config.backlog = Some(path.to_string_lossy().to_string());
+
+    {
+        let mut storage = Storage::new(&config).unwrap();
+        let mut sample = Sample::new(SystemTime::now(), 21.0);
+        sample.metrics.insert("pressure".to_string(), 1013.25);
+        storage.add_sample("office", sample);
+        storage.add_measurement(20.0, f64::NAN);
+    }
+
+    let storage = Storage::new(&config).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let office = storage.series("office").unwrap().latest_sample().unwrap();
+    assert_eq!(office.temperature, 21.0);
+    assert_eq!(office.value("pressure"), Some(1013.25));
+    assert!(storage.latest_sample().unwrap().metrics.is_empty());
+}
+
+#[test]
+fn test_backlog_loads_compressed_segments() {
+    let path = std::env::temp_dir().join(format!("heat_monitor_{}.backlog", uuid::Uuid::new_v4()));
+    let segment = format!("{}.1", path.display());
+    let compressed = format!("{}.zst", segment);
+    let mut config = default_config();
+    config.backlog = Some(path.to_string_lossy().to_string());
+    config.compress_backlog = true;
+
+    // the closed segment holds the older samples although it is read first
+    std::fs::write(&path, "t1 1700000120 22\n").unwrap();
+    std::fs::write(&segment, "t1 1700000000 20\nt1 1700000060 21\n").unwrap();
+
+    let storage = Storage::new(&config).unwrap();
+    assert_eq!(storage.len(), 3);
+    assert_eq!(storage.latest_sample().unwrap().temperature, 22.0);
+    assert!(!std::path::Path::new(&segment).exists());
+    assert!(std::path::Path::new(&compressed).exists());
+
+    let storage = Storage::new(&config).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&compressed).unwrap();
+    assert_eq!(storage.len(), 3);
+    assert_eq!(storage.oldest_sample().unwrap().temperature, 20.0);
+}
+
+#[test]
+fn test_backlog_skips_segments_beyond_capacity() {
+    let path = std::env::temp_dir().join(format!("heat_monitor_{}.backlog", uuid::Uuid::new_v4()));
+    let segment = format!("{}.20231114-000000", path.display());
+    let mut config = default_config();
+    config.backlog = Some(path.to_string_lossy().to_string());
+    config.max_capacity = Some(2);
+
+    std::fs::write(&segment, "t1 1700000000 20\n").unwrap();
+    std::fs::write(&path, "t1 1700000060 21\nt1 1700000120 22\n").unwrap();
+
+    let storage = Storage::new(&config).unwrap();
+    assert_eq!(storage.oldest_sample().unwrap().temperature, 21.0);
+
+    config.max_capacity = Some(3);
+    let storage = Storage::new(&config).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&segment).unwrap();
+    assert_eq!(storage.oldest_sample().unwrap().temperature, 20.0);
+}
+
+#[test]
+fn test_retention_evicts_old_samples() {
+    let mut config = default_config();
+    config.retention_days = Some(1);
+    let mut storage = Storage::new(&config).unwrap();
+    let now = SystemTime::now();
+    storage.add_sensor_measurement_at("default", now - Duration::from_secs(2 * 86400), 19.0, f64::NAN);
+    storage.add_sensor_measurement_at("default", now - Duration::from_secs(3600), 20.0, f64::NAN);
+    storage.add_sensor_measurement_at("office", now - Duration::from_secs(90000), 21.0, f64::NAN);
+
+    assert_eq!(storage.apply_retention(now).unwrap(), 2);
+    assert_eq!(storage.len(), 1);
+    assert_eq!(storage.oldest_sample().unwrap().temperature, 20.0);
+    let office = storage.series("office").unwrap();
+    assert!(office.is_empty());
+    assert_eq!(office.get_last_sample().unwrap().temperature, 21.0);
+}
+
+#[test]
+fn test_retention_prunes_expired_segments() {
+    let path = std::env::temp_dir().join(format!("heat_monitor_{}.backlog", uuid::Uuid::new_v4()));
+    let segment = format!("{}.20231114-000000", path.display());
+    let mut config = default_config();
+    config.backlog = Some(path.to_string_lossy().to_string());
+    config.retention_days = Some(30);
+
+    std::fs::write(&segment, "t1 1700000000 20\n").unwrap();
+    std::fs::File::options().write(true).open(&segment).unwrap()
+        .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)).unwrap();
+
+    let storage = Storage::new(&config).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(storage.is_empty());
+    assert!(!std::path::Path::new(&segment).exists());
+}
+
+#[test]
+fn test_downsampling_keeps_long_ranges_filled() {
+    let mut config = default_config();
+    config.downsampling = Some(heat_monitor::config::Downsampling { raw_days: 1, five_minute_days: 2 });
+    let mut storage = Storage::new(&config).unwrap();
+    let now = SystemTime::now();
+    let start = now - Duration::from_secs(3 * 86400);
+    for minute in 0..(3 * 24 * 60) {
+        storage.add_sensor_measurement_at("default", start + Duration::from_secs(minute * 60), 20.0, f64::NAN);
+    }
+
+    let shrunk = storage.downsample(now).unwrap();
+    // one day each of raw samples, 5-minute and hourly averages, plus the partly covered buckets
+    // where the tiers start
+    let expected = 24 * 60 + 24 * 12 + 24;
+    assert!((expected..=expected + 18).contains(&storage.len()), "{}", storage.len());
+    assert_eq!(shrunk, 3 * 24 * 60 - storage.len());
+
+    let series = storage.series("default").unwrap();
+    let averages = series.per_minute_avg_fill(start, now, Duration::from_secs(60)).unwrap();
+    let gaps: Vec<usize> = averages.iter().enumerate().filter(|(_, a)| **a != Some(20.0)).map(|(i, _)| i).collect();
+    assert!(gaps.is_empty(), "{:?} of {}", gaps, averages.len());
+}
+
+#[test]
+fn test_downsampling_rewrites_old_segments() {
+    let path = std::env::temp_dir().join(format!("heat_monitor_{}.backlog", uuid::Uuid::new_v4()));
+    let segment = format!("{}.20231114-000000", path.display());
+    let mut config = default_config();
+    config.backlog = Some(path.to_string_lossy().to_string());
+    config.downsampling = Some(heat_monitor::config::Downsampling { raw_days: 1, five_minute_days: 30 });
+
+    std::fs::write(&segment, "t1 1699999800 20\nt1 1699999860 22\n").unwrap();
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_699_999_860);
+    std::fs::File::options().write(true).open(&segment).unwrap().set_modified(modified).unwrap();
+
+    let mut storage = Storage::new(&config).unwrap();
+    storage.downsample(SystemTime::now()).unwrap();
+    let content = std::fs::read_to_string(&segment).unwrap();
+    let segment_modified = std::fs::metadata(&segment).unwrap().modified().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&segment).unwrap();
+
+    assert_eq!(content, "r1 default 1699999200 3600 21\n");
+    assert_eq!(segment_modified, modified);
+}
+
+fn snapshot_config() -> (Config, String, String) {
+    let base = std::env::temp_dir().join(format!("heat_monitor_{}", uuid::Uuid::new_v4()));
+    let backlog = format!("{}.backlog", base.display());
+    let snapshot = format!("{}.snapshot", base.display());
+    let mut config = default_config();
+    config.backlog = Some(backlog.clone());
+    config.snapshot = Some(snapshot.clone());
+    (config, backlog, snapshot)
+}
+
+#[test]
+fn test_snapshot_is_restored() {
+    let (config, backlog, snapshot) = snapshot_config();
+    let now = SystemTime::now();
+    {
+        let mut storage = Storage::new(&config).unwrap();
+        storage.add_sensor_measurement_at("office", now - Duration::from_secs(60), 20.0, 45.0);
+        storage.add_sensor_measurement_at("office", now, 21.0, f64::NAN);
+        storage.write_snapshot().unwrap();
+    }
+
+    let mut storage = Storage::new(&config).unwrap();
+    assert!(!std::path::Path::new(&snapshot).exists());
+    let office = storage.series("office").unwrap();
+    assert_eq!(office.len(), 2);
+    assert_eq!(office.oldest_sample().unwrap().value("humidity"), Some(45.0));
+
+    // the resumed backlog keeps being appended to
+    storage.add_sensor_measurement_at("office", now + Duration::from_secs(60), 22.0, f64::NAN);
+    storage.sync_backlog().unwrap();
+    let storage = Storage::new(&config).unwrap();
+    std::fs::remove_file(&backlog).unwrap();
+    assert_eq!(storage.series("office").unwrap().len(), 3);
+}
+
+#[test]
+fn test_stale_snapshot_falls_back_to_backlog() {
+    let (config, backlog, snapshot) = snapshot_config();
+    {
+        let mut storage = Storage::new(&config).unwrap();
+        storage.add_measurement(20.0, f64::NAN);
+        storage.write_snapshot().unwrap();
+    }
+    // written by a run that crashed before taking a new snapshot
+    let mut file = std::fs::File::options().append(true).open(&backlog).unwrap();
+    std::io::Write::write_all(&mut file, b"t1 4000000000 21\n").unwrap();
+
+    let storage = Storage::new(&config).unwrap();
+    std::fs::remove_file(&backlog).unwrap();
+    assert!(!std::path::Path::new(&snapshot).exists());
+    assert_eq!(storage.len(), 2);
+    assert_eq!(storage.latest_sample().unwrap().temperature, 21.0);
+}
+
+#[test]
+fn test_metric_names() {
+    assert!(is_valid_metric_name("co2"));
+    assert!(is_valid_metric_name("battery_voltage"));
+    assert!(!is_valid_metric_name(""));
+    assert!(!is_valid_metric_name("temperature"));
+    assert!(!is_valid_metric_name("co2=1"));
+    assert!(!is_valid_metric_name("air quality"));
+}
+
+#[test]
+fn test_daily_follows_time_zone_across_dst() {
+    let mut storage = create_test_storage();
+    let at = |secs: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+    // Europe/Prague moves to CEST at 01:00 UTC of 2026-03-29, so that day lasts 23 hours
+    let day_start = 1774738800; // 2026-03-28 23:00 UTC, local midnight of March 29
+    storage.add_sensor_measurement_at("office", at(day_start - 60), 15.0, f64::NAN);
+    storage.add_sensor_measurement_at("office", at(day_start + 1800), 18.0, f64::NAN);
+    storage.add_sensor_measurement_at("office", at(day_start + 12 * 3600), 22.0, f64::NAN);
+    storage.add_sensor_measurement_at("office", at(day_start + 23 * 3600 - 60), 20.0, f64::NAN);
+    storage.add_sensor_measurement_at("office", at(day_start + 23 * 3600), 19.0, f64::NAN);
+
+    let series = storage.series("office").unwrap();
+    let days = series.daily("temperature", at(day_start - 3600), at(day_start + 24 * 3600), &chrono_tz::Europe::Prague).unwrap();
+    let dates: Vec<_> = days.iter().map(|day| (day.date.as_str(), day.count)).collect();
+    assert_eq!(dates, vec![("2026-03-28", 1), ("2026-03-29", 3), ("2026-03-30", 1)]);
+    assert_eq!(days[1].min, 18.0);
+    assert_eq!(days[1].min_at, day_start + 1800);
+    assert_eq!(days[1].max, 22.0);
+    assert_eq!(days[1].max_at, day_start + 12 * 3600);
+    assert_eq!(days[1].mean, 20.0);
+
+    let utc = series.daily("temperature", at(day_start - 3600), at(day_start + 24 * 3600), &chrono::Utc).unwrap();
+    let dates: Vec<_> = utc.iter().map(|day| (day.date.as_str(), day.count)).collect();
+    assert_eq!(dates, vec![("2026-03-28", 2), ("2026-03-29", 3)]);
+}
+
+#[test]
+fn test_subscribers_receive_stored_samples() {
+    let mut storage = create_test_storage();
+    let mut stored = storage.subscribe();
+    let now = SystemTime::now();
+    storage.add_sensor_measurement_at("office", now, 21.5, 40.0);
+    // older than the newest sample, dropped
+    storage.add_sensor_measurement_at("office", now - Duration::from_secs(60), 20.0, f64::NAN);
+
+    let update = stored.try_recv().unwrap();
+    assert_eq!(update.series, "office");
+    assert_eq!(update.sample.temperature, 21.5);
+    assert_eq!(update.sample.value("humidity"), Some(40.0));
+    assert!(stored.try_recv().is_err());
+}
+
+#[test]
+fn test_pages_continue_after_cursor() {
+    let mut storage = create_test_storage();
+    let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    for i in 0..5 {
+        storage.add_sensor_measurement_at("office", base + Duration::from_secs(i * 60), 20.0 + i as f64, f64::NAN);
+    }
+    // two samples at the same time must not be split or repeated across pages
+    storage.add_sensor_measurement_at("office", base + Duration::from_secs(240), 30.0, f64::NAN);
+    let series = storage.series("office").unwrap();
+    let to = base + Duration::from_secs(3600);
+
+    let first = series.page(base, to, None, 2);
+    assert_eq!(first.samples.iter().map(|sample| sample.temperature).collect::<Vec<_>>(), vec![20.0, 21.0]);
+    assert!(first.more);
+
+    let cursor: Cursor = first.next.unwrap().to_string().parse().unwrap();
+    let second = series.page(base, to, Some(cursor), 3);
+    assert_eq!(second.samples.iter().map(|sample| sample.temperature).collect::<Vec<_>>(), vec![22.0, 23.0, 24.0]);
+    assert!(second.more);
+
+    let third = series.page(base, to, second.next, 3);
+    assert_eq!(third.samples.iter().map(|sample| sample.temperature).collect::<Vec<_>>(), vec![30.0]);
+    assert!(!third.more);
+
+    let empty = series.page(base, to, third.next, 3);
+    assert!(empty.samples.is_empty());
+    assert_eq!(empty.next, third.next);
+
+    assert!("garbage".parse::<Cursor>().is_err());
+}
+
+#[test]
+fn test_stale_cursor_after_purge() {
+    let mut storage = create_test_storage();
+    let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    for i in 0..5 {
+        storage.add_sensor_measurement_at("office", base + Duration::from_secs(i * 60), 20.0 + i as f64, f64::NAN);
+    }
+    let to = base + Duration::from_secs(3600);
+    let cursor = storage.series("office").unwrap().page(base, to, None, 4).next.unwrap();
+
+    storage.purge(Some("office"));
+    let page = storage.series("office").unwrap().page(base, to, Some(cursor), 4);
+    assert!(page.samples.is_empty());
+    assert!(!page.more);
+
+    let crafted: Cursor = "18000000000000000000_5".parse().unwrap();
+    assert!(storage.series("office").unwrap().page(base, to, Some(crafted), 4).samples.is_empty());
+    let crafted = Cursor { timestamp: base, count: usize::MAX };
+    assert!(storage.series("office").unwrap().page(base, to, Some(crafted), 4).samples.is_empty());
+}
+
+#[test]
+fn test_purge_clears_memory_of_series() {
+    let mut storage = create_test_storage();
+    let base = SystemTime::now() - Duration::from_secs(600);
+    storage.add_sensor_measurement_at("office", base, 21.0, f64::NAN);
+    storage.add_sensor_measurement_at("office", base + Duration::from_secs(60), 22.0, f64::NAN);
+    storage.add_sensor_measurement_at("garage", base, 8.0, f64::NAN);
+
+    assert_eq!(storage.purge(Some("office")), 2);
+    let office = storage.series("office").unwrap();
+    assert!(office.is_empty());
+    assert_eq!(office.memory_usage(), 0);
+    assert_eq!(office.get_last_sample().unwrap().temperature, 22.0);
+    assert_eq!(storage.series("garage").unwrap().len(), 1);
+
+    assert_eq!(storage.purge(None), 1);
+    assert_eq!(storage.memory_usage(), 0);
+}
+
+#[test]
+fn test_reload_applies_capacity() {
+    let mut storage = create_test_storage();
+    let base = SystemTime::now() - Duration::from_secs(600);
+    for i in 0..4 {
+        storage.add_sensor_measurement_at("office", base + Duration::from_secs(i * 60), 20.0 + i as f64, f64::NAN);
+    }
+    let mut config = default_config();
+    config.max_capacity = Some(2);
+    storage.reload(&config);
+    assert_eq!(storage.series("office").unwrap().len(), 2);
+
+    storage.add_sensor_measurement_at("office", base + Duration::from_secs(300), 24.0, f64::NAN);
+    let office = storage.series("office").unwrap();
+    assert_eq!(office.len(), 2);
+    assert_eq!(office.oldest_sample().unwrap().temperature, 23.0);
+}
+
+#[test]
+fn test_resolution_caps_points_of_long_ranges() {
+    let mut storage = create_test_storage();
+    let hours = |hours: u64| Duration::from_secs(hours * 3600);
+
+    // 3 hours at 2 minutes are 90 values
+    assert_eq!(storage.resolution(hours(3)), Duration::from_secs(120));
+    // 30 days need at least 1728 s, the next round interval is half an hour
+    assert_eq!(storage.resolution(hours(30 * 24)), Duration::from_secs(1800));
+    // beyond a day per value whole days
+    assert_eq!(storage.resolution(hours(10 * 365 * 24)), Duration::from_secs(3 * 86400));
+
+    let mut config = default_config();
+    config.max_points = 100;
+    storage.reload(&config);
+    assert_eq!(storage.resolution(hours(24)), Duration::from_secs(900));
+}
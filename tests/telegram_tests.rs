@@ -0,0 +1,50 @@
+use std::time::{Duration, SystemTime};
+use heat_monitor::config::Config;
+use heat_monitor::storage::{Sample, Storage};
+use heat_monitor::telegram::{latest_readings, parse_command, Command};
+
+fn at(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[test]
+fn test_parse_command() {
+    assert_eq!(parse_command("/temp"), Some(Command::Temp { sensor: None }));
+    assert_eq!(parse_command("/temp@HeaterBot office"), Some(Command::Temp { sensor: Some("office".to_string()) }));
+    assert_eq!(parse_command("/chart"), Some(Command::Chart { sensor: None, hours: 24 }));
+    assert_eq!(parse_command("/chart office 7d"), Some(Command::Chart { sensor: Some("office".to_string()), hours: 168 }));
+    assert_eq!(parse_command("/chart 12h"), Some(Command::Chart { sensor: None, hours: 12 }));
+    assert_eq!(parse_command("/chart 6"), Some(Command::Chart { sensor: None, hours: 6 }));
+    // capped at 30 days
+    assert_eq!(parse_command("/chart 365d"), Some(Command::Chart { sensor: None, hours: 720 }));
+    assert_eq!(parse_command("/chart 999999999999999999d"), Some(Command::Chart { sensor: None, hours: 720 }));
+    assert_eq!(parse_command("/alerts"), Some(Command::Alerts));
+    assert_eq!(parse_command("/start"), Some(Command::Help));
+    assert_eq!(parse_command("/unknown"), None);
+    assert_eq!(parse_command("hello"), None);
+    assert_eq!(parse_command(""), None);
+}
+
+#[test]
+fn test_latest_readings() {
+    let config = Config::parse(r#"
+sampling_interval = 45
+averaging_interval = 120
+port = 3000
+listen_address = "0.0.0.0"
+log_path = "/tmp/heat_monitor.log"
+pid_file = "/tmp/heater-monitor.pid"
+temp_sensor_url = "http://192.168.6.75/"
+"#).unwrap();
+    let mut storage = Storage::new(&config).unwrap();
+    assert!(latest_readings(&storage, None, &|secs| format!("@{}", secs)).is_err());
+
+    storage.add_sensor_measurement_at("office", at(1758294000), 21.34, 45.2);
+    storage.add_sample("garage", Sample::new(at(1758294300), 8.0));
+    let label = |secs| format!("@{}", secs);
+    let all = latest_readings(&storage, None, &label).unwrap();
+    assert!(all.contains("office: 21.3 °C, 45 % at @1758294000"), "{}", all);
+    assert!(all.contains("garage: 8.0 °C at @1758294300"), "{}", all);
+    assert_eq!(latest_readings(&storage, Some("garage"), &label).unwrap(), "garage: 8.0 °C at @1758294300");
+    assert!(latest_readings(&storage, Some("attic"), &label).is_err());
+}
@@ -0,0 +1,40 @@
+use heat_monitor::alerts::{AlertEvent, AlertState, Priority};
+use heat_monitor::webhook::payload;
+use serde_json::json;
+
+fn frost() -> AlertEvent {
+    AlertEvent {
+        id: 1,
+        rule: "frost".to_string(),
+        sensor: "office".to_string(),
+        metric: "temperature".to_string(),
+        state: AlertState::Active,
+        value: 14.5,
+        since: 1758294000,
+        timestamp: 1758294600,
+        priority: Priority::Normal,
+    }
+}
+
+#[test]
+fn test_payload_defaults_to_the_event() {
+    let body = payload(None, &frost());
+    assert_eq!(body["rule"], "frost");
+    assert_eq!(body["state"], "active");
+    assert_eq!(body["value"], 14.5);
+    assert_eq!(body["since"], 1758294000);
+}
+
+#[test]
+fn test_payload_template_placeholders() {
+    let template = json!({
+        "text": "{message}",
+        "fields": [{ "title": "{sensor}", "value": "{value} since {since}" }],
+        "priority": 5,
+    });
+    let body = payload(Some(&template), &frost());
+    assert_eq!(body["text"], "frost is active for office: temperature 14.5");
+    assert_eq!(body["fields"][0]["title"], "office");
+    assert_eq!(body["fields"][0]["value"], "14.5 since 1758294000");
+    assert_eq!(body["priority"], 5);
+}